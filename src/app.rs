@@ -15,12 +15,21 @@ use eframe::egui::{self, Ui};
 use egui_dock::widgets::tab_viewer::OnCloseResponse;
 use egui_dock::{DockArea, DockState, NodePath, TabViewer};
 use tokio::runtime::Handle;
+use tracing::warn;
 
+use crate::cli;
+use crate::control;
 use crate::discovery::{self, PortInfo};
+use crate::files::FileBrowser;
+use crate::hostkeys::HostKeys;
+use crate::knownhosts;
 use crate::persist;
+use crate::profiles::{Draft, Profile, ProfileView, Profiles};
 use crate::recents::{self, Recents};
 use crate::session::Session;
 use crate::settings::ConnectionSettings;
+use crate::sharing::{self, Import};
+use crate::sshconfig::{self, ConfigHost};
 use crate::term::{input, render};
 use crate::ui;
 
@@ -34,9 +43,28 @@ const MIN_TERMINAL_HEIGHT: f32 = 60.0;
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub struct TabId(pub u64);
 
+/// What a dock tab shows.
+///
+/// Only terminals are saved. A file panel is tied to a live SSH connection, so it is left out
+/// of the saved layout rather than restored onto a tab that has not connected yet. The host key
+/// manager and the profile library are opened for a job and closed after it, so they are not
+/// saved either.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tab {
+    Terminal(TabId),
+    /// The SFTP panel of the SSH tab with this id.
+    Files(TabId),
+    HostKeys,
+    Profiles,
+}
+
 pub struct UniTermApp {
-    dock: DockState<TabId>,
+    dock: DockState<Tab>,
     sessions: BTreeMap<TabId, Session>,
+    /// File panels, keyed by the SSH tab they belong to.
+    files: BTreeMap<TabId, FileBrowser>,
+    /// The host key manager, while its tab is open.
+    host_keys: Option<HostKeys>,
     ports: Vec<PortInfo>,
     next_id: u64,
     rt: Handle,
@@ -47,39 +75,141 @@ pub struct UniTermApp {
     restore_notice: Option<String>,
     /// Connections that have worked before, offered for one-click reopening.
     recents: Recents,
+    /// The hosts in `~/.ssh/config`, offered beside the recents.
+    ssh_hosts: Vec<ConfigHost>,
+    /// Saved connections the user has named.
+    profiles: Profiles,
+    /// Search and editing state of the profile panel, shared with the launcher's list.
+    profile_view: ProfileView,
+    /// The control socket, unless another window has it.
+    control: Option<control::Server>,
 }
 
 impl UniTermApp {
-    /// Build the app, restoring saved state when there is any.
-    pub fn new(rt: Handle, storage: Option<&dyn eframe::Storage>, ctx: &egui::Context) -> Self {
+    /// Build the app, restoring saved state when there is any, then open what the command line
+    /// asked for.
+    pub fn new(
+        rt: Handle,
+        storage: Option<&dyn eframe::Storage>,
+        ctx: &egui::Context,
+        open: Vec<cli::Open>,
+    ) -> Self {
         let ports = discovery::list_ports();
         let mut app = Self {
             dock: DockState::new(Vec::new()),
             sessions: BTreeMap::new(),
+            files: BTreeMap::new(),
+            host_keys: None,
             ports,
             next_id: 0,
             rt,
             unreadable_state: None,
             restore_notice: None,
             recents: Recents::default(),
+            ssh_hosts: Vec::new(),
+            profiles: Profiles::default(),
+            profile_view: ProfileView::default(),
+            control: None,
         };
+        app.reload_ssh_hosts();
+        if control::AVAILABLE {
+            match control::Server::start(control::default_path(), &app.rt, ctx) {
+                Ok(server) => app.control = Some(server),
+                Err(e) => warn!("no control socket: {e}"),
+            }
+        }
 
+        // A blank tab is only there so the window is never empty; one asked for by name does
+        // that job better.
+        let blank = open.is_empty();
         match storage.map(persist::load) {
             Some(persist::Loaded::Restored(state)) => app.restore(*state, ctx),
             Some(persist::Loaded::Unreadable { reason, payload }) => {
                 app.unreadable_state = Some(payload);
                 app.restore_notice = Some(reason);
-                app.start_fresh();
+                if blank {
+                    app.start_fresh();
+                }
             }
-            Some(persist::Loaded::Fresh) | None => app.start_fresh(),
+            Some(persist::Loaded::Fresh) | None if blank => app.start_fresh(),
+            Some(persist::Loaded::Fresh) | None => {}
         }
+        app.open_from_command_line(open, ctx);
         app
     }
 
+    /// Open and connect the tabs named on the command line.
+    ///
+    /// These were asked for explicitly, so they connect without the auto-connect policy's
+    /// checks; a port that is missing or a password not yet typed fails in the tab and says
+    /// why. A connection a restored tab already has is not opened twice: that tab is brought
+    /// forward and connected instead, so a desktop launcher can be clicked again safely.
+    fn open_from_command_line(&mut self, open: Vec<cli::Open>, ctx: &egui::Context) {
+        let (_, problems) = self.open_tabs(open, ctx);
+        if !problems.is_empty() {
+            let notice = problems.join(" ");
+            self.restore_notice = Some(match self.restore_notice.take() {
+                Some(earlier) => format!("{earlier} {notice}"),
+                None => notice,
+            });
+        }
+    }
+
+    /// Open and connect tabs as [`Self::open_from_command_line`] describes, for it and for the
+    /// control socket. Returns the tabs, and what could not be opened.
+    fn open_tabs(
+        &mut self,
+        open: Vec<cli::Open>,
+        ctx: &egui::Context,
+    ) -> (Vec<TabId>, Vec<String>) {
+        let mut tabs = Vec::new();
+        let mut problems = Vec::new();
+        for open in open {
+            let session = match open {
+                cli::Open::Connection(settings) => Session::new(*settings),
+                cli::Open::Ssh(target) => Session::new(target.settings()),
+                cli::Open::Profile(name) => match self.profiles.find(&name) {
+                    Ok(profile) => {
+                        let mut session = Session::new(profile.settings.clone());
+                        profile.apply(&mut session);
+                        session
+                    }
+                    Err(e) => {
+                        problems.push(e);
+                        continue;
+                    }
+                },
+            };
+            let identity = session.settings.identity();
+            let existing = self
+                .sessions
+                .iter()
+                .find(|(_, s)| s.settings.identity() == identity)
+                .map(|(id, _)| *id);
+            let id = existing.unwrap_or_else(|| {
+                let id = TabId(self.next_id);
+                self.next_id += 1;
+                self.sessions.insert(id, session);
+                self.dock.push_to_focused_leaf(Tab::Terminal(id));
+                id
+            });
+            if let Some(session) = self.sessions.get_mut(&id) {
+                if session.can_connect() {
+                    session.connect(&self.rt, ctx);
+                }
+            }
+            if let Some(path) = self.dock.find_tab(&Tab::Terminal(id)) {
+                let _ = self.dock.set_active_tab(path);
+            }
+            tabs.push(id);
+        }
+        (tabs, problems)
+    }
+
     /// One empty tab, so the window is never blank.
     fn start_fresh(&mut self) {
         let id = self.new_session();
-        self.dock = DockState::new(vec![id]);
+        self.dock = DockState::new(vec![Tab::Terminal(id)]);
     }
 
     /// Rebuild tabs and layout from saved state.
@@ -90,6 +220,7 @@ impl UniTermApp {
     fn restore(&mut self, state: persist::PersistedState, ctx: &egui::Context) {
         let mut skipped = Vec::new();
         self.recents = Recents::from_entries(state.recents);
+        self.profiles = Profiles::from_entries(state.profiles);
 
         for tab in state.tabs {
             let id = tab.id;
@@ -105,6 +236,7 @@ impl UniTermApp {
             session.append_lf = tab.append_lf;
             session.log_path = tab.log_path;
             session.log_enabled = tab.log_enabled;
+            session.profile = tab.profile;
 
             // Dial only what the user opted in for, and only when it is safe to.
             if tab.auto_connect {
@@ -123,15 +255,16 @@ impl UniTermApp {
         self.next_id = self.next_id.max(state.next_id);
 
         // Drop layout entries with no matching definition.
-        self.dock = state.dock;
         let known: Vec<TabId> = self.sessions.keys().copied().collect();
-        self.dock.retain_tabs(|tab| known.contains(tab));
+        self.dock = state
+            .dock
+            .filter_map_tabs(|id| known.contains(id).then_some(Tab::Terminal(*id)));
 
         // Any definition the layout lost track of would be invisible; re-attach it.
-        let placed: Vec<TabId> = self.dock.iter_all_tabs().map(|(_, id)| *id).collect();
+        let placed: Vec<Tab> = self.dock.iter_all_tabs().map(|(_, tab)| *tab).collect();
         for id in known {
-            if !placed.contains(&id) {
-                self.dock.push_to_focused_leaf(id);
+            if !placed.contains(&Tab::Terminal(id)) {
+                self.dock.push_to_focused_leaf(Tab::Terminal(id));
             }
         }
         if self.dock.iter_all_tabs().next().is_none() {
@@ -152,7 +285,10 @@ impl UniTermApp {
         persist::PersistedState {
             version: persist::SCHEMA_VERSION,
             next_id: self.next_id,
-            dock: self.dock.clone(),
+            dock: self.dock.filter_map_tabs(|tab| match tab {
+                Tab::Terminal(id) => Some(*id),
+                Tab::Files(_) | Tab::HostKeys | Tab::Profiles => None,
+            }),
             tabs: self
                 .sessions
                 .iter()
@@ -170,9 +306,11 @@ impl UniTermApp {
                     append_lf: session.append_lf,
                     log_path: session.log_path.clone(),
                     log_enabled: session.log_enabled,
+                    profile: session.profile,
                 })
                 .collect(),
             recents: self.recents.entries().to_vec(),
+            profiles: self.profiles.entries().to_vec(),
         }
     }
 
@@ -195,22 +333,102 @@ impl UniTermApp {
         self.ports = discovery::list_ports();
     }
 
+    fn reload_ssh_hosts(&mut self) {
+        self.ssh_hosts = sshconfig::default_path()
+            .map(|path| sshconfig::load(&path))
+            .unwrap_or_default();
+    }
+
     /// Open a tab for a remembered connection.
     ///
     /// Connecting reuses the same policy as startup auto-connect, so a serial device that is not
     /// attached, a port that now holds different hardware, or an SSH tab whose password was never
     /// saved opens ready-to-go with the reason shown rather than failing.
     fn open_recent(&mut self, settings: ConnectionSettings, ctx: &egui::Context) {
+        self.open_session(Session::new(settings), ctx);
+    }
+
+    /// Open a tab set up from a profile, connecting as a recent would.
+    fn open_profile(&mut self, id: u64, ctx: &egui::Context) {
+        let Some(profile) = self.profiles.get(id) else {
+            return;
+        };
+        let mut session = Session::new(profile.settings.clone());
+        profile.apply(&mut session);
+        self.open_session(session, ctx);
+    }
+
+    fn open_session(&mut self, mut session: Session, ctx: &egui::Context) {
         let id = TabId(self.next_id);
         self.next_id += 1;
 
-        let mut session = Session::new(settings.clone());
-        match persist::may_auto_connect(&settings, &self.ports) {
+        match persist::may_auto_connect(&session.settings, &self.ports) {
             persist::AutoConnect::Yes => session.connect(&self.rt, ctx),
             persist::AutoConnect::No(reason) => session.last_error = Some(reason),
         }
         self.sessions.insert(id, session);
-        self.dock.push_to_focused_leaf(id);
+        self.dock.push_to_focused_leaf(Tab::Terminal(id));
+    }
+
+    /// Carry out what the profile panel asked for.
+    fn profile_action(&mut self, action: ui::ProfileAction, ctx: &egui::Context) {
+        match action {
+            ui::ProfileAction::Open(id) => self.open_profile(id, ctx),
+            ui::ProfileAction::SaveTab(tab) => {
+                let Some(session) = self.sessions.get_mut(&tab) else {
+                    return;
+                };
+                let name = session.settings.label();
+                let profile = Profile::from_session(session, name, String::new());
+                let id = self.profiles.add(profile);
+                session.profile = Some(id);
+                // Straight into editing, since the tab's label is seldom the name wanted.
+                self.profile_view.editing = self.profiles.get(id).map(Draft::of);
+            }
+            ui::ProfileAction::Update { profile, tab } => {
+                if let (Some(profile), Some(session)) =
+                    (self.profiles.get_mut(profile), self.sessions.get(&tab))
+                {
+                    profile.take_from(session);
+                }
+            }
+            ui::ProfileAction::Export { path, recents } => {
+                let recents = recents.then_some(&self.recents);
+                let view = &mut self.profile_view;
+                (view.notice, view.error) = match sharing::export(&path, &self.profiles, recents) {
+                    Ok(()) => (Some(format!("Exported to {}.", path.display())), None),
+                    Err(e) => (None, Some(e)),
+                };
+            }
+            ui::ProfileAction::Import(path) => {
+                let view = &mut self.profile_view;
+                view.notice = None;
+                match sharing::read(&path) {
+                    Ok(bundle) => {
+                        let import = Import::new(path, bundle, &self.profiles, &self.recents);
+                        view.import = Some(import);
+                        view.error = None;
+                    }
+                    Err(e) => view.error = Some(e),
+                }
+            }
+            ui::ProfileAction::ApplyImport => {
+                if let Some(import) = self.profile_view.import.take() {
+                    let summary = import.apply(&mut self.profiles, &mut self.recents);
+                    self.profile_view.notice = Some(summary);
+                }
+            }
+        }
+    }
+
+    /// Bring the profile panel forward, or open it.
+    fn show_profiles(&mut self) {
+        match self.dock.find_tab(&Tab::Profiles) {
+            Some(path) => {
+                let _ = self.dock.set_active_tab(path);
+            }
+            None => self.dock.push_to_focused_leaf(Tab::Profiles),
+        }
     }
 
     /// Menu listing remembered connections.
@@ -219,6 +437,7 @@ impl UniTermApp {
         let mut to_pin = None;
         let mut to_remove = None;
         let mut clear = false;
+        let mut reload = false;
         let now = recents::now_seconds();
 
         let label = if self.recents.is_empty() {
@@ -227,12 +446,31 @@ impl UniTermApp {
             format!("Recent ({})", self.recents.len())
         };
         ui.menu_button(label, |ui| {
+            ui.set_min_width(320.0);
+            if !self.ssh_hosts.is_empty() {
+                ui.menu_button("From ~/.ssh/config", |ui| {
+                    for host in &self.ssh_hosts {
+                        if ui
+                            .button(&host.alias)
+                            .on_hover_text(host.settings.description())
+                            .clicked()
+                        {
+                            to_open = Some(host.settings.clone());
+                            ui.close();
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("Reload").on_hover_text("Read the file again").clicked() {
+                        reload = true;
+                    }
+                });
+                ui.separator();
+            }
             if self.recents.is_empty() {
                 ui.label("Nothing yet.");
                 ui.weak("Connections appear here once they have worked.");
                 return;
             }
-            ui.set_min_width(320.0);
             for entry in self.recents.entries() {
                 let identity = entry.identity();
                 ui.horizontal(|ui| {
@@ -282,6 +520,9 @@ impl UniTermApp {
         if clear {
             self.recents.clear_unpinned();
         }
+        if reload {
+            self.reload_ssh_hosts();
+        }
         if let Some(settings) = to_open {
             let ctx = ui.ctx().clone();
             self.open_recent(settings, &ctx);
@@ -295,6 +536,8 @@ impl UniTermApp {
     /// something.
     fn launcher(&mut self, ui: &mut Ui) {
         let mut to_open = None;
+        let mut to_open_profile = None;
+        let mut manage_profiles = false;
         let mut new_tab = false;
         let now = recents::now_seconds();
 
@@ -311,13 +554,57 @@ impl UniTermApp {
                 }
                 ui.add_space(24.0);
 
+                if !self.profiles.is_empty() {
+                    ui.label("Profiles");
+                    ui.add_space(4.0);
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.profile_view.filter)
+                            .desired_width(260.0)
+                            .hint_text("search"),
+                    );
+                    ui.add_space(4.0);
+                    // Bounded like the recents below; a big library scrolls instead.
+                    egui::ScrollArea::vertical()
+                        .id_salt("launcher profiles")
+                        .max_height(240.0)
+                        .show(ui, |ui| {
+                            for folder in self.profiles.folders() {
+                                let filter = &self.profile_view.filter;
+                                let mut shown = self
+                                    .profiles
+                                    .entries()
+                                    .iter()
+                                    .filter(|p| p.folder == folder && p.matches(filter))
+                                    .peekable();
+                                if shown.peek().is_none() {
+                                    continue;
+                                }
+                                if !folder.is_empty() {
+                                    ui.weak(folder);
+                                }
+                                for profile in shown {
+                                    if ui
+                                        .add_sized([260.0, 24.0], egui::Button::new(&profile.name))
+                                        .on_hover_text(profile.settings.description())
+                                        .clicked()
+                                    {
+                                        to_open_profile = Some(profile.id);
+                                    }
+                                }
+                            }
+                        });
+                    if ui.button("Manage profiles\u{2026}").clicked() {
+                        manage_profiles = true;
+                    }
+                    ui.add_space(18.0);
+                }
+
                 if self.recents.is_empty() {
                     ui.weak("Connections you use will be listed here for one-click reopening.");
-                    return;
+                } else {
+                    ui.label("Recent connections");
+                    ui.add_space(6.0);
                 }
-
-                ui.label("Recent connections");
-                ui.add_space(6.0);
                 // Bounded so a long history cannot push the button off-screen.
                 for entry in self.recents.entries().iter().take(10) {
                     ui.horizontal(|ui| {
@@ -339,12 +626,40 @@ impl UniTermApp {
                         ui.weak(recents::relative_time(now, entry.last_used));
                     });
                 }
+
+                if self.ssh_hosts.is_empty() {
+                    return;
+                }
+                ui.add_space(18.0);
+                ui.label("From ~/.ssh/config");
+                ui.add_space(6.0);
+                for host in self.ssh_hosts.iter().take(10) {
+                    ui.horizontal(|ui| {
+                        ui.add_space((ui.available_width() / 2.0 - 190.0).max(0.0));
+                        if ui
+                            .add_sized([260.0, 24.0], egui::Button::new(&host.alias))
+                            .on_hover_text(host.settings.description())
+                            .clicked()
+                        {
+                            to_open = Some(host.settings.clone());
+                        }
+                    });
+                }
+                if self.ssh_hosts.len() > 10 {
+                    ui.weak(format!("and {} more in the Recent menu", self.ssh_hosts.len() - 10));
+                }
             });
         });
 
         if new_tab {
             let id = self.new_session();
-            self.dock.push_to_focused_leaf(id);
+            self.dock.push_to_focused_leaf(Tab::Terminal(id));
+        }
+        if manage_profiles {
+            self.show_profiles();
+        }
+        if let Some(id) = to_open_profile {
+            self.open_profile(id, ui.ctx());
         }
         if let Some(settings) = to_open {
             let ctx = ui.ctx().clone();
@@ -370,11 +685,27 @@ impl UniTermApp {
                     .clicked()
                 {
                     let id = self.new_session();
-                    self.dock.push_to_focused_leaf(id);
+                    self.dock.push_to_focused_leaf(Tab::Terminal(id));
                 }
 
                 self.recents_menu(ui);
 
+                if ui
+                    .button("Profiles")
+                    .on_hover_text("Your library of named connections")
+                    .clicked()
+                {
+                    self.show_profiles();
+                }
+
+                if ui
+                    .button("Host keys")
+                    .on_hover_text("Review and remove the SSH host keys in ~/.ssh/known_hosts")
+                    .clicked()
+                {
+                    self.show_host_keys(None);
+                }
+
                 ui.separator();
                 ui.label(format!("{} port(s)", self.ports.len()));
 
@@ -439,6 +770,18 @@ impl eframe::App for UniTermApp {
         for settings in established {
             self.recents.record(&settings, now);
         }
+        for (id, files) in &mut self.files {
+            let connection = self.sessions.get(id).and_then(Session::ssh_connection);
+            files.poll(&self.rt, connection, ui.ctx());
+        }
+        let opening = match &mut self.control {
+            Some(control) => control.serve(&mut self.sessions),
+            None => Vec::new(),
+        };
+        for opening in opening {
+            let (tabs, problems) = self.open_tabs(opening.open.clone(), ui.ctx());
+            opening.answer(&tabs, problems);
+        }
 
         self.toolbar(ui);
 
@@ -451,10 +794,16 @@ impl eframe::App for UniTermApp {
 
         let mut closed = Vec::new();
         let mut added = Vec::new();
+        let mut profile_actions = Vec::new();
 
         let style = egui_dock::Style::from_egui(ui.style().as_ref());
         let mut viewer = Viewer {
             sessions: &mut self.sessions,
+            files: &mut self.files,
+            host_keys: &mut self.host_keys,
+            profiles: &mut self.profiles,
+            profile_view: &mut self.profile_view,
+            profile_actions: &mut profile_actions,
             ports: &self.ports,
             rt: &self.rt,
             closed: &mut closed,
@@ -467,38 +816,139 @@ impl eframe::App for UniTermApp {
             .show_leaf_close_all_buttons(false)
             .show_inside(ui, &mut viewer);
 
-        for id in closed {
-            // Dropping the session drops its command sender, which stops the task.
-            self.sessions.remove(&id);
+        for tab in closed {
+            match tab {
+                // Dropping the session drops its command sender, which stops the task. Its
+                // file panel has nothing left to browse, so it goes too.
+                Tab::Terminal(id) => {
+                    self.sessions.remove(&id);
+                    if self.files.remove(&id).is_some() {
+                        self.dock.retain_tabs(|tab| *tab != Tab::Files(id));
+                    }
+                }
+                Tab::Files(id) => {
+                    self.files.remove(&id);
+                }
+                Tab::HostKeys => self.host_keys = None,
+                Tab::Profiles => self.profile_view = ProfileView {
+                    filter: std::mem::take(&mut self.profile_view.filter),
+                    ..ProfileView::default()
+                },
+            }
+        }
+        for action in profile_actions {
+            self.profile_action(action, ui.ctx());
         }
         for path in added {
             let id = self.new_session();
             self.dock.set_focused_node_and_surface(path);
-            self.dock.push_to_focused_leaf(id);
+            self.dock.push_to_focused_leaf(Tab::Terminal(id));
+        }
+        let requested: Vec<TabId> = self
+            .sessions
+            .iter_mut()
+            .filter_map(|(id, session)| std::mem::take(&mut session.show_files).then_some(*id))
+            .collect();
+        for id in requested {
+            self.show_files(id);
+        }
+        let requested = self
+            .sessions
+            .iter_mut()
+            .find_map(|(id, session)| std::mem::take(&mut session.show_host_keys).then_some(*id));
+        if let Some(id) = requested {
+            self.show_host_keys(Some(id));
+        }
+        // A replaced key: reconnect the tab, which will ask about the new one.
+        let reconnect = self.host_keys.as_mut().and_then(|keys| keys.reconnect.take());
+        if let Some(id) = reconnect {
+            if let Some(session) = self.sessions.get_mut(&id) {
+                session.reconnect(&self.rt, ui.ctx());
+            }
+            if let Some(path) = self.dock.find_tab(&Tab::Terminal(id)) {
+                let _ = self.dock.set_active_tab(path);
+            }
+        }
+    }
+}
+
+impl UniTermApp {
+    /// Bring a tab's file panel forward, opening it beside the terminal if there is none.
+    fn show_files(&mut self, id: TabId) {
+        if let Some(path) = self.dock.find_tab(&Tab::Files(id)) {
+            let _ = self.dock.set_active_tab(path);
+            return;
+        }
+        self.files.entry(id).or_default();
+        match self.dock.find_tab(&Tab::Terminal(id)) {
+            Some(path) => {
+                self.dock[path.surface].split_right(path.node, 0.6, vec![Tab::Files(id)]);
+            }
+            None => self.dock.push_to_focused_leaf(Tab::Files(id)),
+        }
+    }
+
+    /// Bring the host key manager forward. Asked for by a tab whose host key was refused, it
+    /// shows that tab's store and host; otherwise the user's whole file.
+    fn show_host_keys(&mut self, origin: Option<TabId>) {
+        let refused = origin.and_then(|id| {
+            let session = self.sessions.get(&id)?;
+            let rejection = session.refused_host_key.clone()?;
+            Some((id, knownhosts::path_for(&session.settings.ssh), rejection))
+        });
+        let keys = self
+            .host_keys
+            .get_or_insert_with(|| HostKeys::open(knownhosts::default_path()));
+        if let Some((id, path, rejection)) = refused {
+            keys.focus(path, &rejection, id);
+        }
+        match self.dock.find_tab(&Tab::HostKeys) {
+            Some(path) => {
+                let _ = self.dock.set_active_tab(path);
+            }
+            None => self.dock.push_to_focused_leaf(Tab::HostKeys),
         }
     }
 }
 
 struct Viewer<'a> {
     sessions: &'a mut BTreeMap<TabId, Session>,
+    files: &'a mut BTreeMap<TabId, FileBrowser>,
+    host_keys: &'a mut Option<HostKeys>,
+    profiles: &'a mut Profiles,
+    profile_view: &'a mut ProfileView,
+    profile_actions: &'a mut Vec<ui::ProfileAction>,
     ports: &'a [PortInfo],
     rt: &'a Handle,
-    closed: &'a mut Vec<TabId>,
+    closed: &'a mut Vec<Tab>,
     added: &'a mut Vec<NodePath>,
 }
 
 impl TabViewer for Viewer<'_> {
-    type Tab = TabId;
+    type Tab = Tab;
 
     fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
-        match self.sessions.get(tab) {
-            Some(session) => session.title().into(),
-            None => "(closed)".into(),
+        match *tab {
+            Tab::Terminal(id) => match self.sessions.get(&id) {
+                Some(session) => session.title().into(),
+                None => "(closed)".into(),
+            },
+            Tab::Files(id) => match self.sessions.get(&id) {
+                Some(session) => format!("Files \u{00b7} {}", session.title()).into(),
+                None => "Files".into(),
+            },
+            Tab::HostKeys => "Host keys".into(),
+            Tab::Profiles => "Profiles".into(),
         }
     }
 
     fn id(&mut self, tab: &mut Self::Tab) -> egui::Id {
-        egui::Id::new(("uniterm-tab", tab.0))
+        match *tab {
+            Tab::Terminal(id) => egui::Id::new(("uniterm-tab", id.0)),
+            Tab::Files(id) => egui::Id::new(("uniterm-files", id.0)),
+            Tab::HostKeys => egui::Id::new("uniterm-host-keys"),
+            Tab::Profiles => egui::Id::new("uniterm-profiles"),
+        }
     }
 
     fn on_close(&mut self, tab: &mut Self::Tab) -> OnCloseResponse {
@@ -510,9 +960,10 @@ impl TabViewer for Viewer<'_> {
         self.added.push(path);
     }
 
-    /// The terminal draws its own background and manages its own scrolling.
-    fn clear_background(&self, _tab: &Self::Tab) -> bool {
-        false
+    /// The terminal draws its own background and manages its own scrolling. The panels scroll
+    /// their own listings but want the ordinary background.
+    fn clear_background(&self, tab: &Self::Tab) -> bool {
+        matches!(tab, Tab::Files(_) | Tab::HostKeys | Tab::Profiles)
     }
 
     fn scroll_bars(&self, _tab: &Self::Tab) -> [bool; 2] {
@@ -520,7 +971,36 @@ impl TabViewer for Viewer<'_> {
     }
 
     fn ui(&mut self, ui: &mut Ui, tab: &mut Self::Tab) {
-        let Some(session) = self.sessions.get_mut(tab) else {
+        match *tab {
+            Tab::Terminal(id) => self.terminal(ui, id),
+            Tab::Files(id) => match self.files.get_mut(&id) {
+                Some(files) => ui::file_panel(ui, files, self.rt),
+                None => {
+                    ui.label("This panel has been closed.");
+                }
+            },
+            Tab::HostKeys => match self.host_keys {
+                Some(keys) => ui::host_key_panel(ui, keys),
+                None => {
+                    ui.label("This panel has been closed.");
+                }
+            },
+            Tab::Profiles => {
+                let tabs: Vec<_> = self
+                    .sessions
+                    .iter()
+                    .map(|(id, session)| (*id, session.settings.label(), session.profile))
+                    .collect();
+                let action = ui::profile_panel(ui, self.profiles, self.profile_view, &tabs);
+                self.profile_actions.extend(action);
+            }
+        }
+    }
+}
+
+impl Viewer<'_> {
+    fn terminal(&mut self, ui: &mut Ui, tab: TabId) {
+        let Some(session) = self.sessions.get_mut(&tab) else {
             ui.label("This tab's session has been closed.");
             return;
         };
@@ -647,10 +1127,10 @@ mod tests {
         let _ = ctx.run_ui(egui::RawInput::default(), |ui| {
             for (glyph, used_for) in [
             ('\u{00b7}', "separator in the status line"),
-            ('\u{00d7}', "forget-this-recent button"),
+            ('\u{00d7}', "forget-recent, remove-jump-host, remove-forward and cancel buttons"),
             ('\u{2022}', "connected indicator in a tab title"),
             ('\u{2026}', "ellipsis on buttons that open a dialog"),
-            ('\u{21bb}', "refresh ports"),
+            ('\u{21bb}', "refresh ports and the file listing"),
             ('\u{25cb}', "connecting indicator in a tab title"),
             ('\u{2605}', "pinned recent"),
             ('\u{2606}', "unpinned recent"),
@@ -676,7 +1156,7 @@ mod tests {
     /// show whether the terminal actually ends up holding the keyboard.
     fn dock_frame(
         ctx: &egui::Context,
-        dock: &mut DockState<TabId>,
+        dock: &mut DockState<Tab>,
         sessions: &mut BTreeMap<TabId, Session>,
         rt: &Handle,
         events: Vec<egui::Event>,
@@ -694,6 +1174,11 @@ mod tests {
             let mut added = Vec::new();
             let mut viewer = Viewer {
                 sessions,
+                files: &mut BTreeMap::new(),
+                host_keys: &mut None,
+                profiles: &mut Profiles::default(),
+                profile_view: &mut ProfileView::default(),
+                profile_actions: &mut Vec::new(),
                 ports: &[],
                 rt,
                 closed: &mut closed,
@@ -705,7 +1190,7 @@ mod tests {
         });
     }
 
-    fn ssh_tab() -> (DockState<TabId>, BTreeMap<TabId, Session>, TabId) {
+    fn ssh_tab() -> (DockState<Tab>, BTreeMap<TabId, Session>, TabId) {
         let tab = TabId(1);
         let mut session = Session::new(ConnectionSettings {
             kind: ConnectionKind::Ssh,
@@ -716,7 +1201,7 @@ mod tests {
         session.settings.ssh.host = "example.test".into();
         let mut sessions = BTreeMap::new();
         sessions.insert(tab, session);
-        (DockState::new(vec![tab]), sessions, tab)
+        (DockState::new(vec![Tab::Terminal(tab)]), sessions, tab)
     }
 
     fn click(pos: egui::Pos2, pressed: bool) -> egui::Event {
@@ -0,0 +1,392 @@
+//! Command-line arguments.
+//!
+//! For test scripts and desktop launchers that want a window on one device without clicking
+//! through the UI. Each `--serial`, `--ssh` or `--profile` opens a tab, in the order given,
+//! alongside whatever the last session left open, and connects it; the options after one
+//! describe that connection, so `--serial A --baud 9600 --serial B` is two ports at
+//! different speeds.
+//!
+//! `--headless` runs a single connection with no window at all; see [`crate::headless`].
+//!
+//! Parsed by hand: the grammar is a dozen options, and a parser crate would be most of the
+//! code this needs. Passwords are refused outright — anything on a command line can be read
+//! by every user on the machine — so a password login asks in the tab, or uses the keychain.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::settings::{
+    ConnectionKind, ConnectionSettings, DataBits, FlowControl, Parity, SerialSettings, SshAuth,
+    StopBits,
+};
+use crate::sshconfig;
+
+pub const USAGE: &str = "\
+Usage: UniTerm [CONNECTION [OPTIONS]]...
+
+Opens a tab for each CONNECTION, next to the tabs from last time, and connects it.
+
+Connections:
+  --serial PORT              A serial port, such as /dev/ttyUSB0 or COM3
+  --ssh [USER@]HOST[:PORT]   An SSH login. HOST may be an alias from ~/.ssh/config
+  --profile NAME             A saved profile, by name or as FOLDER/NAME
+
+Serial options:
+  --baud RATE                Speed in baud (default 115200)
+  --8n1, --7e1, ...          Data bits (5-8), parity (n, o, e) and stop bits (1, 2)
+  --flow none|xonxoff|rtscts Flow control (default none)
+
+SSH options:
+  -i, --identity FILE        Log in with this private key
+
+Without a window:
+  --headless                 Run the one CONNECTION with no window, writing what it
+                             receives to stdout and sending what arrives on stdin
+  --output FILE              Write what is received to FILE instead of stdout
+  --script FILE              Send from FILE instead of stdin; see the README
+  --reconnect                Retry whenever the connection drops
+  --for SECONDS              Stop after this long
+
+  -h, --help                 Show this and exit
+  -V, --version              Show the version and exit
+
+Passwords are not taken here: type them in the tab, or keep them in the keychain.
+";
+
+/// What to do, as the command line says.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Invocation {
+    Run(Vec<Open>),
+    Headless(Headless),
+    Help,
+    Version,
+}
+
+/// One tab to open.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Open {
+    /// Everything about it was on the command line.
+    Connection(Box<ConnectionSettings>),
+    /// An SSH login, which `~/.ssh/config` has a say in; see [`SshTarget::settings`].
+    Ssh(SshTarget),
+    /// A profile by name, looked up once the library has been loaded.
+    Profile(String),
+}
+
+/// `--headless` and its options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Headless {
+    pub open: Open,
+    /// Where received bytes go. Standard output if `None`.
+    pub output: Option<PathBuf>,
+    /// What to send. Standard input if `None`.
+    pub script: Option<PathBuf>,
+    pub reconnect: bool,
+    pub duration: Option<Duration>,
+}
+
+/// `--ssh` and the options after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SshTarget {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub identity: Option<PathBuf>,
+}
+
+impl SshTarget {
+    /// What `ssh` would connect with: the config's settings for the host, alias or not, with
+    /// anything given on the command line on top.
+    pub fn settings(&self) -> ConnectionSettings {
+        self.settings_from(sshconfig::default_path().as_deref())
+    }
+
+    /// [`Self::settings`] with `config` read in place of the user's own, or none at all.
+    pub fn settings_from(&self, config: Option<&Path>) -> ConnectionSettings {
+        let mut settings = sshconfig::settings_for(config, &self.host);
+        if let Some(user) = &self.user {
+            settings.ssh.user = user.clone();
+        }
+        if let Some(port) = self.port {
+            settings.ssh.port = port;
+        }
+        if let Some(identity) = &self.identity {
+            settings.ssh.auth = SshAuth::PublicKey;
+            settings.ssh.key_path = Some(identity.clone());
+        }
+        settings
+    }
+}
+
+/// Read the arguments after the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let mut open: Vec<Open> = Vec::new();
+    let mut headless = false;
+    let (mut output, mut script, mut reconnect, mut duration) = (None, None, false, None);
+    while let Some(arg) = args.next() {
+        // `--baud=9600` as well as `--baud 9600`.
+        let (option, inline) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value.to_owned())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{option} needs a value."))
+        };
+        match option {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "--serial" => {
+                open.push(Open::Connection(Box::new(ConnectionSettings {
+                    kind: ConnectionKind::Serial,
+                    serial: SerialSettings {
+                        name: value()?,
+                        ..SerialSettings::default()
+                    },
+                    ..ConnectionSettings::default()
+                })));
+            }
+            "--ssh" => {
+                let target = value()?;
+                let (user, host, port) = sshconfig::split_target(&target);
+                if host.is_empty() {
+                    return Err(format!("--ssh {target} names no host."));
+                }
+                open.push(Open::Ssh(SshTarget {
+                    user: user.map(str::to_owned),
+                    host: host.to_owned(),
+                    port,
+                    identity: None,
+                }));
+            }
+            "--profile" => open.push(Open::Profile(value()?)),
+            "--baud" => {
+                let text = value()?;
+                let baud = text
+                    .parse()
+                    .ok()
+                    .filter(|baud| *baud > 0)
+                    .ok_or_else(|| format!("--baud {text} is not a speed."))?;
+                serial(&mut open, option)?.baud_rate = baud;
+            }
+            "--flow" => {
+                let text = value()?;
+                serial(&mut open, option)?.flow_control = match text.to_lowercase().as_str() {
+                    "none" => FlowControl::None,
+                    "xonxoff" | "software" => FlowControl::Software,
+                    "rtscts" | "hardware" => FlowControl::Hardware,
+                    _ => return Err(format!("--flow {text}: use none, xonxoff or rtscts.")),
+                };
+            }
+            "-i" | "--identity" => {
+                let path = PathBuf::from(value()?);
+                match open.last_mut() {
+                    Some(Open::Ssh(target)) => target.identity = Some(path),
+                    _ => return Err(format!("{option} belongs after an --ssh.")),
+                }
+            }
+            "--headless" => headless = true,
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--script" => script = Some(PathBuf::from(value()?)),
+            "--reconnect" => reconnect = true,
+            "--for" => {
+                let text = value()?;
+                let seconds = text
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                    .ok_or_else(|| format!("--for {text} is not a number of seconds."))?;
+                duration = Some(Duration::from_secs_f64(seconds));
+            }
+            "--password" | "--passphrase" => {
+                return Err(format!(
+                    "{option} is not accepted: any user on this machine can read a command \
+                     line. Type it in the tab, or keep it in the keychain."
+                ));
+            }
+            _ => match framing(option) {
+                Some((data_bits, parity, stop_bits)) => {
+                    let serial = serial(&mut open, option)?;
+                    serial.data_bits = data_bits;
+                    serial.parity = parity;
+                    serial.stop_bits = stop_bits;
+                }
+                None => return Err(format!("Unknown option {arg}. Try --help.")),
+            },
+        }
+    }
+    if !headless {
+        let given = [
+            ("--output", output.is_some()),
+            ("--script", script.is_some()),
+            ("--reconnect", reconnect),
+            ("--for", duration.is_some()),
+        ];
+        if let Some((option, _)) = given.iter().find(|(_, given)| *given) {
+            return Err(format!("{option} only applies with --headless."));
+        }
+        return Ok(Invocation::Run(open));
+    }
+    let count = open.len();
+    let mut open = open.into_iter();
+    match (open.next(), count) {
+        (Some(open), 1) => Ok(Invocation::Headless(Headless {
+            open,
+            output,
+            script,
+            reconnect,
+            duration,
+        })),
+        _ => Err(format!("--headless runs exactly one connection, not {count}.")),
+    }
+}
+
+/// The serial port the last `--serial` opened, which a serial option applies to.
+fn serial<'a>(open: &'a mut [Open], option: &str) -> Result<&'a mut SerialSettings, String> {
+    match open.last_mut() {
+        Some(Open::Connection(settings)) if settings.kind == ConnectionKind::Serial => {
+            Ok(&mut settings.serial)
+        }
+        _ => Err(format!("{option} belongs after a --serial.")),
+    }
+}
+
+/// `--8n1` and the like: data bits, parity and stop bits in one, as serial settings are
+/// usually written down.
+fn framing(option: &str) -> Option<(DataBits, Parity, StopBits)> {
+    let [data, parity, stop] = option.strip_prefix("--")?.as_bytes() else {
+        return None;
+    };
+    let data = match data {
+        b'5' => DataBits::Five,
+        b'6' => DataBits::Six,
+        b'7' => DataBits::Seven,
+        b'8' => DataBits::Eight,
+        _ => return None,
+    };
+    let parity = match parity.to_ascii_lowercase() {
+        b'n' => Parity::None,
+        b'o' => Parity::Odd,
+        b'e' => Parity::Even,
+        _ => return None,
+    };
+    let stop = match stop {
+        b'1' => StopBits::One,
+        b'2' => StopBits::Two,
+        _ => return None,
+    };
+    Some((data, parity, stop))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<Vec<Open>, String> {
+        match parse(args.iter().map(|a| a.to_string()))? {
+            Invocation::Run(open) => Ok(open),
+            other => panic!("expected tabs, got {other:?}"),
+        }
+    }
+
+    fn serial_of(open: &Open) -> &SerialSettings {
+        match open {
+            Open::Connection(settings) => &settings.serial,
+            other => panic!("expected a serial port, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn serial_options_apply_to_the_port_before_them() {
+        let open = run(&[
+            "--serial", "/dev/ttyUSB0", "--baud", "921600", "--8n1", "--serial=COM3", "--7E2",
+            "--flow", "rtscts",
+        ])
+        .unwrap();
+        assert_eq!(open.len(), 2);
+        let first = serial_of(&open[0]);
+        assert_eq!((first.name.as_str(), first.baud_rate), ("/dev/ttyUSB0", 921_600));
+        assert_eq!((first.data_bits, first.parity), (DataBits::Eight, Parity::None));
+        let second = serial_of(&open[1]);
+        assert_eq!((second.name.as_str(), second.baud_rate), ("COM3", 115_200));
+        assert_eq!((second.data_bits, second.parity), (DataBits::Seven, Parity::Even));
+        assert_eq!(second.stop_bits, StopBits::Two);
+        assert_eq!(second.flow_control, FlowControl::Hardware);
+    }
+
+    #[test]
+    fn ssh_targets_and_profiles_are_read_in_order() {
+        let open = run(&["--ssh", "lab@bench:2222", "-i", "key", "--profile", "Bench 3/Scope"])
+            .unwrap();
+        assert_eq!(
+            open,
+            [
+                Open::Ssh(SshTarget {
+                    user: Some("lab".into()),
+                    host: "bench".into(),
+                    port: Some(2222),
+                    identity: Some(PathBuf::from("key")),
+                }),
+                Open::Profile("Bench 3/Scope".into()),
+            ]
+        );
+        let Open::Ssh(target) = &open[0] else {
+            unreachable!()
+        };
+        let settings = target.settings_from(None);
+        assert_eq!(settings.kind, ConnectionKind::Ssh);
+        assert_eq!(settings.ssh.host, "bench");
+        assert_eq!((settings.ssh.user.as_str(), settings.ssh.port), ("lab", 2222));
+        assert_eq!(settings.ssh.auth, SshAuth::PublicKey);
+
+        // The command line wins over the config, which fills in the rest.
+        let config = std::env::temp_dir().join("uniterm_cli_ssh_config");
+        std::fs::write(&config, "Host bench\n    HostName bench.lab\n    User tech\n").unwrap();
+        let settings = target.settings_from(Some(&config));
+        let _ = std::fs::remove_file(&config);
+        assert_eq!(settings.ssh.host, "bench.lab");
+        assert_eq!((settings.ssh.user.as_str(), settings.ssh.port), ("lab", 2222));
+    }
+
+    #[test]
+    fn mistakes_are_reported_rather_than_guessed_at() {
+        let error = |args: &[&str]| run(args).unwrap_err();
+        assert!(error(&["--baud", "9600"]).contains("after a --serial"));
+        assert!(error(&["--ssh", "box", "--8n1"]).contains("after a --serial"));
+        assert!(error(&["--serial", "COM1", "-i", "key"]).contains("after an --ssh"));
+        assert!(error(&["--serial", "COM1", "--baud", "fast"]).contains("not a speed"));
+        assert!(error(&["--serial"]).contains("needs a value"));
+        assert!(error(&["--9n1"]).contains("Unknown option"));
+        assert!(error(&["--ssh", "me@"]).contains("names no host"));
+        assert!(error(&["--ssh", "box", "--password", "hunter2"]).contains("not accepted"));
+        assert_eq!(parse(["-h".to_owned()]), Ok(Invocation::Help));
+        assert_eq!(run(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn headless_takes_one_connection_and_its_own_options() {
+        let parse = |args: &[&str]| parse(args.iter().map(|a| a.to_string()));
+        let invocation = parse(&[
+            "--headless", "--serial", "/dev/ttyACM0", "--baud", "9600", "--output", "rx.bin",
+            "--reconnect", "--for=1.5",
+        ])
+        .unwrap();
+        let Invocation::Headless(headless) = invocation else {
+            panic!("expected a headless run, got {invocation:?}");
+        };
+        assert_eq!(serial_of(&headless.open).baud_rate, 9600);
+        assert_eq!(headless.output, Some(PathBuf::from("rx.bin")));
+        assert_eq!(headless.script, None);
+        assert!(headless.reconnect);
+        assert_eq!(headless.duration, Some(Duration::from_millis(1500)));
+
+        let error = |args: &[&str]| parse(args).unwrap_err();
+        assert!(error(&["--headless"]).contains("not 0"));
+        assert!(error(&["--headless", "--ssh", "a", "--ssh", "b"]).contains("not 2"));
+        assert!(error(&["--serial", "COM1", "--script", "s"]).contains("only applies"));
+        assert!(error(&["--headless", "--serial", "COM1", "--for", "0"]).contains("seconds"));
+    }
+}
@@ -0,0 +1,795 @@
+//! Local control socket, for automation.
+//!
+//! A test harness drives the bench an engineer is watching through the window's own tabs,
+//! rather than fighting the window for the serial port. It connects to a Unix socket and speaks
+//! JSON-RPC 2.0, one message per line:
+//!
+//! | Method        | Params                         | Result                                   |
+//! |---------------|--------------------------------|------------------------------------------|
+//! | `list`        |                                | the tabs, as [`describe`] has them       |
+//! | `open`        | `args`, as on the command line | `tabs` opened or found, and `problems`   |
+//! | `send`        | `tab`, and `text` or `hex`     | `sent`: how many bytes went              |
+//! | `read`        | `tab`, `from`, `hex`           | a chunk                                  |
+//! | `subscribe`   | `tab`, `from`, `hex`           | a chunk, then `data` notifications       |
+//! | `unsubscribe` | `tab`                          | `true`                                   |
+//!
+//! Scrollback is addressed the way [`TermBuffer::slice_from`] addresses it: by absolute offset
+//! into everything the tab has held, reconnection dividers included. A chunk is `tab`, `from`,
+//! `to` and the bytes in between — as `text`, or with `hex: true` as hex pairs, the way the
+//! send box takes them. Text is decoded leniently, so ask for hex where every byte matters. A
+//! `from` later than the one asked for means the ring had trimmed what came between. A
+//! subscription without a `from` starts at the end, lasts while its connection stays open, and
+//! ends with a `closed` notification if the tab closes first.
+//!
+//! Calls are answered on the UI thread, where the tabs live: the socket's task passes each one
+//! over and wakes the window, as a session's task does with what it receives.
+//!
+//! Only the user running the window may use the socket: whatever reaches it can type into
+//! every tab and read every scrollback. It is `control.sock` in a directory of that user's own
+//! — `uniterm` in `$XDG_RUNTIME_DIR`, or `uniterm-<uid>` in the temporary directory without
+//! one — which must be theirs and closed to everyone else before anything is bound in it, and
+//! a client run by anyone else is turned away all the same. A second window finds the socket
+//! taken and goes without.
+//!
+//! A request line may be at most [`MAX_LINE`] bytes; a longer one is answered with an error
+//! and the connection closed. A subscriber that lets [`QUEUE`] messages pile up unread is
+//! dropped rather than queued for without end, and can read from where it got to and
+//! subscribe again.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::MutexGuard;
+
+use eframe::egui;
+use serde_json::{json, Value};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::app::TabId;
+use crate::cli;
+use crate::session::{ConnectionState, Session};
+use crate::settings::{self, SendMode};
+use crate::term::TermBuffer;
+
+/// Whether this build can offer the socket. Windows has Unix sockets, but tokio does not.
+pub const AVAILABLE: bool = cfg!(unix);
+
+/// JSON-RPC's own error codes, and one for a call that was understood but could not be done.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const FAILED: i64 = -32000;
+
+/// The longest request line accepted, newline included.
+const MAX_LINE: usize = 1 << 20;
+
+/// How many messages may wait for a client before it counts as not reading.
+const QUEUE: usize = 256;
+
+/// Where the socket goes.
+pub fn default_path() -> PathBuf {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("uniterm"),
+        #[cfg(unix)]
+        _ => std::env::temp_dir().join(format!("uniterm-{}", user_id())),
+        #[cfg(not(unix))]
+        _ => std::env::temp_dir().join("uniterm"),
+    };
+    dir.join("control.sock")
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() }
+}
+
+/// Make sure `dir` exists, belongs to this user and is closed to everyone else. One that
+/// someone else made first, or a link in its place, is refused rather than used.
+#[cfg(unix)]
+fn private_dir(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(format!("Could not create {}: {e}", dir.display())),
+    }
+    let metadata = std::fs::symlink_metadata(dir)
+        .map_err(|e| format!("Could not check {}: {e}", dir.display()))?;
+    let private = metadata.is_dir()
+        && metadata.uid() == user_id()
+        && metadata.permissions().mode() & 0o077 == 0;
+    if !private {
+        return Err(format!("{} is not a directory only you can use.", dir.display()));
+    }
+    Ok(())
+}
+
+/// A JSON-RPC error.
+#[derive(Clone, Debug, PartialEq)]
+struct Fault {
+    code: i64,
+    message: String,
+}
+
+impl Fault {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+/// A call, checked.
+#[derive(Clone, Debug, PartialEq)]
+enum Call {
+    List,
+    Open(Vec<cli::Open>),
+    Send { tab: TabId, bytes: Vec<u8> },
+    Read { tab: TabId, from: u64, hex: bool },
+    Subscribe { tab: TabId, from: Option<u64>, hex: bool },
+    Unsubscribe { tab: TabId },
+    /// The client went away. Not something it can ask for.
+    Hangup,
+}
+
+impl Call {
+    fn parse(method: &str, params: &Value) -> Result<Self, Fault> {
+        let tab = || {
+            params
+                .get("tab")
+                .and_then(Value::as_u64)
+                .map(TabId)
+                .ok_or_else(|| Fault::params(format!("`{method}` needs `tab`, an id from `list`.")))
+        };
+        let from = || match params.get("from") {
+            None => Ok(None),
+            Some(from) => from
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| Fault::params("`from` is an offset, a whole number.")),
+        };
+        let hex = || match params.get("hex") {
+            None => Ok(false),
+            Some(hex) => hex.as_bool().ok_or_else(|| Fault::params("`hex` is true or false.")),
+        };
+        match method {
+            "list" => Ok(Self::List),
+            "open" => {
+                let args = params
+                    .get("args")
+                    .and_then(Value::as_array)
+                    .and_then(|args| {
+                        args.iter()
+                            .map(|arg| arg.as_str().map(str::to_owned))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| {
+                        Fault::params("`open` needs `args`, command-line arguments as strings.")
+                    })?;
+                match cli::parse(args).map_err(Fault::params)? {
+                    cli::Invocation::Run(open) if !open.is_empty() => Ok(Self::Open(open)),
+                    _ => Err(Fault::params("`args` has to name a connection to open.")),
+                }
+            }
+            "send" => {
+                let bytes = match (params.get("text"), params.get("hex")) {
+                    (Some(Value::String(text)), None) => text.clone().into_bytes(),
+                    (None, Some(Value::String(hex))) => {
+                        settings::encode_send(hex, SendMode::Hex, false, false)
+                            .map_err(Fault::params)?
+                    }
+                    _ => return Err(Fault::params("`send` needs `text` or `hex`, a string.")),
+                };
+                Ok(Self::Send { tab: tab()?, bytes })
+            }
+            "read" => Ok(Self::Read {
+                tab: tab()?,
+                from: from()?.unwrap_or(0),
+                hex: hex()?,
+            }),
+            "subscribe" => Ok(Self::Subscribe {
+                tab: tab()?,
+                from: from()?,
+                hex: hex()?,
+            }),
+            "unsubscribe" => Ok(Self::Unsubscribe { tab: tab()? }),
+            _ => Err(Fault::new(METHOD_NOT_FOUND, format!("There is no method `{method}`."))),
+        }
+    }
+}
+
+/// Read one line from a client: the id to answer with, if it wants an answer, and the call.
+///
+/// An error comes with the id to report it under. Unreadable requests are answered with a null
+/// id, as JSON-RPC asks; a notification's mistakes go unanswered, as it also asks.
+fn request(line: &str) -> Result<(Option<Value>, Call), (Option<Value>, Fault)> {
+    let message: Value = serde_json::from_str(line)
+        .map_err(|e| (Some(Value::Null), Fault::new(PARSE_ERROR, format!("Not JSON: {e}"))))?;
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        let fault = Fault::new(INVALID_REQUEST, "Expected a request object with a `method`.");
+        return Err((Some(id.unwrap_or(Value::Null)), fault));
+    };
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    match Call::parse(method, &params) {
+        Ok(call) => Ok((id, call)),
+        Err(fault) => Err((id, fault)),
+    }
+}
+
+fn response(id: Value, result: Result<Value, Fault>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(Fault { code, message }) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// A call on its way to the UI thread.
+struct Request {
+    /// Which client asked, so its subscriptions can be told apart.
+    client: u64,
+    call: Call,
+    reply: oneshot::Sender<Result<Value, Fault>>,
+    /// The client's outgoing messages, for a subscription to send to.
+    notify: mpsc::Sender<Value>,
+}
+
+/// An `open`, which is the app's business rather than its tabs'.
+pub struct Opening {
+    pub open: Vec<cli::Open>,
+    reply: oneshot::Sender<Result<Value, Fault>>,
+}
+
+impl Opening {
+    pub fn answer(self, tabs: &[TabId], problems: Vec<String>) {
+        let tabs: Vec<u64> = tabs.iter().map(|tab| tab.0).collect();
+        let _ = self.reply.send(Ok(json!({ "tabs": tabs, "problems": problems })));
+    }
+}
+
+struct Subscription {
+    client: u64,
+    tab: TabId,
+    /// Where the next notification starts.
+    next: u64,
+    hex: bool,
+    notify: mpsc::Sender<Value>,
+}
+
+/// The socket, as the UI thread sees it.
+pub struct Server {
+    path: PathBuf,
+    requests: mpsc::UnboundedReceiver<Request>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Server {
+    /// Listen at `path`. Calls arrive through [`Self::serve`], which the frame `ctx` belongs to
+    /// is woken to run.
+    #[cfg(unix)]
+    pub fn start(path: PathBuf, rt: &Handle, ctx: &egui::Context) -> Result<Self, String> {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        // The directory is what keeps others out: the socket is reachable the moment it is
+        // bound, before the chmod below narrows it.
+        if let Some(dir) = path.parent() {
+            private_dir(dir)?;
+        }
+        // A window that crashed leaves its socket behind; one that answers is still running.
+        if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+            return Err(format!("{} is in use by another window.", path.display()));
+        }
+        if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+            let _ = std::fs::remove_file(&path);
+        }
+        let listener = {
+            let _runtime = rt.enter();
+            tokio::net::UnixListener::bind(&path)
+        }
+        .map_err(|e| format!("Could not listen at {}: {e}", path.display()))?;
+        if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)) {
+            let _ = std::fs::remove_file(&path);
+            return Err(format!("Could not restrict {}: {e}", path.display()));
+        }
+        let (tx, requests) = mpsc::unbounded_channel();
+        rt.spawn(listen::accept(listener, tx, ctx.clone()));
+        Ok(Self {
+            path,
+            requests,
+            subscriptions: Vec::new(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start(_path: PathBuf, _rt: &Handle, _ctx: &egui::Context) -> Result<Self, String> {
+        Err("The control socket needs Unix sockets.".to_owned())
+    }
+
+    /// Answer the calls that have come in, then send subscribers what their tabs received
+    /// since last time. Called once per frame.
+    ///
+    /// Everything is answered from `sessions` except `open`, which is handed back.
+    pub fn serve(&mut self, sessions: &mut BTreeMap<TabId, Session>) -> Vec<Opening> {
+        let mut opening = Vec::new();
+        while let Ok(request) = self.requests.try_recv() {
+            let Request {
+                client,
+                call,
+                reply,
+                notify,
+            } = request;
+            let result = match call {
+                Call::Open(open) => {
+                    opening.push(Opening { open, reply });
+                    continue;
+                }
+                Call::List => Ok(sessions.iter().map(|(tab, s)| describe(*tab, s)).collect()),
+                Call::Send { tab, bytes } => match sessions.get_mut(&tab) {
+                    Some(session) if session.is_connected() => {
+                        let sent = bytes.len();
+                        session.send(bytes);
+                        Ok(json!({ "sent": sent }))
+                    }
+                    Some(_) => Err(Fault::new(FAILED, format!("Tab {} is not connected.", tab.0))),
+                    None => Err(no_tab(tab)),
+                },
+                Call::Read { tab, from, hex } => {
+                    scrollback(sessions, tab).map(|buffer| chunk(tab, &buffer, from, hex).0)
+                }
+                Call::Subscribe { tab, from, hex } => scrollback(sessions, tab).map(|buffer| {
+                    let (chunk, next) = chunk(tab, &buffer, from.unwrap_or(buffer.end()), hex);
+                    self.subscriptions.retain(|s| (s.client, s.tab) != (client, tab));
+                    self.subscriptions.push(Subscription {
+                        client,
+                        tab,
+                        next,
+                        hex,
+                        notify,
+                    });
+                    chunk
+                }),
+                Call::Unsubscribe { tab } => {
+                    self.subscriptions.retain(|s| (s.client, s.tab) != (client, tab));
+                    Ok(Value::Bool(true))
+                }
+                Call::Hangup => {
+                    self.subscriptions.retain(|s| s.client != client);
+                    Ok(Value::Null)
+                }
+            };
+            let _ = reply.send(result);
+        }
+        self.publish(sessions);
+        opening
+    }
+
+    fn publish(&mut self, sessions: &BTreeMap<TabId, Session>) {
+        self.subscriptions.retain_mut(|subscription| {
+            let tab = subscription.tab;
+            let Some(session) = sessions.get(&tab) else {
+                let closed = notification("closed", json!({ "tab": tab.0 }));
+                let _ = subscription.notify.try_send(closed);
+                return false;
+            };
+            let Ok(buffer) = session.buffer.lock() else {
+                return true;
+            };
+            if buffer.end() == subscription.next {
+                return !subscription.notify.is_closed();
+            }
+            let (params, next) = chunk(tab, &buffer, subscription.next, subscription.hex);
+            subscription.next = next;
+            // A full queue means the client stopped reading; it is let go, not waited for.
+            subscription.notify.try_send(notification("data", params)).is_ok()
+        });
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A tab, for `list`.
+fn describe(tab: TabId, session: &Session) -> Value {
+    let state = match session.state {
+        ConnectionState::Disconnected => "disconnected",
+        ConnectionState::Connecting => "connecting",
+        ConnectionState::Reconnecting => "reconnecting",
+        ConnectionState::Connected => "connected",
+    };
+    json!({
+        "tab": tab.0,
+        "title": session.settings.label(),
+        "kind": session.settings.kind.label(),
+        "state": state,
+        "error": session.last_error,
+        "end": session.buffer.lock().map(|buffer| buffer.end()).unwrap_or(0),
+    })
+}
+
+fn no_tab(tab: TabId) -> Fault {
+    Fault::new(FAILED, format!("There is no tab {}.", tab.0))
+}
+
+fn scrollback(
+    sessions: &BTreeMap<TabId, Session>,
+    tab: TabId,
+) -> Result<MutexGuard<'_, TermBuffer>, Fault> {
+    let session = sessions.get(&tab).ok_or_else(|| no_tab(tab))?;
+    session
+        .buffer
+        .lock()
+        .map_err(|_| Fault::new(FAILED, format!("The scrollback of tab {} is lost.", tab.0)))
+}
+
+/// What `buffer` holds from `from` on, and the offset after it.
+fn chunk(tab: TabId, buffer: &TermBuffer, from: u64, hex: bool) -> (Value, u64) {
+    let (data, start) = buffer.slice_from(from);
+    let to = start + data.len() as u64;
+    let mut chunk = json!({ "tab": tab.0, "from": start, "to": to });
+    if hex {
+        let pairs: Vec<String> = data.iter().map(|byte| format!("{byte:02x}")).collect();
+        chunk["hex"] = pairs.join(" ").into();
+    } else {
+        chunk["text"] = String::from_utf8_lossy(data).into();
+    }
+    (chunk, to)
+}
+
+/// The socket's side: accepting clients and carrying their calls to the UI thread.
+#[cfg(unix)]
+mod listen {
+    use eframe::egui;
+    use serde_json::Value;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::sync::{mpsc, oneshot};
+    use tracing::warn;
+
+    use super::{
+        request, response, Call, Fault, Request, FAILED, INVALID_REQUEST, MAX_LINE, QUEUE,
+    };
+
+    pub async fn accept(
+        listener: UnixListener,
+        requests: mpsc::UnboundedSender<Request>,
+        ctx: egui::Context,
+    ) {
+        let mut clients = 0;
+        while !requests.is_closed() {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    // The directory should already have kept anyone else out.
+                    match stream.peer_cred() {
+                        Ok(peer) if peer.uid() == super::user_id() => {}
+                        Ok(peer) => {
+                            warn!("control socket: refused a client run by user {}", peer.uid());
+                            continue;
+                        }
+                        Err(e) => {
+                            warn!("control socket: could not tell who connected: {e}");
+                            continue;
+                        }
+                    }
+                    clients += 1;
+                    tokio::spawn(client(stream, clients, requests.clone(), ctx.clone()));
+                }
+                Err(e) => {
+                    // Out of file descriptors, most likely; give it a moment rather than spin.
+                    warn!("control socket: {e}");
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    async fn client(
+        stream: UnixStream,
+        id: u64,
+        requests: mpsc::UnboundedSender<Request>,
+        ctx: egui::Context,
+    ) {
+        let (read, mut write) = stream.into_split();
+        // Answers and notifications share the socket, so one task writes them all.
+        let (notify, mut outgoing) = mpsc::channel::<Value>(QUEUE);
+        let writer = tokio::spawn(async move {
+            while let Some(message) = outgoing.recv().await {
+                if write.write_all(format!("{message}\n").as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let mut read = BufReader::new(read);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let mut limited = (&mut read).take(MAX_LINE as u64 + 1);
+            if !matches!(limited.read_until(b'\n', &mut line).await, Ok(1..)) {
+                break;
+            }
+            if line.len() > MAX_LINE {
+                let message = format!("A request may be at most {MAX_LINE} bytes.");
+                let fault = Fault::new(INVALID_REQUEST, message);
+                let _ = notify.send(response(Value::Null, Err(fault))).await;
+                break;
+            }
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (reply_id, result) = match request(&line) {
+                Ok((reply_id, call)) => (reply_id, ask(&requests, &ctx, id, call, &notify).await),
+                Err((reply_id, fault)) => (reply_id, Err(fault)),
+            };
+            if let Some(reply_id) = reply_id {
+                let _ = notify.send(response(reply_id, result)).await;
+            }
+        }
+        // Subscriptions hold senders too, and the writer runs until the last one is gone.
+        let _ = ask(&requests, &ctx, id, Call::Hangup, &notify).await;
+        drop(notify);
+        let _ = writer.await;
+    }
+
+    async fn ask(
+        requests: &mpsc::UnboundedSender<Request>,
+        ctx: &egui::Context,
+        client: u64,
+        call: Call,
+        notify: &mpsc::Sender<Value>,
+    ) -> Result<Value, Fault> {
+        let closing = || Fault::new(FAILED, "UniTerm is closing.");
+        let (reply, answer) = oneshot::channel();
+        let request = Request {
+            client,
+            call,
+            reply,
+            notify: notify.clone(),
+        };
+        if requests.send(request).is_err() {
+            return Err(closing());
+        }
+        ctx.request_repaint();
+        answer.await.unwrap_or_else(|_| Err(closing()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{ConnectionKind, ConnectionSettings, TcpSettings};
+
+    #[test]
+    fn calls_are_checked_before_they_reach_the_tabs() {
+        let parse = |method: &str, params: Value| Call::parse(method, &params);
+        assert_eq!(parse("list", Value::Null), Ok(Call::List));
+        assert_eq!(
+            parse("send", json!({ "tab": 2, "hex": "0d 0A" })),
+            Ok(Call::Send {
+                tab: TabId(2),
+                bytes: b"\r\n".to_vec()
+            })
+        );
+        assert_eq!(
+            parse("subscribe", json!({ "tab": 2, "hex": true })),
+            Ok(Call::Subscribe {
+                tab: TabId(2),
+                from: None,
+                hex: true
+            })
+        );
+        let Ok(Call::Open(open)) = parse("open", json!({ "args": ["--serial", "COM3"] })) else {
+            panic!("expected an open");
+        };
+        assert_eq!(open.len(), 1);
+
+        let code = |method: &str, params: Value| parse(method, params).unwrap_err().code;
+        assert_eq!(code("read", json!({})), INVALID_PARAMS);
+        assert_eq!(code("read", json!({ "tab": 1, "from": -1 })), INVALID_PARAMS);
+        assert_eq!(code("send", json!({ "tab": 1, "text": "a", "hex": "62" })), INVALID_PARAMS);
+        assert_eq!(code("open", json!({ "args": ["--help"] })), INVALID_PARAMS);
+        assert_eq!(code("open", json!({ "args": ["--baud", "9600"] })), INVALID_PARAMS);
+        assert_eq!(code("reboot", Value::Null), METHOD_NOT_FOUND);
+
+        assert_eq!(request("{").unwrap_err().0, Some(Value::Null));
+        assert_eq!(request("[]").unwrap_err().1.code, INVALID_REQUEST);
+        // A notification's mistakes are not answered.
+        assert_eq!(request(r#"{"method": "nope"}"#).unwrap_err().0, None);
+    }
+
+    #[cfg(unix)]
+    mod socket {
+        use super::*;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+        use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+        use tokio::net::UnixStream;
+
+        /// A client, and the frames that answer it.
+        struct Bench {
+            server: Server,
+            sessions: BTreeMap<TabId, Session>,
+            write: OwnedWriteHalf,
+            lines: Lines<BufReader<OwnedReadHalf>>,
+        }
+
+        impl Bench {
+            async fn call(&mut self, method: &str, params: Value) -> Value {
+                let request =
+                    json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+                self.write.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+                self.next().await
+            }
+
+            /// Run frames until the client hears something.
+            async fn next(&mut self) -> Value {
+                for _ in 0..200 {
+                    assert!(self.server.serve(&mut self.sessions).is_empty());
+                    let wait = std::time::Duration::from_millis(10);
+                    if let Ok(line) = tokio::time::timeout(wait, self.lines.next_line()).await {
+                        return serde_json::from_str(&line.unwrap().unwrap()).unwrap();
+                    }
+                }
+                panic!("the client heard nothing");
+            }
+        }
+
+        /// A socket in a directory of this test run's own, which `Server::start` makes.
+        fn socket_path(name: &str) -> PathBuf {
+            let dir = format!("uniterm_control_{}", std::process::id());
+            std::env::temp_dir().join(dir).join(format!("{name}.sock"))
+        }
+
+        async fn bench(name: &str) -> Bench {
+            let path = socket_path(name);
+            let server =
+                Server::start(path.clone(), &Handle::current(), &egui::Context::default()).unwrap();
+            let session = Session::new(ConnectionSettings {
+                kind: ConnectionKind::Tcp,
+                tcp: TcpSettings {
+                    host: "bench".into(),
+                    port: 4001,
+                },
+                ..Default::default()
+            });
+            session.buffer.lock().unwrap().append(b"hello\r\n");
+            let (read, write) = UnixStream::connect(&path).await.unwrap().into_split();
+            Bench {
+                server,
+                sessions: BTreeMap::from([(TabId(7), session)]),
+                write,
+                lines: BufReader::new(read).lines(),
+            }
+        }
+
+        #[tokio::test]
+        async fn a_client_lists_reads_and_follows_a_tab() {
+            let mut bench = bench("follow").await;
+
+            let tabs = bench.call("list", Value::Null).await["result"].clone();
+            assert_eq!(tabs[0]["tab"], 7);
+            assert_eq!(tabs[0]["state"], "disconnected");
+            assert_eq!(tabs[0]["end"], 7);
+
+            let read = bench.call("read", json!({ "tab": 7 })).await;
+            let expected = json!({ "tab": 7, "from": 0, "to": 7, "text": "hello\r\n" });
+            assert_eq!(read["result"], expected);
+            let read = bench.call("read", json!({ "tab": 7, "from": 5, "hex": true })).await;
+            assert_eq!(read["result"]["hex"], "0d 0a");
+
+            let sent = bench.call("send", json!({ "tab": 7, "text": "x" })).await;
+            assert!(sent["error"]["message"].as_str().unwrap().contains("not connected"));
+            let missing = bench.call("read", json!({ "tab": 8 })).await;
+            assert_eq!(missing["error"]["code"], FAILED);
+
+            // Subscribing starts at the end, so only what arrives afterwards is sent.
+            let subscribed = bench.call("subscribe", json!({ "tab": 7 })).await;
+            assert_eq!(subscribed["result"]["from"], 7);
+            assert_eq!(subscribed["result"]["text"], "");
+            bench.sessions[&TabId(7)].buffer.lock().unwrap().append(b"more");
+            let data = bench.next().await;
+            assert_eq!(data["method"], "data");
+            assert_eq!(data["params"], json!({ "tab": 7, "from": 7, "to": 11, "text": "more" }));
+
+            bench.sessions.clear();
+            let closed = bench.next().await;
+            assert_eq!(closed, notification("closed", json!({ "tab": 7 })));
+        }
+
+        #[tokio::test]
+        async fn mistakes_are_answered_as_errors() {
+            let mut bench = bench("errors").await;
+            bench.write.write_all(b"not json\n").await.unwrap();
+            let answer = bench.next().await;
+            assert_eq!(answer["id"], Value::Null);
+            assert_eq!(answer["error"]["code"], PARSE_ERROR);
+            let answer = bench.call("reboot", Value::Null).await;
+            assert_eq!(answer["error"]["code"], METHOD_NOT_FOUND);
+        }
+
+        #[tokio::test]
+        async fn an_overlong_request_is_refused_and_the_client_let_go() {
+            let mut bench = bench("overlong").await;
+            let line = vec![b' '; MAX_LINE + 1];
+            // The server may hang up before taking it all, so how the write ends is not the
+            // point; the answer is.
+            let _ = bench.write.write_all(&line).await;
+            let answer = bench.next().await;
+            assert_eq!(answer["error"]["code"], INVALID_REQUEST);
+            // Hanging up is itself a call, so frames have to keep coming until it is answered.
+            for _ in 0..200 {
+                bench.server.serve(&mut bench.sessions);
+                let wait = std::time::Duration::from_millis(10);
+                if let Ok(line) = tokio::time::timeout(wait, bench.lines.next_line()).await {
+                    assert_eq!(line.unwrap(), None, "nothing more should be said");
+                    return;
+                }
+            }
+            panic!("the connection was left open");
+        }
+
+        #[tokio::test]
+        async fn a_subscriber_that_stops_reading_is_dropped() {
+            let mut bench = bench("stalled").await;
+            bench.call("subscribe", json!({ "tab": 7 })).await;
+            assert_eq!(bench.server.subscriptions.len(), 1);
+            // The client never reads again, so the socket and then the queue fill up.
+            for _ in 0..5_000 {
+                bench.sessions[&TabId(7)].buffer.lock().unwrap().append(&[b'x'; 4096]);
+                bench.server.serve(&mut bench.sessions);
+                if bench.server.subscriptions.is_empty() {
+                    return;
+                }
+                tokio::task::yield_now().await;
+            }
+            panic!("the subscription outlived a client that stopped reading");
+        }
+
+        #[tokio::test]
+        async fn a_directory_others_can_reach_is_refused() {
+            use std::os::unix::fs::PermissionsExt;
+            let path = socket_path("shared_dir").with_extension("d").join("control.sock");
+            let dir = path.parent().unwrap();
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let rt = Handle::current();
+            let refused = Server::start(path.clone(), &rt, &egui::Context::default());
+            assert!(refused.err().unwrap().contains("only you"));
+            assert!(!path.exists());
+            let _ = std::fs::remove_dir(dir);
+        }
+
+        #[tokio::test]
+        async fn one_window_has_the_socket_and_cleans_it_up() {
+            let bench = bench("owner").await;
+            let path = socket_path("owner");
+            let rt = Handle::current();
+            let second = Server::start(path.clone(), &rt, &egui::Context::default());
+            assert!(second.err().unwrap().contains("in use"));
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let mode = std::fs::metadata(path.parent().unwrap()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+            drop(bench);
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! SFTP file panel state.
+//!
+//! A panel belongs to an SSH tab and runs on that tab's connection, so pushing a firmware
+//! image or pulling a log needs no second login. It is docked beside the terminal rather than
+//! inside it, and can be dragged anywhere like any other tab.
+//!
+//! Browsing uses one SFTP channel; each transfer opens its own. A long upload therefore never
+//! stalls the listing, and several transfers run side by side without their requests queueing
+//! behind one another.
+//!
+//! The panel outlives its connection. When the tab drops and reconnects, the panel notices the
+//! new connection and lists the same directory again.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::session::sftp::{self, Entry};
+use crate::session::ssh::Connection;
+
+/// How often a transfer reports progress. Every block would be thousands of repaints a second
+/// on a fast link.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Which way a transfer goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// One copy, running or finished.
+pub struct Transfer {
+    id: u64,
+    pub direction: Direction,
+    /// The remote path.
+    pub remote: String,
+    pub local: PathBuf,
+    pub done: u64,
+    pub total: Option<u64>,
+    /// `None` while running.
+    pub outcome: Option<Result<(), String>>,
+    task: JoinHandle<()>,
+}
+
+impl Transfer {
+    /// How far along it is, when the size is known.
+    pub fn fraction(&self) -> Option<f32> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.done as f64 / total as f64) as f32),
+            None => None,
+        }
+    }
+}
+
+/// Task to UI messages.
+enum Update {
+    Listed {
+        path: String,
+        result: Result<Vec<Entry>, String>,
+    },
+    Progress {
+        id: u64,
+        done: u64,
+        total: Option<u64>,
+    },
+    Finished {
+        id: u64,
+        result: Result<u64, String>,
+    },
+}
+
+/// The panel for one SSH tab.
+pub struct FileBrowser {
+    /// The directory shown, once the server has said where the login directory is.
+    pub path: Option<String>,
+    /// The path box, which can be edited and then entered.
+    pub path_text: String,
+    pub entries: Vec<Entry>,
+    /// A listing has been asked for and not answered yet.
+    pub loading: bool,
+    /// Why the last listing failed, shown in place of the entries.
+    pub error: Option<String>,
+    pub transfers: Vec<Transfer>,
+    next_transfer: u64,
+
+    /// The connection the channels run on, to notice when a reconnect replaces it.
+    connection: Option<Connection>,
+    /// Directories to list; `None` asks for the login directory. Dropping it ends the task.
+    requests: Option<mpsc::UnboundedSender<Option<String>>>,
+    updates_tx: mpsc::UnboundedSender<Update>,
+    updates: mpsc::UnboundedReceiver<Update>,
+}
+
+impl Default for FileBrowser {
+    fn default() -> Self {
+        let (updates_tx, updates) = mpsc::unbounded_channel();
+        Self {
+            path: None,
+            path_text: String::new(),
+            entries: Vec::new(),
+            loading: false,
+            error: None,
+            transfers: Vec::new(),
+            next_transfer: 0,
+            connection: None,
+            requests: None,
+            updates_tx,
+            updates,
+        }
+    }
+}
+
+impl Drop for FileBrowser {
+    /// Closing the panel cancels what it was copying, rather than leaving transfers nobody can
+    /// see finishing in the background.
+    fn drop(&mut self) {
+        for transfer in &self.transfers {
+            transfer.task.abort();
+        }
+    }
+}
+
+impl FileBrowser {
+    /// Whether the panel has a connection to work on.
+    pub fn is_connected(&self) -> bool {
+        self.requests.is_some()
+    }
+
+    /// Follow the tab's connection and apply what the tasks have reported. Called once per
+    /// frame.
+    pub fn poll(&mut self, rt: &Handle, connection: Option<&Connection>, ctx: &egui::Context) {
+        let current = self.connection.as_ref();
+        let changed = match (current, connection) {
+            (Some(old), Some(new)) => !old.same_as(new),
+            (None, None) => false,
+            _ => true,
+        };
+        if changed {
+            self.connection = connection.cloned();
+            self.requests = connection.map(|c| self.start(rt, c.clone(), ctx.clone()));
+            if self.requests.is_some() {
+                self.request(self.path.clone());
+            }
+        }
+
+        while let Ok(update) = self.updates.try_recv() {
+            match update {
+                Update::Listed { path, result } => {
+                    self.loading = false;
+                    match result {
+                        Ok(entries) => {
+                            self.path_text = path.clone();
+                            self.path = Some(path);
+                            self.entries = entries;
+                            self.error = None;
+                        }
+                        Err(e) => self.error = Some(e),
+                    }
+                }
+                Update::Progress { id, done, total } => {
+                    if let Some(transfer) = self.transfers.iter_mut().find(|t| t.id == id) {
+                        transfer.done = done;
+                        transfer.total = total;
+                    }
+                }
+                Update::Finished { id, result } => {
+                    let Some(transfer) = self.transfers.iter_mut().find(|t| t.id == id) else {
+                        continue;
+                    };
+                    match result {
+                        Ok(done) => {
+                            transfer.done = done;
+                            transfer.total.get_or_insert(done);
+                            transfer.outcome = Some(Ok(()));
+                        }
+                        Err(e) => transfer.outcome = Some(Err(e)),
+                    }
+                    // A finished upload into the directory on show belongs in its listing.
+                    let uploaded_here = transfer.direction == Direction::Upload
+                        && transfer.outcome == Some(Ok(()))
+                        && sftp::parent(&transfer.remote) == self.path;
+                    if uploaded_here {
+                        self.refresh();
+                    }
+                }
+            }
+        }
+    }
+
+    /// List `path`.
+    pub fn open(&mut self, path: String) {
+        self.request(Some(path));
+    }
+
+    /// Go up a directory.
+    pub fn up(&mut self) {
+        if let Some(parent) = self.path.as_deref().and_then(sftp::parent) {
+            self.open(parent);
+        }
+    }
+
+    /// List the current directory again.
+    pub fn refresh(&mut self) {
+        self.request(self.path.clone());
+    }
+
+    /// Copy the file `name` in the current directory to `local`.
+    pub fn download(&mut self, rt: &Handle, name: &str, local: PathBuf, ctx: &egui::Context) {
+        if let Some(dir) = &self.path {
+            let remote = sftp::join(dir, name);
+            self.transfer(rt, Direction::Download, remote, local, ctx);
+        }
+    }
+
+    /// Copy `local` into the current directory under its own name.
+    pub fn upload(&mut self, rt: &Handle, local: PathBuf, ctx: &egui::Context) {
+        let (Some(dir), Some(name)) = (&self.path, local.file_name()) else {
+            return;
+        };
+        let remote = sftp::join(dir, &name.to_string_lossy());
+        self.transfer(rt, Direction::Upload, remote, local, ctx);
+    }
+
+    /// Stop a running transfer. What was copied so far stays where it landed.
+    pub fn cancel(&mut self, index: usize) {
+        if let Some(transfer) = self.transfers.get_mut(index) {
+            if transfer.outcome.is_none() {
+                transfer.task.abort();
+                transfer.outcome = Some(Err("Cancelled.".to_owned()));
+            }
+        }
+    }
+
+    /// Forget the transfers that have finished.
+    pub fn clear_finished(&mut self) {
+        self.transfers.retain(|t| t.outcome.is_none());
+    }
+
+    /// Ask the browsing task for a listing. If the task has given up, the next poll starts
+    /// another, which is how a panel whose channel failed to open gets retried.
+    fn request(&mut self, path: Option<String>) {
+        match &self.requests {
+            Some(requests) if requests.send(path).is_ok() => self.loading = true,
+            _ => {
+                self.requests = None;
+                self.connection = None;
+            }
+        }
+    }
+
+    /// The browsing task: one SFTP channel, answering listing requests in turn.
+    fn start(
+        &self,
+        rt: &Handle,
+        connection: Connection,
+        ctx: egui::Context,
+    ) -> mpsc::UnboundedSender<Option<String>> {
+        let (requests, mut incoming) = mpsc::unbounded_channel::<Option<String>>();
+        let updates = self.updates_tx.clone();
+        rt.spawn(async move {
+            let mut client = match connection.open_sftp().await {
+                Ok(client) => client,
+                Err(e) => {
+                    let path = String::new();
+                    let _ = updates.send(Update::Listed { path, result: Err(e) });
+                    ctx.request_repaint();
+                    return;
+                }
+            };
+            while let Some(path) = incoming.recv().await {
+                let (path, result) = match path {
+                    Some(path) => {
+                        let result = client.list(&path).await;
+                        (path, result)
+                    }
+                    None => match client.realpath(".").await {
+                        Ok(home) => {
+                            let result = client.list(&home).await;
+                            (home, result)
+                        }
+                        Err(e) => (String::new(), Err(e)),
+                    },
+                };
+                let _ = updates.send(Update::Listed { path, result });
+                ctx.request_repaint();
+            }
+        });
+        requests
+    }
+
+    fn transfer(
+        &mut self,
+        rt: &Handle,
+        direction: Direction,
+        remote: String,
+        local: PathBuf,
+        ctx: &egui::Context,
+    ) {
+        let Some(connection) = self.connection.clone() else {
+            return;
+        };
+        let id = self.next_transfer;
+        self.next_transfer += 1;
+        let updates = self.updates_tx.clone();
+        let ctx = ctx.clone();
+        let (from, to) = (remote.clone(), local.clone());
+        let task = rt.spawn(async move {
+            let mut last_report = Instant::now();
+            let progress = |done, total| {
+                if last_report.elapsed() >= PROGRESS_INTERVAL {
+                    last_report = Instant::now();
+                    let _ = updates.send(Update::Progress { id, done, total });
+                    ctx.request_repaint();
+                }
+            };
+            let result = match connection.open_sftp().await {
+                Ok(mut sftp) => match direction {
+                    Direction::Download => sftp.download(&from, &to, progress).await,
+                    Direction::Upload => sftp.upload(&to, &from, progress).await,
+                },
+                Err(e) => Err(e),
+            };
+            let _ = updates.send(Update::Finished { id, result });
+            ctx.request_repaint();
+        });
+        self.transfers.push(Transfer {
+            id,
+            direction,
+            remote,
+            local,
+            done: 0,
+            total: None,
+            outcome: None,
+            task,
+        });
+    }
+}
@@ -0,0 +1,447 @@
+//! One connection, run without a window.
+//!
+//! For CI rigs that need what a tab does — the same transports, USB re-identification and
+//! automatic reconnection — without a display. It is the same [`Session`], driven the way the
+//! window drives it: [`Session::poll`] and then a read of the scrollback since last time, on a
+//! timer rather than once per frame. What arrives is copied out byte for byte, to a file or
+//! standard output; what to send comes from standard input, or from a [`Script`].
+//!
+//! Nothing can be asked. An unknown host key, a password that is not in the keychain or a
+//! one-time code ends the run with the reason on standard error, as it would end an attempt
+//! in a tab. The exit status is 0 when the run ended as asked — the script finished, the time
+//! was up, the remote end closed cleanly — and 1 when it failed.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use tokio::runtime::Handle;
+
+use crate::cli::{Headless, Open};
+use crate::persist::{self, Loaded};
+use crate::profiles::Profiles;
+use crate::session::{ConnectionState, Session};
+
+/// How often the session is polled. Short enough that a script's `expect` is not noticeably
+/// slower than the device, long enough to cost nothing while idle.
+const TICK: Duration = Duration::from_millis(20);
+
+/// How long an `expect` waits unless the script says otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Received bytes kept for `expect` to search, so a chatty device cannot grow it forever.
+const SEEN_LIMIT: usize = 1 << 20;
+
+/// Run `headless` to the end, and return the process exit status.
+///
+/// `saved` is eframe's state file, where a `--profile` is looked up.
+pub fn run(rt: &Handle, headless: Headless, saved: Option<&Path>) -> i32 {
+    let input = match &headless.script {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(text) => match Script::parse(&text) {
+                Ok(script) => Input::Script(script),
+                Err(e) => return fail(&format!("{}: {e}", path.display()), 2),
+            },
+            Err(e) => return fail(&format!("Could not read {}: {e}", path.display()), 2),
+        },
+        None => Input::Stdin(stdin()),
+    };
+    let mut session = match session_for(headless.open, saved) {
+        Ok(session) => session,
+        Err(e) => return fail(&e, 2),
+    };
+    session.auto_reconnect = headless.reconnect;
+    let mut output: Box<dyn Write> = match &headless.output {
+        Some(path) => match std::fs::File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => return fail(&format!("Could not write {}: {e}", path.display()), 2),
+        },
+        None => Box::new(std::io::stdout()),
+    };
+    rt.block_on(pump(rt, session, &mut output, input, headless.duration))
+}
+
+fn fail(message: &str, code: i32) -> i32 {
+    eprintln!("UniTerm: {message}");
+    code
+}
+
+/// A tab for `open`, as [`crate::app::UniTermApp`] would open it.
+fn session_for(open: Open, saved: Option<&Path>) -> Result<Session, String> {
+    match open {
+        Open::Connection(settings) => Ok(Session::new(*settings)),
+        Open::Ssh(target) => Ok(Session::new(target.settings())),
+        Open::Profile(name) => {
+            let profiles = match saved.map(persist::load_file) {
+                Some(Loaded::Restored(state)) => Profiles::from_entries(state.profiles),
+                Some(Loaded::Unreadable { reason, .. }) => return Err(reason),
+                Some(Loaded::Fresh) | None => Profiles::default(),
+            };
+            let profile = profiles.find(&name)?;
+            let mut session = Session::new(profile.settings.clone());
+            profile.apply(&mut session);
+            Ok(session)
+        }
+    }
+}
+
+/// Where sends come from.
+pub enum Input {
+    /// Standard input, passed on as it arrives. Its end sends nothing more but does not end the
+    /// run, so a CI job can capture with `< /dev/null`.
+    Stdin(mpsc::Receiver<Vec<u8>>),
+    Script(Script),
+}
+
+/// Standard input, read on a thread of its own: std's is blocking, and tokio's reads on a
+/// blocking thread that would hold up the runtime's shutdown just the same.
+fn stdin() -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut chunk = [0; 4096];
+        while let Ok(n @ 1..) = stdin.read(&mut chunk) {
+            if tx.send(chunk[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Poll `session` until the run is over, copying what it receives to `output`.
+pub async fn pump(
+    rt: &Handle,
+    mut session: Session,
+    output: &mut dyn Write,
+    mut input: Input,
+    duration: Option<Duration>,
+) -> i32 {
+    let ctx = egui::Context::default();
+    let started = Instant::now();
+    let mut ticks = tokio::time::interval(TICK);
+    let mut offset = 0;
+    let mut connections = 0;
+    // A failure since the connection last came up, which decides the exit status if it does
+    // not come up again.
+    let mut failed = false;
+    session.mark_reconnects = false;
+    session.connect(rt, &ctx);
+    let code = loop {
+        ticks.tick().await;
+        if session.poll(rt, &ctx) {
+            let verb = if connections == 0 { "Connected to" } else { "Reconnected to" };
+            eprintln!("UniTerm: {verb} {}.", session.settings.label());
+            connections += 1;
+            failed = false;
+        }
+        if let Some(message) = session.last_error.take() {
+            eprintln!("UniTerm: {message}");
+            failed |= !session.is_connected();
+        }
+
+        let (data, start) = match session.buffer.lock() {
+            Ok(buffer) => {
+                let (data, start) = buffer.slice_from(offset);
+                (data.to_vec(), start)
+            }
+            Err(_) => (Vec::new(), offset),
+        };
+        if start > offset {
+            eprintln!("UniTerm: {} bytes were lost: the output fell behind.", start - offset);
+        }
+        offset = start + data.len() as u64;
+        if !data.is_empty() {
+            if let Err(e) = output.write_all(&data).and_then(|()| output.flush()) {
+                eprintln!("UniTerm: Could not write what was received: {e}");
+                break 1;
+            }
+            if let Input::Script(script) = &mut input {
+                script.feed(&data);
+            }
+        }
+
+        if let Some(rejection) = &session.pending_host_key {
+            eprintln!(
+                "UniTerm: {} Trust it from the window, or add it to known_hosts.",
+                rejection.message()
+            );
+            break 1;
+        }
+        if session.pending_challenge.is_some() {
+            eprintln!("UniTerm: The server asks for a one-time code, which needs the window.");
+            break 1;
+        }
+
+        if session.is_connected() {
+            match &mut input {
+                Input::Stdin(stdin) => {
+                    while let Ok(bytes) = stdin.try_recv() {
+                        session.send(bytes);
+                    }
+                }
+                Input::Script(script) => match script.advance(Instant::now(), enter(&session)) {
+                    Ok(Some(sends)) => sends.into_iter().for_each(|bytes| session.send(bytes)),
+                    Ok(None) => break 0,
+                    Err(e) => {
+                        eprintln!("UniTerm: {e}");
+                        break 1;
+                    }
+                },
+            }
+        }
+
+        if session.state == ConnectionState::Disconnected && session.retry_countdown().is_none()
+        {
+            break i32::from(failed);
+        }
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break 0;
+        }
+    };
+    session.disconnect();
+    code
+}
+
+/// What the Enter key sends in this tab.
+fn enter(session: &Session) -> &'static [u8] {
+    if session.enter_crlf {
+        b"\r\n"
+    } else {
+        b"\r"
+    }
+}
+
+/// Lines of things to send and to wait for.
+///
+/// ```text
+/// # Log in to the bootloader and check its version.
+/// timeout 30
+/// expect Hit any key
+/// send x
+/// expect =>\x20
+/// line version
+/// expect U-Boot 2024
+/// wait 0.5
+/// ```
+///
+/// `send` sends its text, `line` the same followed by Enter, `expect` waits for its text to
+/// arrive, `wait` pauses, and `timeout` sets how many seconds each later `expect` waits before
+/// the run fails. Text is taken as written after the single space following the command, with
+/// `\r`, `\n`, `\t`, `\e`, `\0`, `\xHH` and `\\` for what cannot be typed. An `expect` looks at
+/// what arrived since the previous one matched, so a prompt that came and went is not matched
+/// again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Script {
+    steps: Vec<Step>,
+    next: usize,
+    /// When the current step began.
+    since: Option<Instant>,
+    /// Received and not yet matched by an `expect`.
+    seen: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Send(Vec<u8>),
+    Line(Vec<u8>),
+    Wait(Duration),
+    Expect(Vec<u8>, Duration),
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut steps = Vec::new();
+        let mut timeout = DEFAULT_TIMEOUT;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line).trim_start();
+            if line.trim_end().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let at = |e: String| format!("line {}: {e}", number + 1);
+            let seconds = || {
+                argument
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                    .map(Duration::from_secs_f64)
+                    .ok_or_else(|| at(format!("{argument:?} is not a number of seconds.")))
+            };
+            steps.push(match command {
+                "send" => Step::Send(unescape(argument).map_err(at)?),
+                "line" => Step::Line(unescape(argument).map_err(at)?),
+                "wait" => Step::Wait(seconds()?),
+                "timeout" => {
+                    timeout = seconds()?;
+                    continue;
+                }
+                "expect" => match unescape(argument).map_err(at)? {
+                    text if text.is_empty() => return Err(at("expect needs some text.".into())),
+                    text => Step::Expect(text, timeout),
+                },
+                other => {
+                    return Err(at(format!(
+                        "unknown command {other:?}; use send, line, expect, wait or timeout."
+                    )))
+                }
+            });
+        }
+        Ok(Self {
+            steps,
+            next: 0,
+            since: None,
+            seen: Vec::new(),
+        })
+    }
+
+    /// Received bytes, for `expect`.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.seen.extend_from_slice(data);
+        if self.seen.len() > SEEN_LIMIT {
+            self.seen.drain(..self.seen.len() - SEEN_LIMIT / 2);
+        }
+    }
+
+    /// Run as far as the script can go at `now`: what to send, or `None` once it has finished.
+    pub fn advance(&mut self, now: Instant, enter: &[u8]) -> Result<Option<Vec<Vec<u8>>>, String> {
+        let mut sends = Vec::new();
+        while let Some(step) = self.steps.get(self.next) {
+            let since = *self.since.get_or_insert(now);
+            match step {
+                Step::Send(text) => sends.push(text.clone()),
+                Step::Line(text) => sends.push([text.as_slice(), enter].concat()),
+                Step::Wait(duration) => {
+                    if now < since + *duration {
+                        return Ok(Some(sends));
+                    }
+                }
+                Step::Expect(text, within) => {
+                    let found = self.seen.windows(text.len()).position(|w| w == text.as_slice());
+                    match found {
+                        Some(at) => {
+                            self.seen.drain(..at + text.len());
+                        }
+                        None if now < since + *within => return Ok(Some(sends)),
+                        None => {
+                            return Err(format!(
+                                "Expected \"{}\" within {}s, and it did not arrive.",
+                                String::from_utf8_lossy(text).escape_debug(),
+                                within.as_secs_f64()
+                            ))
+                        }
+                    }
+                }
+            }
+            self.next += 1;
+            self.since = None;
+        }
+        // Whatever the last steps sent still has to go out before the run ends.
+        Ok((!sends.is_empty()).then_some(sends))
+    }
+}
+
+/// A script's text as bytes, escapes and all.
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => out.push(b'\r'),
+            Some('n') => out.push(b'\n'),
+            Some('t') => out.push(b'\t'),
+            Some('e') => out.push(0x1b),
+            Some('0') => out.push(0),
+            Some('\\') => out.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => out.push(byte),
+                    _ => return Err(format!("\\x{hex} needs two hex digits.")),
+                }
+            }
+            Some(other) => return Err(format!("\\{other} is not an escape.")),
+            None => return Err("a line cannot end in \\.".into()),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_read_as_steps_with_escapes_and_timeouts() {
+        let script = Script::parse(
+            "# comment\r\n\nsend a\\x20b\\r\nline  spaced\ntimeout 2.5\nexpect =>\\x20\nwait 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            script.steps,
+            [
+                Step::Send(b"a b\r".to_vec()),
+                Step::Line(b" spaced".to_vec()),
+                Step::Expect(b"=> ".to_vec(), Duration::from_millis(2500)),
+                Step::Wait(Duration::from_secs(1)),
+            ]
+        );
+
+        let error = |text: &str| Script::parse(text).unwrap_err();
+        assert!(error("send ok\nsned typo").starts_with("line 2: unknown command"));
+        assert!(error("wait soon").contains("not a number of seconds"));
+        assert!(error("expect").contains("needs some text"));
+        assert!(error("send \\x4").contains("two hex digits"));
+        assert!(error("send \\q").contains("not an escape"));
+    }
+
+    #[test]
+    fn indented_commands_read_the_same_with_or_without_an_argument() {
+        let script = Script::parse("  send\n\tline ok\n  expect >").unwrap();
+        assert_eq!(
+            script.steps,
+            [
+                Step::Send(Vec::new()),
+                Step::Line(b"ok".to_vec()),
+                Step::Expect(b">".to_vec(), DEFAULT_TIMEOUT),
+            ]
+        );
+        let error = Script::parse("  wait").unwrap_err();
+        assert!(error.contains("not a number of seconds"), "{error}");
+    }
+
+    #[test]
+    fn a_script_waits_for_what_it_expects_and_gives_up_after_the_timeout() {
+        let mut script =
+            Script::parse("send go\nexpect ready\nline next\ntimeout 1\nexpect done").unwrap();
+        let start = Instant::now();
+
+        assert_eq!(script.advance(start, b"\r"), Ok(Some(vec![b"go".to_vec()])));
+        assert_eq!(script.advance(start, b"\r"), Ok(Some(Vec::new())), "waiting for ready");
+        script.feed(b"...read");
+        script.feed(b"y> ");
+        assert_eq!(script.advance(start, b"\r\n"), Ok(Some(vec![b"next\r\n".to_vec()])));
+
+        // "ready" was consumed, so it cannot satisfy a later expect by accident.
+        let later = start + Duration::from_secs(2);
+        let error = script.advance(later, b"\r").unwrap_err();
+        assert!(error.contains("\"done\" within 1s"), "{error}");
+    }
+
+    #[test]
+    fn a_script_finishes_after_its_last_wait() {
+        let mut script = Script::parse("wait 0.5\nsend bye").unwrap();
+        let start = Instant::now();
+        assert_eq!(script.advance(start, b"\r"), Ok(Some(Vec::new())));
+        let later = start + Duration::from_secs(1);
+        assert_eq!(script.advance(later, b"\r"), Ok(Some(vec![b"bye".to_vec()])));
+        assert_eq!(script.advance(later, b"\r"), Ok(None));
+    }
+}
@@ -0,0 +1,242 @@
+//! Host key manager state.
+//!
+//! A panel over a known_hosts file: what it records for a host, each key's algorithm and
+//! fingerprint, and a way to drop a stale line. A refused connection opens it on the offending
+//! line, so a host that was reinstalled is dealt with here rather than in a text editor.
+//!
+//! "Replace" is remove-then-reconnect. The tab then meets the host as unknown and shows the new
+//! fingerprint for approval in the usual prompt, so nothing is ever trusted without the user
+//! having seen it. It is only offered for a key that *changed*: a revoked key replaced this way
+//! would come back as an unknown host the prompt could accept, which is exactly what a
+//! revocation is there to prevent. Marker lines are not removed here at all; see
+//! [`knownhosts::remove`].
+
+use std::path::PathBuf;
+
+use crate::app::TabId;
+use crate::knownhosts::{self, Listed, Rejection};
+
+/// The one panel. It lists a single store at a time.
+#[derive(Default)]
+pub struct HostKeys {
+    /// The known_hosts file shown. `None` when there is no home directory to find one in.
+    pub path: Option<PathBuf>,
+    /// `host`, `host:port` or `[host]:port`. Empty lists everything.
+    pub filter: String,
+    pub entries: Vec<Listed>,
+    /// The line a refusal pointed at.
+    pub highlight: Option<usize>,
+    /// The line "Replace" may remove: the one a changed key was refused over.
+    pub replaceable: Option<usize>,
+    /// A line whose removal is waiting to be confirmed.
+    pub confirm: Option<usize>,
+    /// What the last action did.
+    pub notice: Option<String>,
+    pub error: Option<String>,
+    /// The tab whose refusal opened the panel, which "Replace" reconnects.
+    pub origin: Option<TabId>,
+    /// A tab to reconnect. Consumed by the app, which owns the sessions.
+    pub reconnect: Option<TabId>,
+}
+
+impl HostKeys {
+    /// The whole of the store at `path`.
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let mut panel = Self {
+            path,
+            ..Self::default()
+        };
+        panel.refresh();
+        panel
+    }
+
+    /// Show the entries behind a refused host key, with the line it was refused over picked
+    /// out, for the tab `origin`.
+    pub fn focus(&mut self, path: Option<PathBuf>, rejection: &Rejection, origin: TabId) {
+        let (host, port, line) = match rejection {
+            Rejection::Unknown { host, port, .. } => (host, *port, None),
+            Rejection::Changed { host, port, line, .. }
+            | Rejection::Revoked { host, port, line, .. } => (host, *port, Some(*line)),
+        };
+        self.path = path;
+        self.filter = match port {
+            22 => host.clone(),
+            port => format!("[{host}]:{port}"),
+        };
+        self.highlight = line;
+        self.replaceable = match rejection {
+            Rejection::Changed { line, .. } => Some(*line),
+            _ => None,
+        };
+        self.origin = Some(origin);
+        self.confirm = None;
+        self.notice = None;
+        self.refresh();
+    }
+
+    /// Read the store again.
+    pub fn refresh(&mut self) {
+        self.error = None;
+        self.entries = match &self.path {
+            Some(path) => {
+                let host = parse_filter(&self.filter);
+                knownhosts::list(path, host.as_ref().map(|(host, port)| (host.as_str(), *port)))
+            }
+            None => Vec::new(),
+        };
+    }
+
+    /// Delete `line`, which was listed with `fingerprint`.
+    pub fn remove(&mut self, line: usize, fingerprint: &str) -> bool {
+        self.confirm = None;
+        let Some(path) = &self.path else {
+            return false;
+        };
+        let result = knownhosts::remove(path, line, fingerprint);
+        self.refresh();
+        match result {
+            Ok(backup) => {
+                let name = backup.file_name().unwrap_or_default().to_string_lossy();
+                self.notice =
+                    Some(format!("Removed line {line}. The previous file is kept as {name}."));
+                // Lines below it have moved up, so the number no longer means anything.
+                self.highlight = None;
+                self.replaceable = None;
+                true
+            }
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        }
+    }
+
+    /// Delete the stale `line` and reconnect the tab that was refused, which then asks about
+    /// the host's new key.
+    pub fn replace(&mut self, line: usize, fingerprint: &str) {
+        if self.replaceable != Some(line) {
+            self.error = Some(format!("Line {line} is not a changed key, so it is not replaced."));
+            return;
+        }
+        if self.remove(line, fingerprint) {
+            self.reconnect = self.origin.take();
+        }
+    }
+}
+
+/// The host and port a filter names, as `ssh` spells them in known_hosts.
+fn parse_filter(text: &str) -> Option<(String, u16)> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if let Some((host, port)) = text.strip_prefix('[').and_then(|rest| rest.split_once("]:")) {
+        if let Ok(port) = port.parse() {
+            return Some((host.to_owned(), port));
+        }
+    }
+    // A bare IPv6 address is full of colons; only a single one can separate a port.
+    if let Some((host, port)) = text.split_once(':').filter(|(_, port)| !port.contains(':')) {
+        if let Ok(port) = port.parse() {
+            return Some((host.to_owned(), port));
+        }
+    }
+    Some((text.to_owned(), 22))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIAgJufBWRCob7ytiVrAnEW7PvL432B7cXzJLziOZ59id";
+    const KEY_B: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIGIOya00NHtlVjWcc2n43OG86cbco7o/N0vC+N+QFrLV";
+
+    fn store(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("uniterm_hostkeys_{name}"));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn changed(line: usize, port: u16) -> Rejection {
+        Rejection::Changed {
+            host: "example.com".into(),
+            port,
+            line,
+            algorithm: "ssh-ed25519".into(),
+            fingerprint: "SHA256:new".into(),
+        }
+    }
+
+    #[test]
+    fn filters_are_read_the_way_known_hosts_writes_hosts() {
+        let parsed = |text| parse_filter(text);
+        assert_eq!(parsed(""), None);
+        assert_eq!(parsed(" srv "), Some(("srv".into(), 22)));
+        assert_eq!(parsed("srv:2222"), Some(("srv".into(), 2222)));
+        assert_eq!(parsed("[srv]:2222"), Some(("srv".into(), 2222)));
+        assert_eq!(parsed("[::1]:2222"), Some(("::1".into(), 2222)));
+        assert_eq!(parsed("fe80::1"), Some(("fe80::1".into(), 22)));
+    }
+
+    #[test]
+    fn a_refusal_opens_on_its_host_and_line() {
+        let text = format!(
+            "example.org ssh-ed25519 {KEY_A}\n[example.com]:2222 ssh-ed25519 {KEY_B}\n"
+        );
+        let path = store("focus", &text);
+        let mut panel = HostKeys::open(Some(path.clone()));
+        assert_eq!(panel.entries.len(), 2);
+
+        panel.focus(Some(path.clone()), &changed(2, 2222), TabId(7));
+        assert_eq!(panel.filter, "[example.com]:2222");
+        assert_eq!(panel.highlight, Some(2));
+        assert_eq!(panel.entries.iter().map(|e| e.line).collect::<Vec<_>>(), [2]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn replacing_removes_the_line_and_asks_for_a_reconnect() {
+        let text = format!("example.com ssh-ed25519 {KEY_A}\n");
+        let path = store("replace", &text);
+        let mut panel = HostKeys::default();
+        panel.focus(Some(path.clone()), &changed(1, 22), TabId(3));
+        let stale = panel.entries[0].fingerprint.clone();
+
+        // A fingerprint the line does not hold is refused, and nothing reconnects.
+        panel.replace(1, "SHA256:other");
+        assert!(panel.error.is_some());
+        assert_eq!(panel.reconnect, None);
+
+        panel.replace(1, &stale);
+        assert!(panel.entries.is_empty());
+        assert!(panel.notice.as_deref().unwrap().contains("line 1"));
+        assert_eq!(panel.reconnect, Some(TabId(3)));
+        assert_eq!(panel.origin, None, "one replacement, one reconnect");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(path.with_file_name("uniterm_hostkeys_replace.old"));
+    }
+
+    #[test]
+    fn a_revoked_key_is_never_replaced() {
+        let text = format!("@revoked example.com ssh-ed25519 {KEY_A}\n");
+        let path = store("revoked", &text);
+        let mut panel = HostKeys::default();
+        let revoked = Rejection::Revoked {
+            host: "example.com".into(),
+            port: 22,
+            line: 1,
+            algorithm: "ssh-ed25519".into(),
+            fingerprint: "SHA256:revoked".into(),
+        };
+        panel.focus(Some(path.clone()), &revoked, TabId(3));
+        assert_eq!((panel.highlight, panel.replaceable), (Some(1), None));
+        let fingerprint = panel.entries[0].fingerprint.clone();
+
+        panel.replace(1, &fingerprint);
+        assert_eq!(panel.reconnect, None);
+        panel.remove(1, &fingerprint);
+        assert!(panel.error.as_deref().unwrap().contains("@revoked"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+        let _ = std::fs::remove_file(&path);
+    }
+}
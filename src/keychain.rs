@@ -0,0 +1,527 @@
+//! SSH secrets kept in the OS keychain.
+//!
+//! The one place a password or key passphrase may outlive the process. A tab opts in with
+//! [`SshSettings::keychain`]; its secret is then stored under the connection's identity
+//! ([`SshSettings::identity`], `user@host:port`) and used whenever the field is left blank, so
+//! a restart, or a tab that connects on start, does not have to ask. Nothing secret goes
+//! anywhere else: the saved state only records that the tab opted in.
+//!
+//! On Linux this is the freedesktop Secret Service — GNOME Keyring, KWallet and KeePassXC all
+//! provide it — spoken to directly over the session bus. Secrets cross the bus with the
+//! `plain` algorithm, which is what `secret-tool` does too: the bus is local to the user, and
+//! the keychain encrypts at rest. A locked keychain asks to be unlocked in its own prompt.
+//!
+//! Other platforms have no backend yet, and say so when asked.
+
+use crate::session::ssh::Credentials;
+use crate::settings::{SshAuth, SshSettings};
+
+/// Whether this build can reach a keychain at all. The option is only offered where it can.
+pub const AVAILABLE: bool = cfg!(target_os = "linux");
+
+/// Whether `ssh` keeps its secret in the keychain.
+pub fn enabled(ssh: &SshSettings) -> bool {
+    AVAILABLE && ssh.keychain
+}
+
+/// Which secret an entry holds. A tab only ever needs the one its auth method uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Password,
+    Passphrase,
+}
+
+impl Kind {
+    /// The secret `auth` authenticates with, if it takes one.
+    pub fn wanted(auth: SshAuth) -> Option<Self> {
+        match auth {
+            SshAuth::Password => Some(Self::Password),
+            SshAuth::PublicKey => Some(Self::Passphrase),
+            SshAuth::Agent => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Password => "password",
+            Self::Passphrase => "key passphrase",
+        }
+    }
+
+    /// The field of `credentials` this secret goes in.
+    pub fn slot(self, credentials: &mut Credentials) -> &mut String {
+        match self {
+            Self::Password => &mut credentials.password,
+            Self::Passphrase => &mut credentials.passphrase,
+        }
+    }
+
+    /// The `secret` attribute, which keeps a host's password and passphrase apart.
+    #[cfg(target_os = "linux")]
+    fn attribute(self) -> &'static str {
+        match self {
+            Self::Password => "password",
+            Self::Passphrase => "passphrase",
+        }
+    }
+}
+
+/// What an entry is found by. `application` keeps UniTerm's entries apart from everyone
+/// else's, and lets a user find them with `secret-tool search application uniterm`.
+#[cfg(target_os = "linux")]
+fn attributes(ssh: &SshSettings, kind: Option<Kind>) -> Vec<(&'static str, String)> {
+    let mut attributes = vec![("application", "uniterm".to_owned()), ("identity", ssh.identity())];
+    if let Some(kind) = kind {
+        attributes.push(("secret", kind.attribute().to_owned()));
+    }
+    attributes
+}
+
+/// The saved `kind` of secret for `ssh`, if there is one.
+pub async fn recall(ssh: &SshSettings, kind: Kind) -> Result<Option<String>, String> {
+    #[cfg(target_os = "linux")]
+    {
+        let service = secret_service::SecretService::connect().await?;
+        service.recall(&attributes(ssh, Some(kind))).await
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (ssh, kind);
+        Err(UNSUPPORTED.to_owned())
+    }
+}
+
+/// Save `secret` as the `kind` for `ssh`, replacing any saved before.
+pub async fn remember(ssh: &SshSettings, kind: Kind, secret: &str) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        let label = format!("UniTerm {} for {}", kind.label(), ssh.identity());
+        let service = secret_service::SecretService::connect().await?;
+        service.remember(&label, &attributes(ssh, Some(kind)), secret).await
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (ssh, kind, secret);
+        Err(UNSUPPORTED.to_owned())
+    }
+}
+
+/// Delete everything saved for `ssh`, and say how many entries that was.
+pub async fn forget(ssh: &SshSettings) -> Result<usize, String> {
+    #[cfg(target_os = "linux")]
+    {
+        let service = secret_service::SecretService::connect().await?;
+        service.forget(&attributes(ssh, None)).await
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = ssh;
+        Err(UNSUPPORTED.to_owned())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+const UNSUPPORTED: &str = "Keychain storage is only available on Linux so far.";
+
+#[cfg(target_os = "linux")]
+mod secret_service {
+    //! A client for the parts of the Secret Service API that storing a few passwords needs.
+
+    use std::collections::HashMap;
+    use std::future::poll_fn;
+    use std::pin::pin;
+
+    use serde::{Deserialize, Serialize};
+    use zbus::export::futures_core::Stream;
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Type, Value};
+    use zbus::{Connection, Proxy};
+
+    const DESTINATION: &str = "org.freedesktop.secrets";
+    pub const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    const SERVICE: &str = "org.freedesktop.Secret.Service";
+    const COLLECTION: &str = "org.freedesktop.Secret.Collection";
+    const ITEM: &str = "org.freedesktop.Secret.Item";
+    const PROMPT: &str = "org.freedesktop.Secret.Prompt";
+    /// Where the default collection can be reached when `ReadAlias` has none to name.
+    const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+    /// A secret as the API passes it around.
+    #[derive(Debug, Serialize, Deserialize, Type)]
+    pub struct Secret {
+        pub session: OwnedObjectPath,
+        pub parameters: Vec<u8>,
+        pub value: Vec<u8>,
+        pub content_type: String,
+    }
+
+    fn failed(e: zbus::Error) -> String {
+        format!("The keychain did not answer: {e}")
+    }
+
+    pub struct SecretService {
+        connection: Connection,
+        /// Opened once per connection; the `plain` algorithm needs no more than that.
+        session: OwnedObjectPath,
+    }
+
+    impl SecretService {
+        /// The service on the user's session bus.
+        pub async fn connect() -> Result<Self, String> {
+            let connection = Connection::session()
+                .await
+                .map_err(|e| format!("Could not reach the keychain on the session bus: {e}"))?;
+            Self::over(connection).await
+        }
+
+        /// The service at the other end of `connection`.
+        pub async fn over(connection: Connection) -> Result<Self, String> {
+            let service = proxy(&connection, SERVICE_PATH, SERVICE).await?;
+            let (_, session): (OwnedValue, OwnedObjectPath) = service
+                .call("OpenSession", &("plain", Value::from("")))
+                .await
+                .map_err(failed)?;
+            Ok(Self {
+                connection,
+                session,
+            })
+        }
+
+        pub async fn recall(
+            &self,
+            attributes: &[(&str, String)],
+        ) -> Result<Option<String>, String> {
+            let Some(item) = self.search(attributes).await?.into_iter().next() else {
+                return Ok(None);
+            };
+            let item = proxy(&self.connection, item.as_str(), ITEM).await?;
+            let (secret,): (Secret,) =
+                item.call("GetSecret", &(&self.session,)).await.map_err(failed)?;
+            String::from_utf8(secret.value)
+                .map(Some)
+                .map_err(|_| "The saved secret is not text.".to_owned())
+        }
+
+        pub async fn remember(
+            &self,
+            label: &str,
+            attributes: &[(&str, String)],
+            secret: &str,
+        ) -> Result<(), String> {
+            let service = proxy(&self.connection, SERVICE_PATH, SERVICE).await?;
+            let (collection,): (OwnedObjectPath,) =
+                service.call("ReadAlias", &("default",)).await.map_err(failed)?;
+            let collection = match collection.as_str() {
+                "/" => ObjectPath::from_static_str_unchecked(DEFAULT_COLLECTION).into(),
+                _ => collection,
+            };
+            self.unlock(vec![collection.clone()]).await?;
+
+            let lookup: HashMap<&str, &str> =
+                attributes.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            let properties: HashMap<&str, Value> = HashMap::from([
+                ("org.freedesktop.Secret.Item.Label", Value::from(label)),
+                ("org.freedesktop.Secret.Item.Attributes", Value::from(lookup)),
+            ]);
+            let secret = Secret {
+                session: self.session.clone(),
+                parameters: Vec::new(),
+                value: secret.as_bytes().to_vec(),
+                content_type: "text/plain; charset=utf8".to_owned(),
+            };
+            let collection = proxy(&self.connection, collection.as_str(), COLLECTION).await?;
+            let (_, prompt): (OwnedObjectPath, OwnedObjectPath) = collection
+                .call("CreateItem", &(properties, secret, true))
+                .await
+                .map_err(failed)?;
+            self.prompt(prompt).await
+        }
+
+        pub async fn forget(&self, attributes: &[(&str, String)]) -> Result<usize, String> {
+            let items = self.search(attributes).await?;
+            for item in &items {
+                let item = proxy(&self.connection, item.as_str(), ITEM).await?;
+                let (prompt,): (OwnedObjectPath,) =
+                    item.call("Delete", &()).await.map_err(failed)?;
+                self.prompt(prompt).await?;
+            }
+            Ok(items.len())
+        }
+
+        /// Every item matching `attributes`, unlocked.
+        async fn search(
+            &self,
+            attributes: &[(&str, String)],
+        ) -> Result<Vec<OwnedObjectPath>, String> {
+            let service = proxy(&self.connection, SERVICE_PATH, SERVICE).await?;
+            let query: HashMap<&str, &str> =
+                attributes.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            let (mut unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+                service.call("SearchItems", &(query,)).await.map_err(failed)?;
+            if !locked.is_empty() {
+                unlocked.extend(self.unlock(locked).await?);
+            }
+            Ok(unlocked)
+        }
+
+        /// Unlock `objects`, asking the user if the keychain wants to.
+        async fn unlock(
+            &self,
+            objects: Vec<OwnedObjectPath>,
+        ) -> Result<Vec<OwnedObjectPath>, String> {
+            let service = proxy(&self.connection, SERVICE_PATH, SERVICE).await?;
+            let (mut unlocked, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+                service.call("Unlock", &(&objects,)).await.map_err(failed)?;
+            if prompt.as_str() != "/" {
+                self.prompt(prompt).await?;
+                // The prompt's result is the unlocked objects, but not every keychain fills it
+                // in; having unlocked, asking for all of them is what they meant.
+                unlocked = objects;
+            }
+            Ok(unlocked)
+        }
+
+        /// Show the keychain's own prompt at `path`, if there is one, and wait for the user.
+        async fn prompt(&self, path: OwnedObjectPath) -> Result<(), String> {
+            if path.as_str() == "/" {
+                return Ok(());
+            }
+            let prompt = proxy(&self.connection, path.as_str(), PROMPT).await?;
+            let completed = prompt.receive_signal("Completed").await.map_err(failed)?;
+            let () = prompt.call("Prompt", &("",)).await.map_err(failed)?;
+            let mut completed = pin!(completed);
+            let message = poll_fn(|cx| completed.as_mut().poll_next(cx))
+                .await
+                .ok_or("The keychain went away while asking.")?;
+            let (dismissed, _): (bool, OwnedValue) =
+                message.body().deserialize().map_err(failed)?;
+            if dismissed {
+                return Err("The keychain was not unlocked.".to_owned());
+            }
+            Ok(())
+        }
+    }
+
+    async fn proxy<'a>(
+        connection: &Connection,
+        path: &'a str,
+        interface: &'a str,
+    ) -> Result<Proxy<'a>, String> {
+        Proxy::new(connection, DESTINATION, path, interface).await.map_err(failed)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+    use zbus::{fdo, interface, ObjectServer};
+
+    use super::secret_service::{Secret, SecretService, SERVICE_PATH};
+    use super::*;
+
+    const COLLECTION_PATH: &str = "/org/freedesktop/secrets/collection/login";
+
+    fn path(text: &str) -> OwnedObjectPath {
+        ObjectPath::try_from(text.to_owned()).unwrap().into()
+    }
+
+    /// One item in the stand-in keychain: its path, attributes and secret.
+    type Held = (OwnedObjectPath, HashMap<String, String>, Vec<u8>);
+
+    /// What the stand-in keychain holds.
+    #[derive(Clone, Default)]
+    struct Store(Arc<Mutex<Vec<Held>>>);
+
+    /// Just enough of a Secret Service to be told apart from a broken client: it refuses any
+    /// algorithm but `plain` and matches items on every attribute asked for.
+    struct Service(Store);
+
+    #[interface(name = "org.freedesktop.Secret.Service")]
+    impl Service {
+        fn open_session(
+            &self,
+            algorithm: &str,
+            _input: OwnedValue,
+        ) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+            if algorithm != "plain" {
+                return Err(fdo::Error::NotSupported(algorithm.to_owned()));
+            }
+            let output = OwnedValue::try_from(Value::from("")).unwrap();
+            Ok((output, path("/org/freedesktop/secrets/session/1")))
+        }
+
+        fn search_items(
+            &self,
+            attributes: HashMap<String, String>,
+        ) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+            let items = self.0 .0.lock().unwrap();
+            let found = items
+                .iter()
+                .filter(|(_, held, _)| attributes.iter().all(|(k, v)| held.get(k) == Some(v)))
+                .map(|(path, _, _)| path.clone())
+                .collect();
+            (found, Vec::new())
+        }
+
+        fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+            (objects, path("/"))
+        }
+
+        fn read_alias(&self, _name: &str) -> OwnedObjectPath {
+            path(COLLECTION_PATH)
+        }
+    }
+
+    struct Collection(Store);
+
+    #[interface(name = "org.freedesktop.Secret.Collection")]
+    impl Collection {
+        async fn create_item(
+            &self,
+            #[zbus(object_server)] server: &ObjectServer,
+            properties: HashMap<String, OwnedValue>,
+            secret: Secret,
+            replace: bool,
+        ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+            let attributes = properties
+                .get("org.freedesktop.Secret.Item.Attributes")
+                .and_then(|v| HashMap::<String, String>::try_from(v.try_clone().ok()?).ok())
+                .ok_or_else(|| fdo::Error::InvalidArgs("no attributes".into()))?;
+            let item = {
+                let mut items = self.0 .0.lock().unwrap();
+                match items.iter_mut().find(|(_, held, _)| *held == attributes) {
+                    Some((item, _, value)) if replace => {
+                        *value = secret.value;
+                        return Ok((item.clone(), path("/")));
+                    }
+                    _ => {
+                        let item = path(&format!("{COLLECTION_PATH}/{}", items.len() + 1));
+                        items.push((item.clone(), attributes, secret.value));
+                        item
+                    }
+                }
+            };
+            let served = Item {
+                store: self.0.clone(),
+                path: item.clone(),
+            };
+            server.at(item.as_str(), served).await?;
+            Ok((item, path("/")))
+        }
+    }
+
+    struct Item {
+        store: Store,
+        path: OwnedObjectPath,
+    }
+
+    #[interface(name = "org.freedesktop.Secret.Item")]
+    impl Item {
+        fn get_secret(&self, session: OwnedObjectPath) -> fdo::Result<Secret> {
+            let items = self.store.0.lock().unwrap();
+            let (_, _, value) = items
+                .iter()
+                .find(|(item, _, _)| *item == self.path)
+                .ok_or_else(|| fdo::Error::UnknownObject(self.path.to_string()))?;
+            Ok(Secret {
+                session,
+                parameters: Vec::new(),
+                value: value.clone(),
+                content_type: "text/plain".into(),
+            })
+        }
+
+        fn delete(&self) -> OwnedObjectPath {
+            self.store.0.lock().unwrap().retain(|(item, _, _)| *item != self.path);
+            path("/")
+        }
+    }
+
+    /// A client connected to a fresh stand-in keychain, and what the keychain holds.
+    async fn keychain() -> (SecretService, Store) {
+        let store = Store::default();
+        let (client, server) = UnixStream::pair().unwrap();
+        let guid = zbus::Guid::generate();
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(guid)
+            .unwrap()
+            .p2p()
+            .serve_at(SERVICE_PATH, Service(store.clone()))
+            .unwrap()
+            .serve_at(COLLECTION_PATH, Collection(store.clone()))
+            .unwrap()
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (server, client) = tokio::join!(server, client);
+        // The stand-in lives as long as its connection; leak it for the length of the test.
+        std::mem::forget(server.unwrap());
+        let service = SecretService::over(client.unwrap()).await.unwrap();
+        (service, store)
+    }
+
+    fn bench() -> SshSettings {
+        SshSettings {
+            host: "bench-3".into(),
+            port: 2222,
+            user: "lab".into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn secrets_are_kept_per_identity_and_kind() {
+        let (keychain, store) = keychain().await;
+        let ssh = bench();
+        let password = attributes(&ssh, Some(Kind::Password));
+        let passphrase = attributes(&ssh, Some(Kind::Passphrase));
+
+        assert_eq!(keychain.recall(&password).await.unwrap(), None);
+        keychain.remember("pw", &password, "hunter2").await.unwrap();
+        keychain.remember("pp", &passphrase, "open sesame").await.unwrap();
+        assert_eq!(keychain.recall(&password).await.unwrap().as_deref(), Some("hunter2"));
+        assert_eq!(keychain.recall(&passphrase).await.unwrap().as_deref(), Some("open sesame"));
+
+        // Another user on the same host is another identity.
+        let other = SshSettings {
+            user: "root".into(),
+            ..bench()
+        };
+        let other = attributes(&other, Some(Kind::Password));
+        assert_eq!(keychain.recall(&other).await.unwrap(), None);
+
+        let held = store.0.lock().unwrap().clone();
+        assert_eq!(held.len(), 2);
+        assert_eq!(held[0].1["identity"], "lab@bench-3:2222");
+        assert_eq!(held[0].1["application"], "uniterm");
+    }
+
+    #[tokio::test]
+    async fn remembering_again_replaces_and_forgetting_removes_both() {
+        let (keychain, store) = keychain().await;
+        let ssh = bench();
+        let password = attributes(&ssh, Some(Kind::Password));
+        keychain.remember("pw", &password, "old").await.unwrap();
+        keychain.remember("pw", &password, "new").await.unwrap();
+        assert_eq!(store.0.lock().unwrap().len(), 1, "replaced, not added");
+        assert_eq!(keychain.recall(&password).await.unwrap().as_deref(), Some("new"));
+
+        let passphrase = attributes(&ssh, Some(Kind::Passphrase));
+        keychain.remember("pp", &passphrase, "pp").await.unwrap();
+        assert_eq!(keychain.forget(&attributes(&ssh, None)).await.unwrap(), 2);
+        assert_eq!(keychain.recall(&password).await.unwrap(), None);
+        assert!(store.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn each_auth_method_wants_its_own_secret() {
+        let mut credentials = Credentials::default();
+        assert_eq!(Kind::wanted(SshAuth::Agent), None);
+        *Kind::wanted(SshAuth::Password).unwrap().slot(&mut credentials) = "pw".into();
+        *Kind::wanted(SshAuth::PublicKey).unwrap().slot(&mut credentials) = "pp".into();
+        assert_eq!(credentials.password, "pw");
+        assert_eq!(credentials.passphrase, "pp");
+    }
+}
@@ -18,14 +18,45 @@
 //!
 //! A host legitimately may serve several key types, so a new *type* for a known host reports
 //! `Unknown` rather than `Changed`. That matches OpenSSH.
+//!
+//! # Markers
+//!
+//! Centrally distributed files use two of OpenSSH's line markers:
+//!
+//! * `@revoked` — the key is refused for the hosts the line names, as [`Trust::Revoked`], even
+//!   if a plain line elsewhere in the file lists it. Like a changed key it cannot be waved
+//!   through.
+//! * `@cert-authority` — recognised and listed, but it vouches for nothing here. russh 0.62's
+//!   client only negotiates plain host key algorithms, so a server never presents a
+//!   certificate and hosts behind such a CA are checked key by key.
+//!
+//! Marker lines name hosts by pattern (`*.lab.example.com`, `!gw.lab.example.com`) rather
+//! than by name, since one line covers a whole fleet. Their keys never count as plain host
+//! keys: a CA's key is not the key of the hosts it signs for.
+//!
+//! # Hashed names
+//!
+//! With `HashKnownHosts yes` OpenSSH writes `|1|salt|hash` — an HMAC-SHA1 of the host name,
+//! keyed by a random salt — instead of the name, so a stolen file does not list where its
+//! owner connects. Such entries are matched here wherever they appear, and
+//! [`learn_hashed`] writes them for sites whose policy requires it.
 
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-use russh::keys::known_hosts::{check_known_hosts_path, learn_known_hosts_path};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use russh::keys::ssh_encoding::base64::{Base64, Encoding};
+use russh::keys::ssh_key::public::KeyData;
 use russh::keys::ssh_key::PublicKey;
 use russh::keys::HashAlg;
 use tracing::warn;
 
+use crate::settings::SshSettings;
+
+/// How a hashed host name starts: OpenSSH's only hash format, HMAC-SHA1.
+const HASH_MAGIC: &str = "|1|";
+
 /// Result of checking a server key against the store.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Trust {
@@ -35,6 +66,8 @@ pub enum Trust {
     Unknown,
     /// Recorded differently for the same key type. Refuse.
     Changed { line: usize },
+    /// Marked `@revoked` at `line`. Refuse.
+    Revoked { line: usize },
 }
 
 /// The user's `known_hosts` file.
@@ -49,6 +82,11 @@ pub fn default_path() -> Option<PathBuf> {
     resolve_path(std::env::var_os("UNITERM_KNOWN_HOSTS"), dirs_home())
 }
 
+/// The store an SSH tab checks against: its own override, or the user's file.
+pub fn path_for(settings: &SshSettings) -> Option<PathBuf> {
+    settings.known_hosts.clone().or_else(default_path)
+}
+
 /// The choice itself, split out so it can be tested without touching the environment.
 fn resolve_path(override_var: Option<std::ffi::OsString>, home: Option<PathBuf>) -> Option<PathBuf> {
     if let Some(explicit) = override_var {
@@ -69,42 +107,366 @@ fn dirs_home() -> Option<PathBuf> {
         .filter(|p| !p.as_os_str().is_empty())
 }
 
-/// Strip the comment from a key.
+/// Strip the comment from a key before recording it.
 ///
-/// `ssh_key::PublicKey`'s equality includes the trailing comment, and russh's comparison uses
-/// it, so a key carrying `user@host` would be reported as *changed* against the same key
-/// recorded without one. A comment is metadata, not identity, and mistaking it for tampering
-/// would train users to click through the one warning that must never be clicked through.
+/// A comment is metadata, not identity; keys are compared without it, and it has no business
+/// in somebody else's `known_hosts`.
 fn without_comment(key: &PublicKey) -> PublicKey {
     PublicKey::new(key.key_data().clone(), "")
 }
 
+/// Which marker a line carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Marker {
+    CertAuthority,
+    Revoked,
+}
+
+impl Marker {
+    fn label(self) -> &'static str {
+        match self {
+            Self::CertAuthority => "@cert-authority",
+            Self::Revoked => "@revoked",
+        }
+    }
+}
+
+/// One key line of the store.
+struct Entry {
+    line: usize,
+    marker: Option<Marker>,
+    /// The host field: names, patterns or hashes, comma-separated.
+    patterns: String,
+    key: KeyData,
+}
+
+/// Every key line in the store, comments and malformed lines skipped. An unreadable store has
+/// none, which is safe: it cannot make anything *more* trusted.
+fn entries(path: &Path) -> Vec<Entry> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("could not read {}: {e}", path.display());
+            }
+            return Vec::new();
+        }
+    };
+    let mut found = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        let marker = match fields.next() {
+            None => continue,
+            Some(comment) if comment.starts_with('#') => continue,
+            Some("@cert-authority") => Some(Marker::CertAuthority),
+            Some("@revoked") => Some(Marker::Revoked),
+            Some(unknown) if unknown.starts_with('@') => continue,
+            // Not a marker after all: the host field itself.
+            Some(_) => {
+                fields = line.split_whitespace();
+                None
+            }
+        };
+        let (Some(patterns), Some(_algorithm), Some(base64)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        match russh::keys::parse_public_key_base64(base64) {
+            Ok(key) => found.push(Entry {
+                line: index + 1,
+                marker,
+                patterns: patterns.to_owned(),
+                key: key.key_data().clone(),
+            }),
+            Err(e) => warn!("skipping line {} of {}: {e}", index + 1, path.display()),
+        }
+    }
+    found
+}
+
+/// The entries that apply to this host.
+fn matching(host: &str, port: u16, path: &Path) -> Vec<Entry> {
+    let name = host_name(host, port);
+    entries(path)
+        .into_iter()
+        .filter(|entry| host_matches(&entry.patterns, &name))
+        .collect()
+}
+
+/// The host as known_hosts names it: bare on port 22, `[host]:port` otherwise, lower-cased as
+/// `ssh` does before hashing or matching.
+fn host_name(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_lowercase()
+    } else {
+        format!("[{}]:{port}", host.to_lowercase())
+    }
+}
+
+/// Whether a comma-separated pattern list names this host. A matching `!pattern` excludes the
+/// host whatever else matches, as in OpenSSH.
+fn host_matches(patterns: &str, name: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(negated) if pattern_matches(negated, name) => return false,
+            Some(_) => {}
+            None => matched |= pattern_matches(pattern, name),
+        }
+    }
+    matched
+}
+
+fn pattern_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix(HASH_MAGIC) {
+        Some(hashed) => hash_matches(hashed, name),
+        None => wildcard(&pattern.to_lowercase(), name),
+    }
+}
+
+/// Whether `salt|hash` (both base64) is the hash of `name`.
+fn hash_matches(hashed: &str, name: &str) -> bool {
+    let Some((salt, hash)) = hashed.split_once('|') else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (Base64::decode_vec(salt), Base64::decode_vec(hash)) else {
+        return false;
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &salt);
+    hmac::verify(&key, name.as_bytes(), &hash).is_ok()
+}
+
+/// `|1|salt|hash` for `name`, with a fresh salt.
+fn hash_name(name: &str) -> Result<String, String> {
+    let mut salt = [0u8; 20];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| "Could not generate a salt for the host name.".to_owned())?;
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &salt);
+    let hash = hmac::sign(&key, name.as_bytes());
+    Ok(format!(
+        "{HASH_MAGIC}{}|{}",
+        Base64::encode_string(&salt),
+        Base64::encode_string(hash.as_ref())
+    ))
+}
+
+/// Glob match with `*` and `?`, the only wildcards known_hosts patterns have. ssh_config's
+/// `Host` patterns are the same.
+pub fn wildcard(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has swallowed so far.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// The `@revoked` line covering `key` for this host.
+fn revoked(entries: &[Entry], key: &KeyData) -> Option<usize> {
+    entries
+        .iter()
+        .find(|e| e.marker == Some(Marker::Revoked) && &e.key == key)
+        .map(|e| e.line)
+}
+
 /// Check a server key.
 ///
 /// A store that does not exist yet counts as [`Trust::Unknown`] — the first connection to
-/// anything is a first use.
+/// anything is a first use. Any line recording this exact key makes it known, even if another
+/// line records a different key of the same type, as in OpenSSH.
 pub fn check(host: &str, port: u16, key: &PublicKey, path: &Path) -> Trust {
-    if !path.exists() {
-        return Trust::Unknown;
-    }
-    let key = &without_comment(key);
-    match check_known_hosts_path(host, port, key, path) {
-        Ok(true) => Trust::Known,
-        Ok(false) => Trust::Unknown,
-        Err(russh::keys::Error::KeyChanged { line }) => Trust::Changed { line },
-        Err(e) => {
-            // An unreadable or malformed store must not be treated as "trusted". Falling
-            // back to Unknown means the user is asked rather than silently accepted.
-            warn!("could not read {}: {e}", path.display());
-            Trust::Unknown
+    let entries = matching(host, port, path);
+    if let Some(line) = revoked(&entries, key.key_data()) {
+        return Trust::Revoked { line };
+    }
+    let mut changed = None;
+    for entry in entries.iter().filter(|e| e.marker.is_none()) {
+        if entry.key.algorithm() != key.algorithm() {
+            continue;
+        }
+        if &entry.key == key.key_data() {
+            return Trust::Known;
         }
+        changed.get_or_insert(entry.line);
+    }
+    match changed {
+        Some(line) => Trust::Changed { line },
+        None => Trust::Unknown,
     }
 }
 
 /// Record a key, creating the store if needed.
 pub fn learn(host: &str, port: u16, key: &PublicKey, path: &Path) -> Result<(), String> {
-    learn_known_hosts_path(host, port, &without_comment(key), path)
-        .map_err(|e| format!("Could not write {}: {e}", path.display()))
+    let name = if port == 22 {
+        host.to_owned()
+    } else {
+        format!("[{host}]:{port}")
+    };
+    record(&name, key, path)
+}
+
+/// Record a key under a hashed host name, as OpenSSH's `HashKnownHosts yes` does.
+pub fn learn_hashed(host: &str, port: u16, key: &PublicKey, path: &Path) -> Result<(), String> {
+    record(&hash_name(&host_name(host, port))?, key, path)
+}
+
+/// Append `hosts key` to the store, on a line of its own.
+fn record(hosts: &str, key: &PublicKey, path: &Path) -> Result<(), String> {
+    let failed = |e: std::io::Error| format!("Could not write {}: {e}", path.display());
+    let key = without_comment(key).to_openssh().map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(failed)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(failed)?;
+    // A file whose last line lacks its newline would otherwise get this entry glued on.
+    let mut last = [0u8; 1];
+    let needs_newline = file.seek(SeekFrom::End(-1)).is_ok()
+        && file.read_exact(&mut last).is_ok()
+        && last[0] != b'\n';
+    let separator = if needs_newline { "\n" } else { "" };
+    file.write_all(format!("{separator}{hosts} {key}\n").as_bytes())
+        .map_err(failed)
+}
+
+/// A line of the store, as the host key manager shows it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Listed {
+    pub line: usize,
+    /// The host field as written. A hashed one cannot be read back, only matched.
+    pub hosts: String,
+    /// `@revoked` or `@cert-authority`, for a marker line.
+    pub marker: Option<&'static str>,
+    pub algorithm: String,
+    pub fingerprint: String,
+}
+
+impl Listed {
+    pub fn is_hashed(&self) -> bool {
+        self.hosts.starts_with(HASH_MAGIC)
+    }
+}
+
+/// The store's entries, or only those that apply to `host` and port — hashed ones included,
+/// which is the only way to find those at all.
+pub fn list(path: &Path, host: Option<(&str, u16)>) -> Vec<Listed> {
+    let name = host.map(|(host, port)| host_name(host, port));
+    entries(path)
+        .into_iter()
+        .filter(|entry| match &name {
+            Some(name) => host_matches(&entry.patterns, name),
+            None => true,
+        })
+        .map(|entry| {
+            let key = PublicKey::new(entry.key, "");
+            Listed {
+                line: entry.line,
+                hosts: entry.patterns,
+                marker: entry.marker.map(Marker::label),
+                algorithm: key.algorithm().to_string(),
+                fingerprint: fingerprint(&key),
+            }
+        })
+        .collect()
+}
+
+/// Delete line `line`, provided it still records the key with `fingerprint`. The check stops a
+/// stale listing deleting whatever has moved onto that line since, if the file was edited
+/// meanwhile. The file as it was is kept beside it as `known_hosts.old`, as `ssh-keygen -R`
+/// does, and its path is returned.
+///
+/// Marker lines are refused: dropping a `@revoked` line would let the key it revokes be trusted
+/// again, and a `@cert-authority` line vouches for a whole fleet. Those are edited by hand.
+///
+/// A symlinked store is rewritten where it really is, so the link survives, and both files
+/// keep the original's permissions.
+pub fn remove(path: &Path, line: usize, fingerprint: &str) -> Result<PathBuf, String> {
+    let listed = list(path, None);
+    let Some(entry) = listed
+        .iter()
+        .find(|listed| listed.line == line && listed.fingerprint == fingerprint)
+    else {
+        return Err(format!(
+            "Line {line} of {} no longer holds that key. Refresh and try again.",
+            path.display()
+        ));
+    };
+    if let Some(marker) = entry.marker {
+        return Err(format!(
+            "Line {line} of {} is a {marker} line, which applies to every host it names. \
+             Edit the file by hand to change it.",
+            path.display()
+        ));
+    }
+    let failed = |e: std::io::Error| format!("Could not rewrite {}: {e}", path.display());
+    let target = std::fs::canonicalize(path).map_err(failed)?;
+    let permissions = std::fs::metadata(&target).map_err(failed)?.permissions();
+    let text = std::fs::read_to_string(&target).map_err(failed)?;
+    let kept: String = text
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(index, _)| index + 1 != line)
+        .map(|(_, text)| text)
+        .collect();
+
+    let beside = |suffix: &str| {
+        let mut name = target.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let backup = beside(".old");
+    write_new(&backup, &text, &permissions).map_err(failed)?;
+    // Written aside and renamed over, so a crash mid-write cannot leave half a file.
+    let staging = beside(".tmp");
+    write_new(&staging, &kept, &permissions).map_err(failed)?;
+    std::fs::rename(&staging, &target).map_err(failed)?;
+    Ok(backup)
+}
+
+/// Write `text` to a fresh file at `path` that never has wider permissions than `permissions`.
+fn write_new(path: &Path, text: &str, permissions: &std::fs::Permissions) -> std::io::Result<()> {
+    // Replaced rather than truncated: an existing file keeps its own mode, and a planted link
+    // would be followed.
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(permissions.mode());
+    }
+    let mut file = options.open(path)?;
+    file.write_all(text.as_bytes())?;
+    // The mode given at creation is narrowed by the umask; this makes it exactly the original.
+    file.set_permissions(permissions.clone())
 }
 
 /// `SHA256:...`, the form `ssh` prints and users compare against.
@@ -130,6 +492,14 @@ pub enum Rejection {
         algorithm: String,
         fingerprint: String,
     },
+    /// The key is marked `@revoked`. Refuse and explain.
+    Revoked {
+        host: String,
+        port: u16,
+        line: usize,
+        algorithm: String,
+        fingerprint: String,
+    },
 }
 
 impl Rejection {
@@ -157,13 +527,24 @@ impl Rejection {
                  can mean the connection is being intercepted. If you know the host was \
                  legitimately rekeyed, remove line {line} from known_hosts by hand."
             ),
+            Self::Revoked {
+                host,
+                port,
+                line,
+                algorithm,
+                fingerprint,
+            } => format!(
+                "REVOKED HOST KEY for {host}:{port}. The {algorithm} key the server offered \
+                 ({fingerprint}) is marked @revoked at line {line} of known_hosts. Do not \
+                 connect until whoever runs the host has replaced its key."
+            ),
         }
     }
 
     /// Whether the user may be offered a way through this.
     ///
-    /// Only an unknown host is promptable. A changed key is never click-throughable — that is
-    /// the entire point of recording it.
+    /// Only an unknown host is promptable. A changed or revoked key is never
+    /// click-throughable — that is the entire point of recording it.
     pub fn is_promptable(&self) -> bool {
         matches!(self, Self::Unknown { .. })
     }
@@ -328,11 +709,19 @@ mod tests {
             algorithm: "ssh-ed25519".into(),
             fingerprint: "SHA256:y".into(),
         };
+        let revoked = Rejection::Revoked {
+            host: "h".into(),
+            port: 22,
+            line: 4,
+            algorithm: "ssh-ed25519".into(),
+            fingerprint: "SHA256:z".into(),
+        };
         assert!(unknown.is_promptable());
         assert!(
             !changed.is_promptable(),
             "a changed host key must never be click-throughable"
         );
+        assert!(!revoked.is_promptable(), "nor a revoked one");
     }
 
     #[test]
@@ -351,6 +740,202 @@ mod tests {
         assert!(msg.to_lowercase().contains("intercept"));
     }
 
+    /// A marker line for `key`, as an infrastructure team would distribute it.
+    fn marker_line(marker: &str, patterns: &str, base64: &str) -> String {
+        format!("{marker} {patterns} ssh-ed25519 {base64}\n")
+    }
+
+    #[test]
+    fn host_patterns_match_like_openssh() {
+        let matches = |patterns, host, port| host_matches(patterns, &host_name(host, port));
+        assert!(matches("*.lab.example.com", "Sw1.LAB.example.com", 22));
+        assert!(matches("sw?.lab", "sw1.lab", 22));
+        assert!(!matches("sw?.lab", "sw10.lab", 22));
+        assert!(matches("a,*.lab", "gw.lab", 22));
+        assert!(!matches("*.lab,!gw.lab", "gw.lab", 22), "a negation wins");
+        assert!(!matches("!gw.lab", "other.lab", 22), "a negation alone matches nothing");
+        assert!(matches("[*.lab]:2222", "sw1.lab", 2222));
+        assert!(!matches("*.lab", "sw1.lab", 2222), "another port is another name");
+        assert!(matches("*", "sw1.lab", 2222));
+    }
+
+    /// Entries as `ssh-keygen -H` writes them, for a fixed salt of bytes 0 to 19, computed
+    /// independently of this module.
+    const HASHED: &str = "|1|AAECAwQFBgcICQoLDA0ODxAREhM=|nnUK16ANsXd3hL31YfAkGOluSjU=";
+    const HASHED_2222: &str = "|1|AAECAwQFBgcICQoLDA0ODxAREhM=|Wgcx+Fm+LmaWwC7rQ80eIf2uHe0=";
+
+    #[test]
+    fn hashed_entries_written_by_openssh_are_matched() {
+        let path = store("hashed");
+        let text = format!(
+            "{HASHED} ssh-ed25519 {ED25519_A}\n{HASHED_2222} ssh-ed25519 {ED25519_B}\n"
+        );
+        std::fs::write(&path, text).unwrap();
+        assert_eq!(check("example.com", 22, &key(ED25519_A), &path), Trust::Known);
+        assert_eq!(check("EXAMPLE.com", 22, &key(ED25519_A), &path), Trust::Known);
+        assert_eq!(check("example.com", 2222, &key(ED25519_B), &path), Trust::Known);
+        assert_eq!(
+            check("example.com", 2222, &key(ED25519_A), &path),
+            Trust::Changed { line: 2 }
+        );
+        assert_eq!(check("example.org", 22, &key(ED25519_A), &path), Trust::Unknown);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn learning_hashed_hides_the_name_and_still_matches() {
+        let path = store("learn_hashed");
+        learn_hashed("Example.com", 22, &key(ED25519_A), &path).unwrap();
+        learn_hashed("example.com", 2222, &key(ED25519_B), &path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("example"), "{text}");
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().all(|line| line.starts_with(HASH_MAGIC)), "{text}");
+        // Each entry gets its own salt, so equal names do not give themselves away.
+        assert_ne!(text.lines().next().unwrap()[..32], text.lines().nth(1).unwrap()[..32]);
+
+        assert_eq!(check("example.com", 22, &key(ED25519_A), &path), Trust::Known);
+        assert_eq!(check("example.com", 2222, &key(ED25519_B), &path), Trust::Known);
+        assert_eq!(check("example.com", 22, &key(ED25519_B), &path), Trust::Changed { line: 1 });
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn line_numbers_count_comments_and_blank_lines() {
+        let path = store("line_numbers");
+        let text = format!("# managed by infra\n\nexample.com ssh-ed25519 {ED25519_A}\n");
+        std::fs::write(&path, text).unwrap();
+        assert_eq!(check("example.com", 22, &key(ED25519_B), &path), Trust::Changed { line: 3 });
+        // And a file missing its final newline gets a line of its own appended.
+        std::fs::write(&path, format!("example.com ssh-ed25519 {ED25519_A}")).unwrap();
+        learn("other.com", 22, &key(ED25519_B), &path).unwrap();
+        assert_eq!(check("other.com", 22, &key(ED25519_B), &path), Trust::Known);
+        assert_eq!(check("example.com", 22, &key(ED25519_A), &path), Trust::Known);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_revoked_key_is_refused_even_when_listed() {
+        let path = store("revoked");
+        let text = format!(
+            "# distributed by infra\nsw1.lab ssh-ed25519 {ED25519_A}\n{}",
+            marker_line("@revoked", "*.lab", ED25519_A)
+        );
+        std::fs::write(&path, text).unwrap();
+
+        assert_eq!(check("sw1.lab", 22, &key(ED25519_A), &path), Trust::Revoked { line: 3 });
+        // The marker covers only the hosts it names, and only the key it names.
+        assert_eq!(check("sw1.elsewhere", 22, &key(ED25519_A), &path), Trust::Unknown);
+        assert_eq!(check("sw1.lab", 22, &key(ED25519_B), &path), Trust::Changed { line: 2 });
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_ca_key_is_not_a_host_key() {
+        let path = store("ca_not_host");
+        std::fs::write(&path, marker_line("@cert-authority", "*.lab", ED25519_A)).unwrap();
+        assert_eq!(check("sw1.lab", 22, &key(ED25519_A), &path), Trust::Unknown);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn listing_shows_every_entry_or_those_for_one_host() {
+        let path = store("list");
+        let text = format!(
+            "# fleet\nexample.com ssh-ed25519 {ED25519_A}\n{HASHED} ssh-ed25519 {ED25519_B}\n{}",
+            marker_line("@revoked", "*", ED25519_B)
+        );
+        std::fs::write(&path, text).unwrap();
+
+        let all = list(&path, None);
+        assert_eq!(all.iter().map(|l| l.line).collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(all[0].hosts, "example.com");
+        assert_eq!(all[0].algorithm, "ssh-ed25519");
+        assert_eq!(all[0].fingerprint, fingerprint(&key(ED25519_A)));
+        assert!(all[1].is_hashed());
+        assert_eq!(all[2].marker, Some("@revoked"));
+
+        let lines = |host, port| -> Vec<usize> {
+            list(&path, Some((host, port))).iter().map(|l| l.line).collect()
+        };
+        assert_eq!(lines("example.com", 22), [2, 3, 4]);
+        assert_eq!(lines("example.org", 22), [4]);
+        assert_eq!(lines("example.com", 2222), [4]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn removing_a_line_keeps_the_rest_and_a_backup() {
+        let path = store("remove");
+        let text = format!(
+            "# fleet\nexample.com ssh-ed25519 {ED25519_A}\nexample.org ssh-ed25519 {ED25519_B}\n"
+        );
+        std::fs::write(&path, &text).unwrap();
+
+        remove(&path, 2, &fingerprint(&key(ED25519_A))).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("# fleet\nexample.org ssh-ed25519 {ED25519_B}\n")
+        );
+        let backup = PathBuf::from(format!("{}.old", path.display()));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), text);
+        assert_eq!(check("example.com", 22, &key(ED25519_A), &path), Trust::Unknown);
+        assert_eq!(check("example.org", 22, &key(ED25519_B), &path), Trust::Known);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&backup);
+    }
+
+    #[test]
+    fn marker_lines_are_not_removed() {
+        let path = store("remove_marker");
+        let text = format!("@revoked example.com ssh-ed25519 {ED25519_A}\n");
+        std::fs::write(&path, &text).unwrap();
+        let error = remove(&path, 1, &fingerprint(&key(ED25519_A))).unwrap_err();
+        assert!(error.contains("@revoked line"), "{error}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn removing_through_a_link_keeps_the_link_and_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join("uniterm_kh_linked");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let real = dir.join("fleet_hosts");
+        let link = dir.join("known_hosts");
+        let text =
+            format!("example.com ssh-ed25519 {ED25519_A}\nexample.org ssh-ed25519 {ED25519_B}\n");
+        std::fs::write(&real, &text).unwrap();
+        std::fs::set_permissions(&real, std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        let backup = remove(&link, 1, &fingerprint(&key(ED25519_A))).unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(&real).unwrap(),
+            format!("example.org ssh-ed25519 {ED25519_B}\n")
+        );
+        assert_eq!(backup, dir.join("fleet_hosts.old"));
+        for file in [&real, &backup] {
+            let mode = std::fs::metadata(file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file.display());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_stale_listing_cannot_remove_the_wrong_line() {
+        let path = store("remove_stale");
+        std::fs::write(&path, format!("example.org ssh-ed25519 {ED25519_B}\n")).unwrap();
+        let error = remove(&path, 1, &fingerprint(&key(ED25519_A))).unwrap_err();
+        assert!(error.contains("Refresh"), "{error}");
+        assert!(remove(&path, 7, &fingerprint(&key(ED25519_B))).is_err());
+        assert_eq!(check("example.org", 22, &key(ED25519_B), &path), Trust::Known);
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn learning_creates_missing_parent_directories() {
         let dir = std::env::temp_dir().join("uniterm_kh_nested").join("deeper");
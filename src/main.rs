@@ -7,12 +7,21 @@
 )]
 
 mod app;
+mod cli;
+mod control;
 mod discovery;
+mod files;
+mod headless;
+mod hostkeys;
+mod keychain;
 mod knownhosts;
 mod persist;
+mod profiles;
 mod recents;
 mod session;
 mod settings;
+mod sharing;
+mod sshconfig;
 mod term;
 mod ui;
 
@@ -35,9 +44,35 @@ fn app_id(snap_instance: Option<std::ffi::OsString>) -> String {
 }
 
 fn main() -> eframe::Result {
+    let args = std::env::args_os()
+        .skip(1)
+        .map(|arg| {
+            arg.into_string()
+                .map_err(|arg| format!("{} is not valid text.", arg.to_string_lossy()))
+        })
+        .collect::<Result<Vec<_>, _>>();
+    let (open, headless) = match args.and_then(cli::parse) {
+        Ok(cli::Invocation::Run(open)) => (open, None),
+        Ok(cli::Invocation::Headless(headless)) => (Vec::new(), Some(headless)),
+        Ok(cli::Invocation::Help) => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(cli::Invocation::Version) => {
+            println!("UniTerm {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("UniTerm: {e}");
+            std::process::exit(2);
+        }
+    };
+
     // The Tauri build hard-coded `Level::TRACE`, which buried anything useful. Default to
-    // warnings and let `RUST_LOG` turn detail back on.
+    // warnings and let `RUST_LOG` turn detail back on. Standard error, because a headless run
+    // writes what it receives to standard output.
     tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(
             EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn,uniterm=info")),
         )
@@ -51,6 +86,17 @@ fn main() -> eframe::Result {
         .build()
         .expect("failed to start the tokio runtime");
     let handle = runtime.handle().clone();
+    let app_id = app_id(std::env::var_os("SNAP_INSTANCE_NAME"));
+
+    if let Some(headless) = headless {
+        // Where eframe keeps the saved state, for `--profile`; see `persist::load_file`.
+        let saved = eframe::storage_dir(&app_id).map(|dir| dir.join("app.ron"));
+        let code = headless::run(&handle, headless, saved.as_deref());
+        // Give the session task a moment to close the connection politely, but no longer: a
+        // blocking serial read cannot be interrupted.
+        runtime.shutdown_timeout(std::time::Duration::from_secs(1));
+        std::process::exit(code);
+    }
 
     // The default size is in logical points, so on a scaled display it can exceed the
     // physical screen — at 150% a 1600x1000 request is a 2400x1500 window, which pushes the
@@ -60,7 +106,7 @@ fn main() -> eframe::Result {
         .with_inner_size([1280.0, 820.0])
         .with_min_inner_size([720.0, 480.0])
         .with_clamp_size_to_monitor_size(true)
-        .with_app_id(app_id(std::env::var_os("SNAP_INSTANCE_NAME")))
+        .with_app_id(app_id)
         .with_title("UniTerm");
     if let Ok(icon) = eframe::icon_data::from_png_bytes(ICON_PNG) {
         viewport = viewport.with_icon(icon);
@@ -82,6 +128,7 @@ fn main() -> eframe::Result {
                 handle,
                 cc.storage,
                 &cc.egui_ctx,
+                open,
             )))
         }),
     )
@@ -20,14 +20,17 @@
 //! # What is deliberately not saved
 //!
 //! * **Passwords and key passphrases.** They are held in memory for the life of the process and
-//!   never reach disk. A restored SSH tab using password auth therefore cannot dial on its own.
+//!   never reach this file. A tab can opt into keeping its secret in the OS keychain
+//!   ([`crate::keychain`]), and only that choice is saved here; a restored SSH tab using
+//!   password auth without it cannot dial on its own.
 //! * **Scrollback contents.** Terminal output routinely contains secrets, and it can be
 //!   megabytes; writing it to disk on every close is a data-sensitivity decision the user has
 //!   not made.
 //! * **Live connection state.** Restored tabs come back defined but disconnected unless the
 //!   user opted a tab into auto-connect, for the reasons in [`may_auto_connect`].
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use egui_dock::DockState;
 use serde::{Deserialize, Serialize};
@@ -35,6 +38,7 @@ use tracing::warn;
 
 use crate::app::TabId;
 use crate::discovery::PortInfo;
+use crate::keychain;
 use crate::settings::{ConnectionKind, ConnectionSettings, DisplayMode, SendMode, SshAuth};
 
 /// Bumped when the schema changes incompatibly.
@@ -59,6 +63,9 @@ pub struct PersistedState {
     /// earlier build still loads — which is why the schema version did not need bumping.
     #[serde(default)]
     pub recents: Vec<crate::recents::Recent>,
+    /// The profile library. Defaulted like `recents`.
+    #[serde(default)]
+    pub profiles: Vec<crate::profiles::Profile>,
 }
 
 /// One tab's definition. Every field defaults so that adding one does not invalidate
@@ -91,6 +98,9 @@ pub struct PersistedTab {
     pub log_path: Option<PathBuf>,
     #[serde(default)]
     pub log_enabled: bool,
+    /// See [`crate::session::Session::profile`].
+    #[serde(default)]
+    pub profile: Option<u64>,
 }
 
 fn default_max_bytes() -> usize {
@@ -155,6 +165,35 @@ pub fn load(storage: &dyn eframe::Storage) -> Loaded {
     }
 }
 
+/// Read the saved state straight from eframe's file, for a run with no window and so no
+/// eframe to open it.
+///
+/// Read-only: an open window owns the file and would overwrite anything written here.
+pub fn load_file(path: &Path) -> Loaded {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Loaded::Fresh;
+    };
+    match ron::from_str::<HashMap<String, String>>(&text) {
+        Ok(values) => load(&ReadOnly(values)),
+        Err(e) => Loaded::Unreadable {
+            reason: format!("{} could not be read ({e}).", path.display()),
+            payload: text,
+        },
+    }
+}
+
+/// eframe's key-value file, as [`load`] expects to find it.
+struct ReadOnly(HashMap<String, String>);
+
+impl eframe::Storage for ReadOnly {
+    fn get_string(&self, key: &str) -> Option<String> {
+        self.0.get(key).cloned()
+    }
+    fn set_string(&mut self, _key: &str, _value: String) {}
+    fn remove_string(&mut self, _key: &str) {}
+    fn flush(&mut self) {}
+}
+
 /// Write the state, and set aside any payload that could not be read earlier.
 pub fn save(storage: &mut dyn eframe::Storage, state: &PersistedState, unreadable: Option<&str>) {
     if let Some(payload) = unreadable {
@@ -223,13 +262,34 @@ pub fn may_auto_connect(settings: &ConnectionSettings, ports: &[PortInfo]) -> Au
             }
         }
         ConnectionKind::Ssh => match settings.ssh.auth {
+            // The keychain has it, or the attempt says that it does not.
+            SshAuth::Password if keychain::enabled(&settings.ssh) => AutoConnect::Yes,
             // The password was never written to disk, so there is nothing to dial with.
             SshAuth::Password => AutoConnect::No(
                 "Passwords are not saved, so this tab needs its password before connecting."
                     .to_owned(),
             ),
             SshAuth::PublicKey => AutoConnect::Yes,
+            // The agent has the keys; if it is not running, the attempt says so.
+            SshAuth::Agent => AutoConnect::Yes,
         },
+        // Nothing secret and nothing local to check; whether the host answers is what the
+        // connect attempt itself finds out. A Telnet login, if any, is typed into the terminal.
+        ConnectionKind::Tcp | ConnectionKind::Telnet | ConnectionKind::Rfc2217 => AutoConnect::Yes,
+        // Binding a port needs nothing saved, and there is no peer to be missing.
+        ConnectionKind::Udp => AutoConnect::Yes,
+        // A socket file only exists while whatever serves it is running, so its absence is a
+        // reliable "not yet": a VM that has not been started.
+        ConnectionKind::UnixSocket => {
+            let path = &settings.unix_socket.path;
+            if path.exists() {
+                AutoConnect::Yes
+            } else {
+                AutoConnect::No(format!("{} does not exist.", path.display()))
+            }
+        }
+        // Starting a program touches nothing shared, and "On start" is opted into per tab.
+        ConnectionKind::Local | ConnectionKind::Pipe => AutoConnect::Yes,
     }
 }
 
@@ -238,7 +298,7 @@ mod tests {
     use super::*;
     use crate::discovery::PortKind;
     use eframe::Storage as _;
-    use crate::settings::{SerialSettings, SshSettings};
+    use crate::settings::{SerialSettings, SshSettings, TcpSettings, UnixSocketSettings};
     use std::collections::BTreeMap;
 
     /// In-memory stand-in for eframe's storage.
@@ -266,6 +326,25 @@ mod tests {
             next_id: 7,
             dock: DockState::new(vec![TabId(3), TabId(5)]),
             recents: Vec::new(),
+            profiles: vec![crate::profiles::Profile {
+                id: 4,
+                name: "Router console".into(),
+                folder: "Lab/Rack 2".into(),
+                notes: "Break with Ctrl+]".into(),
+                settings: ConnectionSettings {
+                    kind: ConnectionKind::Tcp,
+                    tcp: TcpSettings {
+                        host: "ts1".into(),
+                        port: 7002,
+                    },
+                    ..Default::default()
+                },
+                display_mode: DisplayMode::Ansi,
+                enter_crlf: true,
+                send_mode: SendMode::Ascii,
+                append_cr: true,
+                append_lf: false,
+            }],
             tabs: vec![
                 PersistedTab {
                     id: TabId(3),
@@ -278,6 +357,7 @@ mod tests {
                             ..Default::default()
                         },
                         ssh: SshSettings::default(),
+                        ..Default::default()
                     },
                     display_mode: DisplayMode::Hex,
                     max_bytes: 50_000,
@@ -290,6 +370,7 @@ mod tests {
                     append_lf: false,
                     log_path: Some(PathBuf::from("/tmp/x.log")),
                     log_enabled: true,
+                    profile: None,
                 },
                 PersistedTab {
                     id: TabId(5),
@@ -302,6 +383,7 @@ mod tests {
                             port: 2222,
                             ..Default::default()
                         },
+                        ..Default::default()
                     },
                     display_mode: DisplayMode::Ansi,
                     max_bytes: 200_000,
@@ -314,6 +396,7 @@ mod tests {
                     append_lf: true,
                     log_path: None,
                     log_enabled: false,
+                    profile: Some(4),
                 },
             ],
         }
@@ -371,6 +454,9 @@ mod tests {
         assert_eq!(ssh_tab.settings.ssh.host, "srv");
         assert_eq!(ssh_tab.settings.ssh.port, 2222);
         assert!(ssh_tab.auto_connect);
+        assert_eq!(ssh_tab.profile, Some(4));
+
+        assert_eq!(restored.profiles, original.profiles);
 
         // The layout came back with both tabs.
         let ids: Vec<_> = restored.dock.iter_all_tabs().map(|(_, id)| *id).collect();
@@ -378,6 +464,24 @@ mod tests {
         assert!(ids.contains(&TabId(3)) && ids.contains(&TabId(5)));
     }
 
+    #[test]
+    fn the_file_eframe_writes_can_be_read_without_eframe() {
+        let mut storage = MemoryStorage::default();
+        save(&mut storage, &sample_state(), None);
+        let path = std::env::temp_dir().join("uniterm_persist_app.ron");
+        let values: HashMap<_, _> = storage.values.into_iter().collect();
+        std::fs::write(&path, ron::to_string(&values).unwrap()).unwrap();
+        let Loaded::Restored(restored) = load_file(&path) else {
+            panic!("expected the state back");
+        };
+        assert_eq!(restored.profiles, sample_state().profiles);
+
+        std::fs::write(&path, "garbage").unwrap();
+        assert!(matches!(load_file(&path), Loaded::Unreadable { .. }));
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(load_file(&path), Loaded::Fresh));
+    }
+
     #[test]
     fn no_secret_fields_appear_in_the_payload() {
         // The persistence property that matters most. Checked by field name rather than by
@@ -509,6 +613,7 @@ mod tests {
                 ..Default::default()
             },
             ssh: SshSettings::default(),
+            ..Default::default()
         };
         assert_eq!(may_auto_connect(&settings, &[]), AutoConnect::No("COM3 is not attached.".into()));
         assert_eq!(
@@ -533,6 +638,7 @@ mod tests {
                 ..Default::default()
             },
             ssh: SshSettings::default(),
+            ..Default::default()
         };
         match may_auto_connect(&settings, &[port("COM3", "SN999")]) {
             AutoConnect::No(reason) => {
@@ -558,6 +664,7 @@ mod tests {
                 ..Default::default()
             },
             ssh: SshSettings::default(),
+            ..Default::default()
         };
         // Same adapter, new port number.
         assert_eq!(
@@ -583,6 +690,7 @@ mod tests {
                 auth: SshAuth::Password,
                 ..Default::default()
             },
+            ..Default::default()
         };
         match may_auto_connect(&settings, &[]) {
             AutoConnect::No(reason) => assert!(reason.contains("Passwords are not saved")),
@@ -590,6 +698,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ssh_password_tabs_auto_connect_with_the_keychain() {
+        let settings = ConnectionSettings {
+            kind: ConnectionKind::Ssh,
+            ssh: SshSettings {
+                host: "srv".into(),
+                user: "phil".into(),
+                auth: SshAuth::Password,
+                keychain: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = may_auto_connect(&settings, &[]) == AutoConnect::Yes;
+        assert_eq!(expected, keychain::AVAILABLE, "only where there is a keychain to ask");
+    }
+
     #[test]
     fn ssh_key_tabs_may_auto_connect() {
         let settings = ConnectionSettings {
@@ -601,8 +726,37 @@ mod tests {
                 auth: SshAuth::PublicKey,
                 ..Default::default()
             },
+            ..Default::default()
+        };
+        assert_eq!(may_auto_connect(&settings, &[]), AutoConnect::Yes);
+    }
+
+    #[test]
+    fn tcp_tabs_may_auto_connect() {
+        let settings = ConnectionSettings {
+            kind: ConnectionKind::Tcp,
+            tcp: TcpSettings {
+                host: "nport".into(),
+                port: 4001,
+            },
+            ..Default::default()
+        };
+        assert_eq!(may_auto_connect(&settings, &[]), AutoConnect::Yes);
+    }
+
+    #[test]
+    fn unix_socket_tabs_auto_connect_only_when_the_socket_exists() {
+        let path = std::env::temp_dir().join("uniterm_persist_socket");
+        let _ = std::fs::remove_file(&path);
+        let settings = ConnectionSettings {
+            kind: ConnectionKind::UnixSocket,
+            unix_socket: UnixSocketSettings { path: path.clone() },
+            ..Default::default()
         };
+        assert!(matches!(may_auto_connect(&settings, &[]), AutoConnect::No(_)));
+        std::fs::write(&path, b"").unwrap();
         assert_eq!(may_auto_connect(&settings, &[]), AutoConnect::Yes);
+        let _ = std::fs::remove_file(&path);
     }
 
     #[test]
@@ -0,0 +1,461 @@
+//! Named connection profiles.
+//!
+//! The deliberate counterpart to [`crate::recents`]: recents are whatever worked lately, capped
+//! and evicted; profiles are what the user chose to keep, under a name and in a folder, for as
+//! long as they want them. A bench of hundreds of devices lives here.
+//!
+//! A profile carries the connection and the way the tab was set up to talk to it — display
+//! mode, line ending and send options — so opening one gives back the tab as it was saved.
+//! Like recents, it holds [`ConnectionSettings`], so a password cannot end up in one.
+
+use serde::{Deserialize, Serialize};
+
+use crate::session::Session;
+use crate::settings::{ConnectionSettings, DisplayMode, SendMode};
+
+/// One saved connection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Stable across renames, so a tab opened from a profile can be saved back to it.
+    pub id: u64,
+    pub name: String,
+    /// Where it is filed. Blank is the top level. A path such as `Lab/Bench 3` is listed under
+    /// its full path.
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub settings: ConnectionSettings,
+    #[serde(default)]
+    pub display_mode: DisplayMode,
+    #[serde(default = "default_true")]
+    pub enter_crlf: bool,
+    #[serde(default)]
+    pub send_mode: SendMode,
+    #[serde(default)]
+    pub append_cr: bool,
+    #[serde(default)]
+    pub append_lf: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Profile {
+    /// A profile of what `session` is set up to do, not yet added to the library.
+    pub fn from_session(session: &Session, name: String, folder: String) -> Self {
+        let mut profile = Self {
+            id: 0,
+            name,
+            folder,
+            notes: String::new(),
+            settings: ConnectionSettings::default(),
+            display_mode: DisplayMode::default(),
+            enter_crlf: true,
+            send_mode: SendMode::default(),
+            append_cr: false,
+            append_lf: false,
+        };
+        profile.take_from(session);
+        profile
+    }
+
+    /// Replace the connection and tab options with `session`'s, keeping name, folder and notes.
+    pub fn take_from(&mut self, session: &Session) {
+        self.settings = session.settings.clone();
+        self.display_mode = session.display_mode;
+        self.enter_crlf = session.enter_crlf;
+        self.send_mode = session.send_mode;
+        self.append_cr = session.append_cr;
+        self.append_lf = session.append_lf;
+    }
+
+    /// Set up a tab the way this profile says.
+    pub fn apply(&self, session: &mut Session) {
+        session.settings = self.settings.clone();
+        session.display_mode = self.display_mode;
+        session.enter_crlf = self.enter_crlf;
+        session.send_mode = self.send_mode;
+        session.append_cr = self.append_cr;
+        session.append_lf = self.append_lf;
+        session.profile = Some(self.id);
+    }
+
+    /// Whether a search for `text` should show this. Matches name, folder, notes and the
+    /// connection's own description, case-insensitively.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.trim().to_lowercase();
+        text.is_empty()
+            || [&self.name, &self.folder, &self.notes, &self.settings.description()]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&text))
+    }
+}
+
+/// The library, sorted by folder and then name. Unbounded: nothing is ever dropped except by
+/// the user.
+#[derive(Clone, Debug, Default)]
+pub struct Profiles {
+    entries: Vec<Profile>,
+}
+
+impl Profiles {
+    pub fn from_entries(entries: Vec<Profile>) -> Self {
+        let mut profiles = Self { entries };
+        for index in 0..profiles.entries.len() {
+            // Ids are only compared, never interpreted, so repairing a clash is enough.
+            let id = profiles.entries[index].id;
+            if profiles.entries[..index].iter().any(|p| p.id == id) {
+                profiles.entries[index].id = profiles.next_id();
+            }
+            let folder = profiles.filed(&profiles.entries[index].folder);
+            profiles.entries[index].folder = folder;
+        }
+        profiles.sort();
+        profiles
+    }
+
+    pub fn entries(&self) -> &[Profile] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Profile> {
+        self.entries.iter().find(|p| p.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Profile> {
+        self.entries.iter_mut().find(|p| p.id == id)
+    }
+
+    /// The profile called `name`, or `folder/name` where names repeat across folders. Case is
+    /// ignored, as it is when names are kept unique.
+    pub fn find(&self, name: &str) -> Result<&Profile, String> {
+        let name = name.trim();
+        let full = |p: &Profile| match p.folder.as_str() {
+            "" => p.name.clone(),
+            folder => format!("{folder}/{}", p.name),
+        };
+        let by_path: Vec<&Profile> =
+            self.entries.iter().filter(|p| same(&full(p), name)).collect();
+        let found = match by_path.as_slice() {
+            [] => self.entries.iter().filter(|p| same(&p.name, name)).collect(),
+            _ => by_path,
+        };
+        match found.as_slice() {
+            [profile] => Ok(profile),
+            [] => Err(format!("There is no profile called \"{name}\".")),
+            several => Err(format!(
+                "Several profiles are called \"{name}\"; give the folder too: {}.",
+                several.iter().map(|p| full(p)).collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    /// Add `profile` under a fresh id, renamed if its folder already has one of that name.
+    pub fn add(&mut self, mut profile: Profile) -> u64 {
+        profile.id = self.next_id();
+        profile.folder = self.filed(&profile.folder);
+        profile.name = self.unique_name(&profile.folder, profile.name.trim(), None);
+        let id = profile.id;
+        self.entries.push(profile);
+        self.sort();
+        id
+    }
+
+    /// A copy of `id` beside it, as "name (copy)".
+    pub fn duplicate(&mut self, id: u64) -> Option<u64> {
+        let mut copy = self.get(id)?.clone();
+        copy.name = format!("{} (copy)", copy.name);
+        Some(self.add(copy))
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        self.entries.retain(|p| p.id != id);
+    }
+
+    /// Rename and refile `id`. A name its new folder already has gets a number added.
+    pub fn rename(&mut self, id: u64, name: &str, folder: &str) {
+        let folder = self.filed(folder.trim().trim_matches('/'));
+        let name = self.unique_name(&folder, name.trim(), Some(id));
+        if let Some(profile) = self.get_mut(id) {
+            profile.name = name;
+            profile.folder = folder;
+        }
+        self.sort();
+    }
+
+    /// Every folder in use, sorted, with the top level as `""`.
+    pub fn folders(&self) -> Vec<&str> {
+        let mut folders: Vec<&str> = self.entries.iter().map(|p| p.folder.as_str()).collect();
+        folders.dedup_by(|a, b| same(a, b));
+        folders
+    }
+
+    /// One past the highest id, or the lowest one free once the highest is `u64::MAX`.
+    fn next_id(&self) -> u64 {
+        match self.entries.iter().map(|p| p.id).max() {
+            None => 0,
+            Some(highest) => highest
+                .checked_add(1)
+                .or_else(|| (0..).find(|id| self.get(*id).is_none()))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// `folder` as the library already spells it, so "lab" files beside "Lab" rather than
+    /// making a second folder that only differs in case.
+    fn filed(&self, folder: &str) -> String {
+        self.entries
+            .iter()
+            .find(|p| same(&p.folder, folder))
+            .map_or(folder, |p| p.folder.as_str())
+            .to_owned()
+    }
+
+    /// `name`, or `name 2`, `name 3`... whichever `folder` does not have yet. `except` is the
+    /// profile being renamed, which does not clash with itself.
+    fn unique_name(&self, folder: &str, name: &str, except: Option<u64>) -> String {
+        let name = if name.is_empty() { "Untitled" } else { name };
+        let taken = |candidate: &str| {
+            self.entries.iter().any(|p| {
+                Some(p.id) != except && same(&p.folder, folder) && same(&p.name, candidate)
+            })
+        };
+        if !taken(name) {
+            return name.to_owned();
+        }
+        (2..)
+            .map(|n| format!("{name} {n}"))
+            .find(|candidate| !taken(candidate))
+            .unwrap_or_default()
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by_cached_key(|p| (p.folder.to_lowercase(), p.name.to_lowercase()));
+    }
+}
+
+/// Whether two names or folders are the same one. Case is ignored everywhere, as it is when
+/// the library is sorted.
+fn same(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// What the profile panel is in the middle of.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileView {
+    /// Shown profiles must match this.
+    pub filter: String,
+    /// The profile whose details are open for editing, with what is being typed. Applied on
+    /// "Save" rather than per keystroke, so a half-typed name never re-sorts the list under the
+    /// cursor.
+    pub editing: Option<Draft>,
+    /// A profile whose deletion is waiting to be confirmed.
+    pub confirm_delete: Option<u64>,
+    /// A file being imported, waiting for the user to settle its clashes.
+    pub import: Option<crate::sharing::Import>,
+    /// What the last export or import did.
+    pub notice: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Name, folder and notes as being edited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Draft {
+    pub id: u64,
+    pub name: String,
+    pub folder: String,
+    pub notes: String,
+}
+
+impl Draft {
+    pub fn of(profile: &Profile) -> Self {
+        Self {
+            id: profile.id,
+            name: profile.name.clone(),
+            folder: profile.folder.clone(),
+            notes: profile.notes.clone(),
+        }
+    }
+
+    /// Write the edits back.
+    pub fn apply(&self, profiles: &mut Profiles) {
+        profiles.rename(self.id, &self.name, &self.folder);
+        if let Some(profile) = profiles.get_mut(self.id) {
+            profile.notes = self.notes.trim_end().to_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{ConnectionKind, TcpSettings};
+
+    fn profile(name: &str, folder: &str) -> Profile {
+        Profile {
+            id: 0,
+            name: name.into(),
+            folder: folder.into(),
+            notes: String::new(),
+            settings: ConnectionSettings {
+                kind: ConnectionKind::Tcp,
+                tcp: TcpSettings {
+                    host: format!("{name}.lab"),
+                    port: 4001,
+                },
+                ..Default::default()
+            },
+            display_mode: DisplayMode::Hex,
+            enter_crlf: false,
+            send_mode: SendMode::Hex,
+            append_cr: true,
+            append_lf: false,
+        }
+    }
+
+    fn names(profiles: &Profiles) -> Vec<String> {
+        profiles
+            .entries()
+            .iter()
+            .map(|p| format!("{}/{}", p.folder, p.name))
+            .collect()
+    }
+
+    #[test]
+    fn profiles_are_filed_by_folder_then_name_with_no_cap() {
+        let mut profiles = Profiles::default();
+        for n in 0..crate::recents::MAX_ENTRIES * 3 {
+            profiles.add(profile(&format!("dut-{n:03}"), "Bench"));
+        }
+        profiles.add(profile("Gateway", ""));
+        profiles.add(profile("alpha", "Bench"));
+        assert_eq!(profiles.len(), crate::recents::MAX_ENTRIES * 3 + 2);
+        assert_eq!(names(&profiles)[..3], ["/Gateway", "Bench/alpha", "Bench/dut-000"]);
+        assert_eq!(profiles.folders(), ["", "Bench"]);
+    }
+
+    #[test]
+    fn names_are_unique_within_a_folder() {
+        let mut profiles = Profiles::default();
+        let first = profiles.add(profile("Scope", "Lab"));
+        profiles.add(profile("scope", "Lab"));
+        profiles.add(profile("Scope", "Other"));
+        assert_eq!(names(&profiles), ["Lab/Scope", "Lab/scope 2", "Other/Scope"]);
+
+        let copy = profiles.duplicate(first).unwrap();
+        assert_eq!(profiles.get(copy).unwrap().name, "Scope (copy)");
+        assert_ne!(copy, first);
+
+        // Renaming onto a name in use numbers it; renaming to its own name does not.
+        profiles.rename(copy, "Scope", "/Lab/");
+        assert_eq!(profiles.get(copy).unwrap().name, "Scope 3");
+        profiles.rename(first, "Scope", "Lab");
+        assert_eq!(profiles.get(first).unwrap().name, "Scope");
+    }
+
+    #[test]
+    fn a_profile_round_trips_through_a_tab() {
+        let mut profiles = Profiles::default();
+        let id = profiles.add(profile("Modem", "Bench"));
+        let mut session = Session::new(ConnectionSettings::default());
+        profiles.get(id).unwrap().apply(&mut session);
+        assert_eq!(session.profile, Some(id));
+        assert_eq!(session.settings.tcp.host, "Modem.lab");
+        assert_eq!(session.display_mode, DisplayMode::Hex);
+        assert!(!session.enter_crlf);
+        assert!(session.append_cr);
+
+        session.settings.tcp.port = 4002;
+        session.send_mode = SendMode::Ascii;
+        let saved = profiles.get_mut(id).unwrap();
+        saved.take_from(&session);
+        assert_eq!(saved.settings.tcp.port, 4002);
+        assert_eq!(saved.send_mode, SendMode::Ascii);
+        assert_eq!(saved.name, "Modem", "saving settings keeps the name");
+    }
+
+    #[test]
+    fn search_looks_at_name_folder_notes_and_address() {
+        let mut modem = profile("Modem", "Bench 3");
+        modem.notes = "Flaky after 5pm".into();
+        assert!(modem.matches(""));
+        assert!(modem.matches("modem"));
+        assert!(modem.matches("bench"));
+        assert!(modem.matches("FLAKY"));
+        assert!(modem.matches("modem.lab:4001"));
+        assert!(!modem.matches("router"));
+    }
+
+    #[test]
+    fn profiles_are_found_by_name_or_by_folder_and_name() {
+        let mut profiles = Profiles::default();
+        let top = profiles.add(profile("Gateway", ""));
+        let bench = profiles.add(profile("Scope", "Bench 3"));
+        profiles.add(profile("Scope", "Bench 4"));
+        assert_eq!(profiles.find("gateway").unwrap().id, top);
+        assert_eq!(profiles.find("bench 3/scope").unwrap().id, bench);
+        assert!(profiles.find("Scope").unwrap_err().contains("Bench 3/Scope, Bench 4/Scope"));
+        assert!(profiles.find("Router").unwrap_err().contains("no profile"));
+    }
+
+    #[test]
+    fn clashing_ids_in_a_saved_file_are_repaired() {
+        let profiles = Profiles::from_entries(vec![profile("a", ""), profile("b", "")]);
+        let ids: Vec<u64> = profiles.entries().iter().map(|p| p.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn folders_differing_only_in_case_are_one_folder() {
+        let mut profiles = Profiles::from_entries(vec![
+            Profile {
+                id: 1,
+                ..profile("Scope", "Lab")
+            },
+            Profile {
+                id: 2,
+                ..profile("Modem", "lab")
+            },
+        ]);
+        profiles.add(profile("Router", "LAB"));
+        profiles.add(profile("scope", "lab"));
+        profiles.add(profile("Zebra", ""));
+        assert_eq!(profiles.folders(), ["", "Lab"]);
+        assert_eq!(
+            names(&profiles),
+            ["/Zebra", "Lab/Modem", "Lab/Router", "Lab/Scope", "Lab/scope 2"]
+        );
+        assert_eq!(profiles.find("LAB/router").unwrap().name, "Router");
+    }
+
+    #[test]
+    fn ids_run_out_gracefully() {
+        let mut profiles = Profiles::from_entries(vec![
+            Profile {
+                id: u64::MAX,
+                ..profile("Last", "")
+            },
+            Profile {
+                id: u64::MAX,
+                ..profile("Clash", "")
+            },
+        ]);
+        let added = profiles.add(profile("Another", ""));
+        let mut ids: Vec<u64> = profiles.entries().iter().map(|p| p.id).collect();
+        ids.sort();
+        assert_eq!(ids, [0, 1, u64::MAX]);
+        assert_eq!(added, 1);
+    }
+}
@@ -91,6 +91,23 @@ impl Recents {
         self.evict();
     }
 
+    /// Take in an entry from elsewhere, such as an imported file. One for a connection already
+    /// listed updates it instead: the incoming settings, the later use, and a pin from either.
+    pub fn merge(&mut self, incoming: Recent) {
+        let identity = incoming.identity();
+        match self.entries.iter_mut().find(|e| e.identity() == identity) {
+            Some(existing) => {
+                existing.settings = incoming.settings;
+                existing.last_used = existing.last_used.max(incoming.last_used);
+                existing.uses = existing.uses.max(incoming.uses);
+                existing.pinned |= incoming.pinned;
+            }
+            None => self.entries.push(incoming),
+        }
+        self.sort();
+        self.evict();
+    }
+
     pub fn remove(&mut self, identity: &str) {
         self.entries.retain(|e| e.identity() != identity);
     }
@@ -179,7 +196,7 @@ fn plural(count: u64, unit: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::settings::{ConnectionKind, SerialSettings, SshAuth, SshSettings};
+    use crate::settings::{ConnectionKind, SerialSettings, SshAuth, SshSettings, TcpSettings};
 
     fn serial(name: &str, baud: u32) -> ConnectionSettings {
         ConnectionSettings {
@@ -190,6 +207,7 @@ mod tests {
                 ..Default::default()
             },
             ssh: SshSettings::default(),
+            ..Default::default()
         }
     }
 
@@ -203,6 +221,18 @@ mod tests {
                 port,
                 ..Default::default()
             },
+            ..Default::default()
+        }
+    }
+
+    fn tcp(host: &str, port: u16) -> ConnectionSettings {
+        ConnectionSettings {
+            kind: ConnectionKind::Tcp,
+            tcp: TcpSettings {
+                host: host.into(),
+                port,
+            },
+            ..Default::default()
         }
     }
 
@@ -259,6 +289,48 @@ mod tests {
         assert_eq!(recents.len(), 2);
     }
 
+    #[test]
+    fn tcp_identity_is_host_and_port() {
+        let mut recents = Recents::default();
+        recents.record(&tcp("nport", 4001), 1);
+        recents.record(&tcp("nport", 4001), 2);
+        recents.record(&tcp("nport", 4002), 3);
+        // Same host and port as an SSH entry is still a different thing to reopen.
+        recents.record(&ssh("", "nport", 4001), 4);
+        assert_eq!(recents.len(), 3);
+        assert_eq!(tcp("nport", 4001).identity(), "tcp:nport:4001");
+        assert_eq!(tcp("fe80::1", 23).identity(), "tcp:[fe80::1]:23");
+
+        // Telnet runs over TCP too, but is a different way to talk to the same port.
+        let mut telnet = tcp("nport", 4001);
+        telnet.kind = ConnectionKind::Telnet;
+        telnet.telnet.host = "nport".into();
+        telnet.telnet.port = 4001;
+        recents.record(&telnet, 5);
+        assert_eq!(recents.len(), 4);
+        assert_eq!(telnet.identity(), "telnet:nport:4001");
+    }
+
+    #[test]
+    fn rfc2217_identity_includes_the_line_parameters() {
+        // A remote serial port is remembered the way a local one is.
+        let remote = |baud| {
+            let mut settings = serial("", baud);
+            settings.kind = ConnectionKind::Rfc2217;
+            settings.rfc2217 = TcpSettings {
+                host: "nport".into(),
+                port: 4001,
+            };
+            settings
+        };
+        let mut recents = Recents::default();
+        recents.record(&remote(9_600), 1);
+        recents.record(&remote(115_200), 2);
+        recents.record(&tcp("nport", 4001), 3);
+        assert_eq!(recents.len(), 3);
+        assert_eq!(remote(9_600).description(), "nport:4001 · 9600 baud");
+    }
+
     #[test]
     fn entries_are_most_recent_first() {
         let mut recents = Recents::default();
@@ -427,5 +499,6 @@ mod tests {
         let mut s = ssh("phil", "srv", 2222);
         s.ssh.auth = SshAuth::PublicKey;
         assert_eq!(s.description(), "phil@srv:2222 · Private key");
+        assert_eq!(tcp("nport", 4001).description(), "nport:4001 · raw TCP");
     }
 }
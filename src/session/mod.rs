@@ -15,10 +15,21 @@
 //!   share one code path.
 
 pub mod log;
+pub mod pipe;
+#[cfg(unix)]
+pub mod pty;
+pub mod rfc2217;
+pub mod sftp;
 pub mod ssh;
 #[cfg(test)]
 mod ssh_tests;
+pub mod telnet;
+#[cfg(test)]
+mod test_support;
 pub mod transport;
+pub mod udp;
+#[cfg(unix)]
+pub mod unix;
 
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
@@ -26,12 +37,13 @@ use std::time::{Duration, Instant};
 
 use eframe::egui;
 use tokio::runtime::Handle;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tracing::debug;
 
+use crate::keychain::{self, Kind};
 use crate::knownhosts::{self, Rejection};
 use crate::settings::{
-    ConnectionKind, ConnectionSettings, DisplayMode, SendMode,
+    ConnectionKind, ConnectionSettings, DisplayMode, SendMode, SerialSettings, SshSettings,
 };
 use crate::term::emu::{self, Emulator, TermSize};
 use crate::term::input::InputModes;
@@ -48,6 +60,8 @@ enum Command {
     Send(Vec<u8>),
     SetLogging { enabled: bool, path: Option<PathBuf> },
     Resize { columns: u16, rows: u16 },
+    /// New line parameters for a remote serial port.
+    SetLine(SerialSettings),
 }
 
 /// Session-task to UI messages.
@@ -57,6 +71,10 @@ enum Event {
     Closed { reason: Option<String> },
     /// The host key was refused. Carries what to show the user.
     HostKey(Rejection),
+    /// The server asked a keyboard-interactive question; the handshake waits for the answers.
+    Challenge(ssh::Challenge),
+    /// The SSH connection is up, for the file panel to open channels on.
+    SshConnection(ssh::Connection),
     /// The serial device reappeared under a different port name.
     PortChanged(String),
     /// Non-fatal problem, e.g. the log file could not be written.
@@ -114,6 +132,13 @@ pub struct Session {
     pub last_error: Option<String>,
     /// A host key awaiting the user's decision.
     pub pending_host_key: Option<Rejection>,
+    /// The host key the last attempt refused outright, kept so the tab can point at the
+    /// known_hosts line to deal with.
+    pub refused_host_key: Option<Rejection>,
+    /// A keyboard-interactive challenge awaiting the user's answers.
+    pub pending_challenge: Option<ssh::Challenge>,
+    /// The answers being typed, one per prompt of `pending_challenge`.
+    pub challenge_answers: Vec<String>,
 
     /// Whether this session has ever been connected.
     ///
@@ -125,9 +150,14 @@ pub struct Session {
     /// Connect this tab automatically on startup. Off by default; the decision is guarded by
     /// [`crate::persist::may_auto_connect`].
     pub auto_connect: bool,
+    /// The profile this tab was opened from or saved as, which "Save to profile" updates.
+    pub profile: Option<u64>,
     /// Retry automatically after an unexpected drop. Off by default — a reconnect can be a
     /// visible action on the remote host, so it should be the user's choice.
     pub auto_reconnect: bool,
+    /// Put a divider in the scrollback at each reconnection. Off without a window, where the
+    /// scrollback is copied out as received and a divider would be bytes the device never sent.
+    pub mark_reconnects: bool,
     /// When the next automatic retry is due.
     retry_at: Option<Instant>,
     /// Index into [`RETRY_BACKOFF_SECONDS`] for the next automatic retry.
@@ -163,18 +193,37 @@ pub struct Session {
     /// is to type. Consumed by the tab viewer rather than acted on here, because focus only
     /// makes sense for a tab that is actually visible, and only the viewer knows that.
     pub focus_terminal: bool,
+    /// Open this tab's file panel, or bring it forward. Set from the controls strip and
+    /// consumed by the app, which owns the dock.
+    pub show_files: bool,
+    /// Open the host key manager on `refused_host_key`. Set and consumed like `show_files`.
+    pub show_host_keys: bool,
+    /// What the last "Forget" of this tab's keychain entry did, once it has done it.
+    pub keychain_notice: Option<String>,
+    forgetting: Option<oneshot::Receiver<String>>,
+    /// The live SSH connection, while there is one.
+    ssh_connection: Option<ssh::Connection>,
 
     commands: Option<mpsc::UnboundedSender<Command>>,
     events: Option<mpsc::UnboundedReceiver<Event>>,
+    /// Carries challenge answers to the handshake. Dropping it cancels authentication.
+    answers: Option<mpsc::UnboundedSender<Vec<String>>>,
 }
 
 impl Session {
     pub fn new(settings: ConnectionSettings) -> Self {
-        // SSH shells emit escape sequences constantly, so a styled-text view would be
-        // unreadable; serial devices are more often plain.
+        // SSH, Telnet and local shells emit escape sequences constantly, so a styled-text view
+        // would be unreadable; serial devices are more often plain.
         let display_mode = match settings.kind {
-            ConnectionKind::Ssh => DisplayMode::Ansi,
-            ConnectionKind::Serial => DisplayMode::default(),
+            ConnectionKind::Ssh | ConnectionKind::Telnet | ConnectionKind::Local => {
+                DisplayMode::Ansi
+            }
+            ConnectionKind::Serial
+            | ConnectionKind::Tcp
+            | ConnectionKind::Rfc2217
+            | ConnectionKind::Pipe
+            | ConnectionKind::Udp
+            | ConnectionKind::UnixSocket => DisplayMode::default(),
         };
         Self {
             settings,
@@ -182,10 +231,15 @@ impl Session {
             state: ConnectionState::Disconnected,
             last_error: None,
             pending_host_key: None,
+            refused_host_key: None,
+            pending_challenge: None,
+            challenge_answers: Vec::new(),
             has_connected: false,
             reconnect_count: 0,
             auto_connect: false,
+            profile: None,
             auto_reconnect: false,
+            mark_reconnects: true,
             retry_at: None,
             retry_attempt: 0,
             credentials: ssh::Credentials::default(),
@@ -203,8 +257,14 @@ impl Session {
             font_size: 13.0,
             controls_height: 150.0,
             focus_terminal: false,
+            show_files: false,
+            show_host_keys: false,
+            keychain_notice: None,
+            forgetting: None,
+            ssh_connection: None,
             commands: None,
             events: None,
+            answers: None,
         }
     }
 
@@ -244,6 +304,30 @@ impl Session {
         self.has_connected
     }
 
+    /// Delete whatever the keychain holds for this tab's connection. The outcome arrives in
+    /// `keychain_notice`.
+    pub fn forget_keychain(&mut self, rt: &Handle, ctx: &egui::Context) {
+        let (tx, rx) = oneshot::channel();
+        let ssh = self.settings.ssh.clone();
+        let ctx = ctx.clone();
+        rt.spawn(async move {
+            let notice = match keychain::forget(&ssh).await {
+                Ok(0) => format!("Nothing was saved for {}.", ssh.identity()),
+                Ok(_) => format!("Forgotten for {}.", ssh.identity()),
+                Err(e) => e,
+            };
+            let _ = tx.send(notice);
+            ctx.request_repaint();
+        });
+        self.forgetting = Some(rx);
+        self.keychain_notice = None;
+    }
+
+    /// The SSH connection, while this is a connected SSH tab.
+    pub fn ssh_connection(&self) -> Option<&ssh::Connection> {
+        self.ssh_connection.as_ref()
+    }
+
     /// How long until the next automatic retry, if one is scheduled.
     pub fn retry_countdown(&self) -> Option<Duration> {
         self.retry_at
@@ -288,6 +372,29 @@ impl Session {
         self.pending_host_key = None;
     }
 
+    /// Hand the typed answers to the waiting handshake.
+    pub fn answer_challenge(&mut self) {
+        if self.pending_challenge.take().is_none() {
+            return;
+        }
+        let answers = std::mem::take(&mut self.challenge_answers);
+        if let Some(sender) = &self.answers {
+            let _ = sender.send(answers);
+        }
+    }
+
+    /// Give up on authentication. The handshake sees its answer channel close and fails.
+    pub fn cancel_challenge(&mut self) {
+        self.clear_challenge();
+        self.disconnect();
+    }
+
+    fn clear_challenge(&mut self) {
+        self.pending_challenge = None;
+        self.challenge_answers.clear();
+        self.answers = None;
+    }
+
     fn connect_inner(
         &mut self,
         rt: &Handle,
@@ -301,7 +408,8 @@ impl Session {
             self.last_error = Some(message.to_owned());
             return;
         }
-        if self.settings.kind == ConnectionKind::Ssh {
+        // A blank field may be filled from the keychain, which only the session task can ask.
+        if self.settings.kind == ConnectionKind::Ssh && !keychain::enabled(&self.settings.ssh) {
             if let Err(message) = self.credentials.satisfies(self.settings.ssh.auth) {
                 self.last_error = Some(message.to_owned());
                 return;
@@ -310,6 +418,7 @@ impl Session {
 
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
         let (evt_tx, evt_rx) = mpsc::unbounded_channel();
+        let (answer_tx, answer_rx) = mpsc::unbounded_channel();
 
         let settings = self.settings.clone();
         let credentials = self.credentials.clone();
@@ -337,6 +446,7 @@ impl Session {
                 ctx,
                 cmd_rx,
                 evt_tx,
+                answer_rx,
                 log_path,
             )
             .await;
@@ -344,6 +454,7 @@ impl Session {
 
         self.commands = Some(cmd_tx);
         self.events = Some(evt_rx);
+        self.answers = Some(answer_tx);
         self.state = if self.has_connected {
             ConnectionState::Reconnecting
         } else {
@@ -351,6 +462,7 @@ impl Session {
         };
         self.last_error = None;
         self.pending_host_key = None;
+        self.refused_host_key = None;
         self.sent_size = None;
     }
 
@@ -407,6 +519,8 @@ impl Session {
     /// even while parked on a read, so there is no need to abort the task.
     pub fn disconnect(&mut self) {
         self.commands = None;
+        self.ssh_connection = None;
+        self.clear_challenge();
         self.state = ConnectionState::Disconnected;
     }
 
@@ -429,6 +543,7 @@ impl Session {
                     Event::Closed { reason } => {
                         self.state = ConnectionState::Disconnected;
                         self.commands = None;
+                        self.ssh_connection = None;
                         // `None` is a clean, user-requested close: not a drop, and not
                         // something to retry.
                         if reason.is_some() {
@@ -444,8 +559,14 @@ impl Session {
                         } else {
                             // A changed key is never promptable; show it as an error instead.
                             self.last_error = Some(rejection.message());
+                            self.refused_host_key = Some(rejection);
                         }
                     }
+                    Event::Challenge(challenge) => {
+                        self.challenge_answers = vec![String::new(); challenge.prompts.len()];
+                        self.pending_challenge = Some(challenge);
+                    }
+                    Event::SshConnection(connection) => self.ssh_connection = Some(connection),
                     Event::PortChanged(name) => {
                         // The device came back on a different port; follow it.
                         self.settings.serial.name = name;
@@ -454,12 +575,31 @@ impl Session {
                 }
             }
         }
+        if let Some(forgetting) = &mut self.forgetting {
+            match forgetting.try_recv() {
+                Ok(notice) => {
+                    self.keychain_notice = Some(notice);
+                    self.forgetting = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {}
+                Err(oneshot::error::TryRecvError::Closed) => self.forgetting = None,
+            }
+        }
+        // Whatever ended the handshake, nothing is waiting for answers any more.
+        if !matches!(
+            self.state,
+            ConnectionState::Connecting | ConnectionState::Reconnecting
+        ) {
+            self.clear_challenge();
+        }
 
         if connected {
             // Mark the seam only for a genuine reconnection, not the first connection.
             if self.has_connected {
                 self.reconnect_count += 1;
-                self.mark_reconnected();
+                if self.mark_reconnects {
+                    self.mark_reconnected();
+                }
             }
             self.has_connected = true;
             self.retry_attempt = 0;
@@ -514,6 +654,19 @@ impl Session {
         }
     }
 
+    /// Push the current line parameters to a running remote serial port.
+    ///
+    /// Only RFC 2217 can change them in flight; a local port's widgets are locked while it is
+    /// open.
+    pub fn apply_line_settings(&mut self) {
+        if self.settings.kind != ConnectionKind::Rfc2217 {
+            return;
+        }
+        if let Some(commands) = self.commands.as_ref() {
+            let _ = commands.send(Command::SetLine(self.settings.serial.clone()));
+        }
+    }
+
     pub fn set_max_bytes(&mut self, max_bytes: usize) {
         self.max_bytes = max_bytes;
         if let Ok(mut buffer) = self.buffer.lock() {
@@ -662,6 +815,42 @@ async fn resolve_serial_port(settings: &crate::settings::SerialSettings) -> Opti
     .flatten()
 }
 
+/// The secrets to connect `ssh` with: those typed, with a blank one filled from the keychain.
+/// Also returns a typed secret the keychain does not hold yet, to be saved after it has worked
+/// rather than now, so a mistyped password is never kept.
+async fn from_keychain(
+    ssh: &SshSettings,
+    mut credentials: ssh::Credentials,
+    events: &mpsc::UnboundedSender<Event>,
+) -> Result<(ssh::Credentials, Option<(Kind, String)>), String> {
+    let Some(kind) = Kind::wanted(ssh.auth) else {
+        return Ok((credentials, None));
+    };
+    let typed = kind.slot(&mut credentials);
+    let mut unsaved = None;
+    match keychain::recall(ssh, kind).await {
+        Ok(saved) if typed.is_empty() => *typed = saved.unwrap_or_default(),
+        Ok(saved) if saved.as_deref() != Some(typed.as_str()) => {
+            unsaved = Some((kind, typed.clone()));
+        }
+        Ok(_) => {}
+        // Without a password there is nothing to try, but a blank passphrase is an unencrypted
+        // key, and a typed secret works without the keychain; both just say why it was not used.
+        Err(e) if typed.is_empty() && kind == Kind::Password => return Err(e),
+        Err(e) => {
+            let _ = events.send(Event::Warning(e));
+        }
+    }
+    if credentials.satisfies(ssh.auth).is_err() {
+        return Err(format!(
+            "No {} is saved in the keychain for {} yet. Enter it first.",
+            kind.label(),
+            ssh.identity()
+        ));
+    }
+    Ok((credentials, unsaved))
+}
+
 /// The session task: open the transport, then pump it until told to stop.
 #[allow(clippy::too_many_arguments)]
 async fn run(
@@ -673,9 +862,12 @@ async fn run(
     ctx: egui::Context,
     mut commands: mpsc::UnboundedReceiver<Command>,
     events: mpsc::UnboundedSender<Event>,
+    answers: mpsc::UnboundedReceiver<Vec<String>>,
     log_path: Option<PathBuf>,
 ) {
     let label = settings.label();
+    // A typed secret the keychain should have, saved once it has been shown to work.
+    let mut unsaved = None;
 
     // ---- open ----
     let transport = match settings.kind {
@@ -699,12 +891,7 @@ async fn run(
             transport::open_serial(&serial).map_err(|e| (e, None))
         }
         ConnectionKind::Ssh => {
-            let known_hosts = match settings
-                .ssh
-                .known_hosts
-                .clone()
-                .or_else(knownhosts::default_path)
-            {
+            let known_hosts = match knownhosts::path_for(&settings.ssh) {
                 Some(path) => path,
                 None => {
                     let _ = events.send(Event::Closed {
@@ -716,11 +903,40 @@ async fn run(
                     return;
                 }
             };
+            let credentials = if keychain::enabled(&settings.ssh) {
+                match from_keychain(&settings.ssh, credentials, &events).await {
+                    Ok((credentials, typed)) => {
+                        unsaved = typed;
+                        credentials
+                    }
+                    Err(message) => {
+                        let _ = events.send(Event::Closed {
+                            reason: Some(message),
+                        });
+                        ctx.request_repaint();
+                        return;
+                    }
+                }
+            } else {
+                credentials
+            };
+            let prompter = {
+                let events = events.clone();
+                let ctx = ctx.clone();
+                ssh::Prompter::new(
+                    move |challenge| {
+                        let _ = events.send(Event::Challenge(challenge));
+                        ctx.request_repaint();
+                    },
+                    answers,
+                )
+            };
             ssh::connect(
                 settings.ssh.clone(),
                 credentials,
                 approved_fingerprint,
                 known_hosts,
+                prompter,
                 size.0,
                 size.1,
             )
@@ -731,6 +947,44 @@ async fn run(
                 other => (other.message(), None),
             })
         }
+        ConnectionKind::Tcp => transport::open_tcp(&settings.tcp)
+            .await
+            .map_err(|e| (e, None)),
+        ConnectionKind::Telnet => telnet::connect(&settings.telnet, size.0, size.1)
+            .await
+            .map(Transport::Telnet)
+            .map_err(|e| (e, None)),
+        ConnectionKind::Rfc2217 => rfc2217::connect(&settings.rfc2217, &settings.serial)
+            .await
+            .map(Transport::Telnet)
+            .map_err(|e| (e, None)),
+        #[cfg(unix)]
+        ConnectionKind::Local => pty::spawn(&settings.local, size.0, size.1)
+            .map(Transport::Local)
+            .map_err(|e| (e, None)),
+        #[cfg(not(unix))]
+        ConnectionKind::Local => Err((
+            "Local shells need a Unix pseudo-terminal, which this platform does not have."
+                .to_owned(),
+            None,
+        )),
+        ConnectionKind::Pipe => pipe::spawn(&settings.pipe)
+            .map(Transport::Pipe)
+            .map_err(|e| (e, None)),
+        ConnectionKind::Udp => udp::open(&settings.udp)
+            .await
+            .map(Transport::Udp)
+            .map_err(|e| (e, None)),
+        #[cfg(unix)]
+        ConnectionKind::UnixSocket => unix::connect(&settings.unix_socket)
+            .await
+            .map(Transport::UnixSocket)
+            .map_err(|e| (e, None)),
+        #[cfg(not(unix))]
+        ConnectionKind::UnixSocket => Err((
+            "Unix sockets are not supported on this platform.".to_owned(),
+            None,
+        )),
     };
 
     let mut transport = match transport {
@@ -752,6 +1006,20 @@ async fn run(
     };
 
     let _ = events.send(Event::Connected);
+    if let Transport::Ssh(ssh) = &transport {
+        let _ = events.send(Event::SshConnection(ssh.connection()));
+    }
+    for warning in transport.take_warnings() {
+        let _ = events.send(Event::Warning(warning));
+    }
+    if let Some((kind, secret)) = unsaved {
+        if let Err(e) = keychain::remember(&settings.ssh, kind, &secret).await {
+            let _ = events.send(Event::Warning(format!(
+                "Connected, but the {} was not saved: {e}",
+                kind.label()
+            )));
+        }
+    }
     ctx.request_repaint();
     debug!("session open: {label}");
 
@@ -772,7 +1040,11 @@ async fn run(
                         continue;
                     }
                     if let Ok(mut buffer) = buffer.lock() {
-                        buffer.append(&data);
+                        if transport.is_datagram() {
+                            buffer.append_packet(&data);
+                        } else {
+                            buffer.append(&data);
+                        }
                     }
                     if let Some(warning) = logger.write(&data).await {
                         let _ = events.send(Event::Warning(warning));
@@ -785,17 +1057,26 @@ async fn run(
             command = commands.recv() => match command {
                 // Sender dropped: the user asked to disconnect.
                 None => break None,
-                Some(Command::Send(bytes)) => {
-                    if let Err(e) = transport.send(&bytes).await {
-                        break Some(e);
+                Some(Command::Send(bytes)) => match transport.send(&bytes).await {
+                    Ok(()) => {}
+                    // A datagram that cannot go out is lost on its own; the socket is fine for
+                    // the next one, so this is not worth ending the session over.
+                    Err(e) if transport.is_datagram() => {
+                        let _ = events.send(Event::Warning(e));
                     }
-                }
+                    Err(e) => break Some(e),
+                },
                 Some(Command::Resize { columns, rows }) => {
                     if let Err(e) = transport.resize(columns, rows).await {
                         // A failed resize is not worth dropping the connection over.
                         let _ = events.send(Event::Warning(e));
                     }
                 }
+                Some(Command::SetLine(line)) => {
+                    if let Err(e) = transport.set_line(&line).await {
+                        break Some(e);
+                    }
+                }
                 Some(Command::SetLogging { enabled, path }) => {
                     logger.flush().await;
                     let (new_logger, warning) =
@@ -835,6 +1116,7 @@ mod tests {
                 ..Default::default()
             },
             ssh: SshSettings::default(),
+            ..Default::default()
         })
     }
 
@@ -847,6 +1129,7 @@ mod tests {
                 user: "phil".into(),
                 ..Default::default()
             },
+            ..Default::default()
         })
     }
 
@@ -856,6 +1139,11 @@ mod tests {
         // unreadable.
         assert_eq!(ssh_session().display_mode, DisplayMode::Ansi);
         assert_eq!(serial_session().display_mode, DisplayMode::Ascii);
+        let telnet = Session::new(ConnectionSettings {
+            kind: ConnectionKind::Telnet,
+            ..Default::default()
+        });
+        assert_eq!(telnet.display_mode, DisplayMode::Ansi);
     }
 
     #[test]
@@ -952,6 +1240,13 @@ mod tests {
         let encoded = serde_json::to_string(&session.settings).expect("settings serialise");
         assert!(!encoded.contains("sentinel-pw-9c3f"), "password leaked: {encoded}");
         assert!(!encoded.contains("sentinel-pp-4a71"), "passphrase leaked: {encoded}");
+
+        // Opting into the keychain records the choice, and still not the secret.
+        session.settings.ssh.keychain = true;
+        let encoded = serde_json::to_string(&session.settings).expect("settings serialise");
+        assert!(encoded.contains("\"keychain\":true"), "{encoded}");
+        assert!(!encoded.contains("sentinel-pw-9c3f"), "password leaked: {encoded}");
+        assert!(!encoded.contains("sentinel-pp-4a71"), "passphrase leaked: {encoded}");
     }
 
     #[test]
@@ -1059,7 +1354,7 @@ mod tests {
                     }
                     Event::PortChanged(name) => session.settings.serial.name = name,
                     Event::Warning(m) => session.last_error = Some(m),
-                    Event::HostKey(_) => {}
+                    Event::HostKey(_) | Event::Challenge(_) | Event::SshConnection(_) => {}
                 }
             }
         }
@@ -0,0 +1,243 @@
+//! Subprocess transport.
+//!
+//! Runs a command with its stdin and stdout as the link, and no terminal in between: an escape
+//! hatch for any link UniTerm does not speak natively, by way of `socat`, a vendor tool or a
+//! protocol decoder. stderr is shown too, interleaved as it arrives, since a bridge that fails
+//! usually says why there.
+//!
+//! Without a PTY the program sees pipes, not a terminal, so there is no window size to report
+//! and no line discipline: bytes go through exactly as sent.
+
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+
+use super::transport::{exit_reason, Exited, Incoming, READ_BUFFER};
+use crate::settings::ProcessSettings;
+
+/// How long a command gets to exit on its own once its stdin is closed.
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// A running command.
+pub struct PipeTransport {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+    buffer: Vec<u8>,
+    error_buffer: Vec<u8>,
+    exited: Exited,
+}
+
+impl PipeTransport {
+    pub async fn recv(&mut self) -> Incoming {
+        while self.stdout.is_some() || self.stderr.is_some() {
+            tokio::select! {
+                read = read_some(&mut self.stdout, &mut self.buffer) => match read {
+                    Ok(0) | Err(_) => self.stdout = None,
+                    Ok(count) => return Incoming::Data(self.buffer[..count].to_vec()),
+                },
+                read = read_some(&mut self.stderr, &mut self.error_buffer) => match read {
+                    Ok(0) | Err(_) => self.stderr = None,
+                    Ok(count) => return Incoming::Data(self.error_buffer[..count].to_vec()),
+                },
+            }
+        }
+        // Both streams are done, which almost always means the process is too.
+        match self.child.wait().await {
+            Ok(status) => Incoming::Closed(Some(exit_reason("The command", status))),
+            Err(e) => Incoming::Closed(Some(format!("The command ended: {e}"))),
+        }
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        let Some(stdin) = self.stdin.as_mut() else {
+            return Err("The command's input is closed.".to_owned());
+        };
+        // Flushed per write: a bridge is interactive, so nothing may sit in a buffer.
+        let result = match stdin.write_all(data).await {
+            Ok(()) => stdin.flush().await,
+            Err(e) => Err(e),
+        };
+        result.map_err(|e| match e.kind() {
+            io::ErrorKind::BrokenPipe => "The command stopped reading its input.".to_owned(),
+            _ => format!("Write failed: {e}"),
+        })
+    }
+
+    /// Notices the command exiting when something it started still holds the pipes open.
+    /// An ordinary exit is left to `recv`, which reports it once both streams are drained.
+    pub fn check_alive(&mut self) -> Option<String> {
+        self.exited.check(&mut self.child, "The command")
+    }
+
+    /// Close stdin, which is how most filters know to finish, and kill the command if it does
+    /// not.
+    pub async fn close(mut self) {
+        drop(self.stdin.take());
+        if tokio::time::timeout(CLOSE_GRACE, self.child.wait())
+            .await
+            .is_err()
+        {
+            let _ = self.child.start_kill();
+            let _ = self.child.wait().await;
+        }
+    }
+}
+
+/// Read from a stream that may already have ended; an ended one never completes, so the other
+/// arm of the `select!` is left to finish.
+async fn read_some<R: AsyncRead + Unpin>(
+    stream: &mut Option<R>,
+    buffer: &mut [u8],
+) -> io::Result<usize> {
+    match stream {
+        Some(stream) => stream.read(buffer).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// A command line run by the platform shell, so quoting, pipes and redirection work as typed.
+pub(super) fn shell_command(line: &str) -> Command {
+    #[cfg(windows)]
+    {
+        // Passed through untouched: cmd.exe does its own parsing, and Rust's argument quoting
+        // would double-escape it.
+        let mut command = Command::new("cmd");
+        command.arg("/C").raw_arg(line);
+        // No console window flashing up behind a GUI application.
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+        command
+    }
+    #[cfg(not(windows))]
+    {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(line);
+        command
+    }
+}
+
+/// Start the command.
+pub fn spawn(settings: &ProcessSettings) -> Result<PipeTransport, String> {
+    let line = settings.command.trim();
+    let mut child = shell_command(line)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Could not run `{line}`: {e}"))?;
+    Ok(PipeTransport {
+        stdin: child.stdin.take(),
+        stdout: child.stdout.take(),
+        stderr: child.stderr.take(),
+        child,
+        buffer: vec![0; READ_BUFFER],
+        error_buffer: vec![0; READ_BUFFER],
+        exited: Exited::default(),
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> ProcessSettings {
+        ProcessSettings {
+            command: line.to_owned(),
+        }
+    }
+
+    /// Everything the command writes, and why it ended.
+    async fn run_to_end(pipe: &mut PipeTransport) -> (Vec<u8>, Option<String>) {
+        let mut output = Vec::new();
+        loop {
+            let next = tokio::time::timeout(Duration::from_secs(10), pipe.recv())
+                .await
+                .expect("timed out waiting for the command");
+            match next {
+                Incoming::Data(data) => output.extend(data),
+                Incoming::Closed(reason) => return (output, reason),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn bytes_pass_through_unchanged() {
+        // `head -c` ends the command once it has echoed everything, so the test has an end.
+        let mut pipe = spawn(&command("head -c 6")).unwrap();
+        // No terminal in between: a bare CR, a NUL and a 0xFF all survive.
+        pipe.send(&[b'\r', 0, 0xff, b'a', b'\n', 0x1b]).await.unwrap();
+        let (output, reason) = run_to_end(&mut pipe).await;
+        assert_eq!(output, [b'\r', 0, 0xff, b'a', b'\n', 0x1b]);
+        assert_eq!(reason.as_deref(), Some("The command exited with status 0."));
+    }
+
+    #[tokio::test]
+    async fn stderr_is_shown_and_the_exit_status_reported() {
+        let mut pipe = spawn(&command("echo out; echo err >&2; exit 4")).unwrap();
+        let (output, reason) = run_to_end(&mut pipe).await;
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.contains("out\n") && text.contains("err\n"), "{text:?}");
+        assert_eq!(reason.as_deref(), Some("The command exited with status 4."));
+    }
+
+    #[tokio::test]
+    async fn a_missing_program_is_reported_through_its_status() {
+        let mut pipe = spawn(&command("/nonexistent/uniterm-test")).unwrap();
+        let (output, reason) = run_to_end(&mut pipe).await;
+        assert!(!output.is_empty(), "the shell says what went wrong on stderr");
+        assert_eq!(reason.as_deref(), Some("The command exited with status 127."));
+    }
+
+    #[tokio::test]
+    async fn output_written_just_before_exiting_is_read_first() {
+        let mut pipe = spawn(&command("echo last words; exit 3")).unwrap();
+        while pipe.child.try_wait().unwrap().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // Exited, with its output still unread: the liveness tick leaves it to `recv`.
+        assert_eq!(pipe.check_alive(), None);
+        let (output, reason) = run_to_end(&mut pipe).await;
+        assert_eq!(output, b"last words\n");
+        assert_eq!(reason.as_deref(), Some("The command exited with status 3."));
+    }
+
+    #[tokio::test]
+    async fn an_exit_is_noticed_while_a_background_job_holds_the_output() {
+        let mut pipe = spawn(&command("sleep 5 & echo started")).unwrap();
+        let started = std::time::Instant::now();
+        let reason = loop {
+            if let Some(reason) = pipe.check_alive() {
+                break reason;
+            }
+            assert!(started.elapsed() < Duration::from_secs(4), "the exit went unnoticed");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        assert_eq!(reason, "The command exited with status 0.");
+        assert!(started.elapsed() >= crate::session::transport::ORPHAN_GRACE);
+    }
+
+    #[tokio::test]
+    async fn closing_ends_a_filter_through_end_of_input() {
+        let pipe = spawn(&command("cat")).unwrap();
+        let started = std::time::Instant::now();
+        pipe.close().await;
+        assert!(
+            started.elapsed() < CLOSE_GRACE,
+            "cat should finish on EOF rather than wait to be killed"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_command_that_ignores_its_input_is_killed_on_close() {
+        let pipe = spawn(&command("sleep 30")).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), pipe.close())
+            .await
+            .expect("close must not wait for the command to finish on its own");
+    }
+}
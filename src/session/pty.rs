@@ -0,0 +1,312 @@
+//! Local shell transport.
+//!
+//! Runs a program on a pseudo-terminal, so to it UniTerm is a terminal like any other: it gets
+//! a controlling TTY, job control works, and a resize reaches it as `SIGWINCH` via
+//! `TIOCSWINSZ` the way SSH's `window_change` reaches a remote shell.
+//!
+//! Unix only. Windows has ConPTY, which is a different API with different semantics, and
+//! nothing here would carry over.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::unix::AsyncFd;
+use tokio::process::{Child, Command};
+
+use super::pipe::shell_command;
+use super::transport::{exit_reason, Exited, Incoming, READ_BUFFER};
+use crate::settings::ProcessSettings;
+
+/// `TERM` for the child. The emulator implements xterm's 256-colour set, as for SSH.
+const TERM: &str = "xterm-256color";
+
+/// How long a closed shell gets to act on `SIGHUP` before it is killed.
+const HANGUP_GRACE: Duration = Duration::from_secs(1);
+
+/// A program running on a local pseudo-terminal.
+pub struct PtyTransport {
+    master: AsyncFd<OwnedFd>,
+    child: Child,
+    buffer: Vec<u8>,
+    exited: Exited,
+}
+
+impl PtyTransport {
+    pub async fn recv(&mut self) -> Incoming {
+        let buffer = &mut self.buffer;
+        loop {
+            let mut guard = match self.master.readable().await {
+                Ok(guard) => guard,
+                Err(e) => return Incoming::Closed(Some(format!("Read failed: {e}"))),
+            };
+            match guard.try_io(|fd| read(fd.as_raw_fd(), buffer)) {
+                Ok(Ok(0)) => break,
+                Ok(Ok(count)) => return Incoming::Data(buffer[..count].to_vec()),
+                // Linux reports the last slave closing as EIO rather than end-of-file.
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => break,
+                Ok(Err(e)) => return Incoming::Closed(Some(format!("Read failed: {e}"))),
+                Err(_would_block) => continue,
+            }
+        }
+        // The last slave closing is usually the program exiting, but it may have closed its
+        // terminal and carried on; that is reported rather than waited out.
+        match tokio::time::timeout(HANGUP_GRACE, self.child.wait()).await {
+            Ok(Ok(status)) => Incoming::Closed(Some(exit_reason("The program", status))),
+            Ok(Err(e)) => Incoming::Closed(Some(format!("The program ended: {e}"))),
+            Err(_) => Incoming::Closed(Some("The program closed its terminal.".to_owned())),
+        }
+    }
+
+    pub async fn send(&mut self, mut data: &[u8]) -> Result<(), String> {
+        while !data.is_empty() {
+            let mut guard = self
+                .master
+                .writable()
+                .await
+                .map_err(|e| format!("Write failed: {e}"))?;
+            match guard.try_io(|fd| write(fd.as_raw_fd(), data)) {
+                Ok(Ok(count)) => data = &data[count..],
+                Ok(Err(e)) => return Err(format!("Write failed: {e}")),
+                Err(_would_block) => continue,
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the window size. The kernel signals the foreground process group itself.
+    pub fn resize(&mut self, columns: u16, rows: u16) -> Result<(), String> {
+        let size = winsize(columns, rows);
+        // SAFETY: the fd is open for as long as `self.master` is, and `size` outlives the call.
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ as _, &size) } < 0 {
+            return Err(format!(
+                "Resizing the terminal failed: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    /// The program exiting is noticed here when something it started still holds the
+    /// terminal open, which would otherwise keep the read side alive. An ordinary exit is left
+    /// to `recv`, which sees the terminal close once its output is drained.
+    pub fn check_alive(&mut self) -> Option<String> {
+        self.exited.check(&mut self.child, "The program")
+    }
+
+    /// Hang up: closing the master sends `SIGHUP` to the session, which is what closing a
+    /// terminal window does. Anything still running after a moment is killed.
+    pub async fn close(self) {
+        let Self { master, mut child, .. } = self;
+        drop(master);
+        if tokio::time::timeout(HANGUP_GRACE, child.wait()).await.is_err() {
+            let _ = child.start_kill();
+            let _ = child.wait().await;
+        }
+    }
+}
+
+/// Start `settings.command` (or the user's shell) on a new pseudo-terminal of the given size.
+pub fn spawn(settings: &ProcessSettings, columns: u16, rows: u16) -> Result<PtyTransport, String> {
+    let (master, slave) = open_pty(columns, rows)
+        .map_err(|e| format!("Could not allocate a pseudo-terminal: {e}"))?;
+
+    let mut command = match settings.command.trim() {
+        "" => Command::new(std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned())),
+        line => shell_command(line),
+    };
+    let stdio = |fd: &OwnedFd| {
+        fd.try_clone()
+            .map(Stdio::from)
+            .map_err(|e| format!("Could not set up the terminal: {e}"))
+    };
+    command
+        .stdin(stdio(&slave)?)
+        .stdout(stdio(&slave)?)
+        .stderr(stdio(&slave)?)
+        .env("TERM", TERM)
+        .kill_on_drop(true);
+    // SAFETY: only async-signal-safe calls between fork and exec.
+    unsafe {
+        command.pre_exec(|| {
+            // A new session, with the terminal as its controlling TTY, so job control and
+            // Ctrl+C reach the program rather than UniTerm.
+            if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let child = command.spawn().map_err(|e| match settings.command.trim() {
+        "" => format!("Could not start the shell: {e}"),
+        line => format!("Could not run `{line}`: {e}"),
+    })?;
+    // The child holds its own copies; keeping this one would stop the read side from ever
+    // seeing the program exit.
+    drop(slave);
+
+    let master = AsyncFd::new(master).map_err(|e| format!("Could not watch the terminal: {e}"))?;
+    Ok(PtyTransport {
+        master,
+        child,
+        buffer: vec![0; READ_BUFFER],
+        exited: Exited::default(),
+    })
+}
+
+fn winsize(columns: u16, rows: u16) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+/// Allocate a pseudo-terminal pair. The master is non-blocking for the reactor; both ends are
+/// close-on-exec so no other child inherits them.
+fn open_pty(columns: u16, rows: u16) -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut master: RawFd = -1;
+    let mut slave: RawFd = -1;
+    let mut size = winsize(columns, rows);
+    // SAFETY: the out-pointers are valid for writes, and a null name and termios are allowed.
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            // `*const` on Linux and `*mut` on macOS; a raw `*mut` coerces to either.
+            &raw mut size,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: openpty succeeded, so both are open descriptors that nothing else owns.
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    for fd in [&master, &slave] {
+        set_flag(fd.as_raw_fd(), libc::F_GETFD, libc::F_SETFD, libc::FD_CLOEXEC)?;
+    }
+    set_flag(master.as_raw_fd(), libc::F_GETFL, libc::F_SETFL, libc::O_NONBLOCK)?;
+    Ok((master, slave))
+}
+
+fn set_flag(fd: RawFd, get: libc::c_int, set: libc::c_int, flag: libc::c_int) -> io::Result<()> {
+    // SAFETY: plain fcntl on a descriptor the caller owns.
+    unsafe {
+        let flags = libc::fcntl(fd, get);
+        if flags < 0 || libc::fcntl(fd, set, flags | flag) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn read(fd: RawFd, buffer: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: `buffer` is valid for `buffer.len()` bytes of writes.
+    let count = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+    if count < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(count as usize)
+    }
+}
+
+fn write(fd: RawFd, data: &[u8]) -> io::Result<usize> {
+    // SAFETY: `data` is valid for `data.len()` bytes of reads.
+    let count = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+    if count < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: &str) -> ProcessSettings {
+        ProcessSettings {
+            command: line.to_owned(),
+        }
+    }
+
+    /// Collect output until `want` appears or the program ends.
+    async fn read_until(pty: &mut PtyTransport, want: &str) -> (String, Option<String>) {
+        let mut output = Vec::new();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            let next = tokio::time::timeout_at(deadline, pty.recv())
+                .await
+                .expect("timed out waiting for the program");
+            match next {
+                Incoming::Data(data) => output.extend(data),
+                Incoming::Closed(reason) => {
+                    return (String::from_utf8_lossy(&output).into_owned(), reason)
+                }
+            }
+            let text = String::from_utf8_lossy(&output).into_owned();
+            if !want.is_empty() && text.contains(want) {
+                return (text, None);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn the_program_sees_a_terminal_of_the_right_size() {
+        let mut pty = spawn(&command("stty size; test -t 0 && echo tty-ok"), 100, 30).unwrap();
+        let (output, _) = read_until(&mut pty, "tty-ok").await;
+        assert!(output.contains("30 100"), "{output:?}");
+        pty.close().await;
+    }
+
+    #[tokio::test]
+    async fn resize_reaches_the_program() {
+        let mut pty = spawn(&command("read line; stty size; echo done"), 80, 24).unwrap();
+        pty.resize(132, 43).unwrap();
+        pty.send(b"\n").await.unwrap();
+        let (output, _) = read_until(&mut pty, "done").await;
+        assert!(output.contains("43 132"), "{output:?}");
+        pty.close().await;
+    }
+
+    #[tokio::test]
+    async fn typed_input_is_echoed_by_the_line_discipline() {
+        let mut pty = spawn(&command("read line; echo \"got $line\""), 80, 24).unwrap();
+        pty.send(b"hello\r").await.unwrap();
+        let (output, _) = read_until(&mut pty, "got hello").await;
+        assert!(output.starts_with("hello"), "{output:?}");
+        pty.close().await;
+    }
+
+    #[tokio::test]
+    async fn the_exit_status_is_reported() {
+        let mut pty = spawn(&command("exit 3"), 80, 24).unwrap();
+        let (_, reason) = read_until(&mut pty, "").await;
+        assert_eq!(reason.as_deref(), Some("The program exited with status 3."));
+    }
+
+    #[tokio::test]
+    async fn output_written_just_before_exiting_is_read_first() {
+        let mut pty = spawn(&command("echo last words; exit 3"), 80, 24).unwrap();
+        while pty.child.try_wait().unwrap().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // Exited, with its output still unread: the liveness tick leaves it to `recv`.
+        assert_eq!(pty.check_alive(), None);
+        let (output, reason) = read_until(&mut pty, "").await;
+        assert!(output.contains("last words"), "{output:?}");
+        assert_eq!(reason.as_deref(), Some("The program exited with status 3."));
+    }
+
+    #[tokio::test]
+    async fn a_missing_program_is_reported() {
+        let mut pty = spawn(&command("/nonexistent/uniterm-test"), 80, 24).unwrap();
+        let (_, reason) = read_until(&mut pty, "").await;
+        assert_eq!(reason.as_deref(), Some("The program exited with status 127."));
+    }
+}
@@ -0,0 +1,279 @@
+//! RFC 2217 remote serial ports.
+//!
+//! A device server (ser2net, Moxa NPort, Digi and the like) exposes a serial port as a Telnet
+//! connection, and the COM-PORT-OPTION extension lets the client set the baud rate and framing
+//! of the port at the far end. So this is a Telnet session with different options: the stream
+//! is binary in both directions, since it carries whatever the device sends, and the terminal
+//! options give way to COM-PORT-OPTION.
+//!
+//! The line parameters are the tab's ordinary [`SerialSettings`]. They are sent once the
+//! server agrees to the option, and again whenever they change, without reconnecting.
+
+use super::telnet::{self, escape, Codec, TelnetTransport, IAC, SB, SE};
+use crate::settings::{DataBits, FlowControl, Parity, SerialSettings, StopBits, TcpSettings};
+
+/// The Telnet option code for COM-PORT-OPTION.
+pub const COM_PORT_OPTION: u8 = 44;
+
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+
+/// The COM-PORT-OPTION subnegotiations that apply `line` to the remote port.
+pub fn encode_line(line: &SerialSettings) -> Vec<u8> {
+    let data_size = match line.data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    };
+    let parity = match line.parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    };
+    let stop_size = match line.stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    };
+    let control = match line.flow_control {
+        FlowControl::None => 1,
+        FlowControl::Software => 2,
+        FlowControl::Hardware => 3,
+    };
+
+    let mut out = Vec::new();
+    let mut command = |code: u8, value: &[u8]| {
+        out.extend([IAC, SB, COM_PORT_OPTION, code]);
+        out.extend(escape(value));
+        out.extend([IAC, SE]);
+    };
+    command(SET_BAUDRATE, &line.baud_rate.to_be_bytes());
+    command(SET_DATASIZE, &[data_size]);
+    command(SET_PARITY, &[parity]);
+    command(SET_STOPSIZE, &[stop_size]);
+    command(SET_CONTROL, &[control]);
+    out
+}
+
+/// Connect to a device server and offer to configure its port.
+pub async fn connect(
+    settings: &TcpSettings,
+    line: &SerialSettings,
+) -> Result<TelnetTransport, String> {
+    let codec = Codec::com_port(line.clone());
+    telnet::open(&settings.host, settings.port, &settings.address(), codec).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::telnet::{BINARY, DO, SGA, WILL};
+    use crate::session::test_support::{poll_until, wait_for};
+    use crate::session::{ConnectionState, Session};
+    use crate::settings::{ConnectionKind, ConnectionSettings};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// What the stand-in device server saw.
+    #[derive(Debug, Default)]
+    struct Observed {
+        /// Every COM-PORT-OPTION command received, as `(code, value)`.
+        commands: Vec<(u8, Vec<u8>)>,
+        /// Data bytes, unescaped.
+        data: Vec<u8>,
+    }
+
+    impl Observed {
+        fn bauds(&self) -> Vec<u32> {
+            self.commands
+                .iter()
+                .filter(|(code, _)| *code == SET_BAUDRATE)
+                .map(|(_, v)| u32::from_be_bytes(v.as_slice().try_into().unwrap()))
+                .collect()
+        }
+
+        fn last(&self, code: u8) -> Option<u8> {
+            self.commands
+                .iter()
+                .rev()
+                .find(|(c, _)| *c == code)
+                .map(|(_, v)| v[0])
+        }
+    }
+
+    /// A device server that agrees to everything, records what it is told, acknowledges each
+    /// COM-PORT-OPTION command the way RFC 2217 servers do, and echoes data back.
+    async fn start_server() -> (u16, Arc<Mutex<Observed>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let observed = Arc::new(Mutex::new(Observed::default()));
+        let seen = observed.clone();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket
+                .write_all(&[
+                    IAC, WILL, BINARY, IAC, WILL, SGA, IAC, DO, BINARY, IAC, DO, COM_PORT_OPTION,
+                ])
+                .await
+                .unwrap();
+
+            let mut pending = Vec::new();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => n,
+                };
+                pending.extend_from_slice(&buf[..n]);
+                let mut reply = Vec::new();
+                let mut i = 0;
+                while i < pending.len() {
+                    match (pending[i], pending.get(i + 1).copied()) {
+                        (IAC, None) => break,
+                        (IAC, Some(IAC)) => {
+                            seen.lock().unwrap().data.push(IAC);
+                            reply.extend([IAC, IAC]);
+                            i += 2;
+                        }
+                        (IAC, Some(SB)) => {
+                            let Some(end) = pending[i..].windows(2).position(|w| w == [IAC, SE])
+                            else {
+                                break;
+                            };
+                            let body = &pending[i + 2..i + end];
+                            if body.first() == Some(&COM_PORT_OPTION) {
+                                let code = body[1];
+                                let mut value = Vec::new();
+                                let mut bytes = body[2..].iter();
+                                while let Some(&b) = bytes.next() {
+                                    if b == IAC {
+                                        bytes.next();
+                                    }
+                                    value.push(b);
+                                }
+                                reply.extend([IAC, SB, COM_PORT_OPTION, code + 100]);
+                                reply.extend(escape(&value));
+                                reply.extend([IAC, SE]);
+                                seen.lock().unwrap().commands.push((code, value));
+                            }
+                            i += end + 2;
+                        }
+                        (IAC, Some(_)) if pending.len() < i + 3 => break,
+                        // WILL/WONT/DO/DONT: agreed to up front, nothing more to say.
+                        (IAC, Some(_)) => i += 3,
+                        (byte, _) => {
+                            seen.lock().unwrap().data.push(byte);
+                            reply.push(byte);
+                            i += 1;
+                        }
+                    }
+                }
+                pending.drain(..i);
+                if !reply.is_empty() && socket.write_all(&reply).await.is_err() {
+                    return;
+                }
+            }
+        });
+        (port, observed)
+    }
+
+    fn received(session: &Session) -> Vec<u8> {
+        session.buffer.lock().unwrap().bytes().to_vec()
+    }
+
+    #[test]
+    fn line_parameters_encode_per_rfc_2217() {
+        let line = SerialSettings {
+            baud_rate: 9_600,
+            data_bits: DataBits::Seven,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+            flow_control: FlowControl::Hardware,
+            ..Default::default()
+        };
+        assert_eq!(
+            encode_line(&line),
+            [
+                IAC, SB, 44, 1, 0, 0, 0x25, 0x80, IAC, SE, //
+                IAC, SB, 44, 2, 7, IAC, SE, //
+                IAC, SB, 44, 3, 3, IAC, SE, //
+                IAC, SB, 44, 4, 2, IAC, SE, //
+                IAC, SB, 44, 5, 3, IAC, SE,
+            ]
+        );
+    }
+
+    #[test]
+    fn an_ff_in_the_baud_rate_is_escaped() {
+        let line = SerialSettings {
+            baud_rate: 0xff,
+            ..Default::default()
+        };
+        assert!(encode_line(&line).starts_with(&[IAC, SB, 44, 1, 0, 0, 0, IAC, IAC, IAC, SE]));
+    }
+
+    #[test]
+    fn line_changes_wait_for_the_server_to_agree() {
+        let mut codec = Codec::com_port(SerialSettings::default());
+        codec.start();
+        let faster = SerialSettings {
+            baud_rate: 921_600,
+            ..Default::default()
+        };
+        assert_eq!(codec.set_line(&faster), None, "not before DO COM-PORT-OPTION");
+        // Agreement sends whatever is current, not what the codec started with.
+        let reply = codec.decode(&[IAC, DO, COM_PORT_OPTION]).reply;
+        assert_eq!(reply, encode_line(&faster));
+    }
+
+    #[tokio::test]
+    async fn the_remote_port_is_configured_and_reconfigured_live() {
+        let (port, observed) = start_server().await;
+        let ctx = eframe::egui::Context::default();
+        let handle = tokio::runtime::Handle::current();
+        let mut session = Session::new(ConnectionSettings {
+            kind: ConnectionKind::Rfc2217,
+            rfc2217: TcpSettings {
+                host: "127.0.0.1".into(),
+                port,
+            },
+            serial: SerialSettings {
+                baud_rate: 19_200,
+                parity: Parity::Odd,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        session.connect(&handle, &ctx);
+        poll_until(&mut session, &ctx, "the connection", |s| s.is_connected()).await;
+        wait_for(&observed, |o| o.bauds() == [19_200]).await;
+        assert_eq!(observed.lock().unwrap().last(SET_PARITY), Some(2));
+        assert_eq!(observed.lock().unwrap().last(SET_DATASIZE), Some(8));
+
+        // Binary both ways: 0xFF survives, and a bare CR gains no NUL.
+        session.send(vec![b'\r', 0xff, 0x00, b'\r']);
+        wait_for(&observed, |o| o.data.len() >= 4).await;
+        assert_eq!(observed.lock().unwrap().data, [b'\r', 0xff, 0x00, b'\r']);
+        poll_until(&mut session, &ctx, "the echo", |s| received(s).len() >= 4).await;
+        assert_eq!(
+            received(&session),
+            [b'\r', 0xff, 0x00, b'\r'],
+            "neither the echo nor the server's acknowledgements may reach the terminal"
+        );
+
+        // A change while connected goes out without reconnecting.
+        session.settings.serial.baud_rate = 57_600;
+        session.settings.serial.parity = Parity::None;
+        session.apply_line_settings();
+        wait_for(&observed, |o| o.bauds() == [19_200, 57_600]).await;
+        assert_eq!(observed.lock().unwrap().last(SET_PARITY), Some(1));
+        assert_eq!(session.state, ConnectionState::Connected);
+
+        session.disconnect();
+    }
+}
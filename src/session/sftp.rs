@@ -0,0 +1,913 @@
+//! SFTP client.
+//!
+//! Version 3 of the SSH file transfer protocol (draft-ietf-secsh-filexfer-02), which is what
+//! OpenSSH and practically every embedded server speak. It runs on a channel of an SSH
+//! connection that is already up — see [`super::ssh::Connection::open_sftp`] — so browsing a
+//! device's files costs no second login.
+//!
+//! Only what the file panel needs is here: resolving a path, listing a directory, and copying
+//! a file either way. Transfers keep several requests in flight, because waiting out a round
+//! trip per block makes a firmware image crawl over anything slower than a LAN.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, SeekFrom};
+
+/// The only protocol version spoken.
+const VERSION: u32 = 3;
+
+const FXP_INIT: u8 = 1;
+const FXP_VERSION: u8 = 2;
+const FXP_OPEN: u8 = 3;
+const FXP_CLOSE: u8 = 4;
+const FXP_READ: u8 = 5;
+const FXP_WRITE: u8 = 6;
+const FXP_FSTAT: u8 = 8;
+const FXP_OPENDIR: u8 = 11;
+const FXP_READDIR: u8 = 12;
+const FXP_REALPATH: u8 = 16;
+const FXP_STAT: u8 = 17;
+const FXP_STATUS: u8 = 101;
+const FXP_HANDLE: u8 = 102;
+const FXP_DATA: u8 = 103;
+const FXP_NAME: u8 = 104;
+const FXP_ATTRS: u8 = 105;
+
+const FX_OK: u32 = 0;
+const FX_EOF: u32 = 1;
+const FX_NO_SUCH_FILE: u32 = 2;
+const FX_PERMISSION_DENIED: u32 = 3;
+
+const FXF_READ: u32 = 0x01;
+const FXF_WRITE: u32 = 0x02;
+const FXF_CREAT: u32 = 0x08;
+const FXF_TRUNC: u32 = 0x10;
+
+const ATTR_SIZE: u32 = 0x01;
+const ATTR_UIDGID: u32 = 0x02;
+const ATTR_PERMISSIONS: u32 = 0x04;
+const ATTR_ACMODTIME: u32 = 0x08;
+const ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFLNK: u32 = 0o120_000;
+
+/// Bytes per READ or WRITE. The draft only promises that servers take 34000-byte packets.
+const CHUNK: u32 = 32 * 1024;
+/// Requests kept in flight during a transfer.
+const WINDOW: usize = 16;
+/// Largest reply accepted, so a corrupt length cannot allocate gigabytes.
+const MAX_PACKET: usize = 1024 * 1024;
+
+/// One directory entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    /// A directory, or a link to one.
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    /// Seconds since the Unix epoch.
+    pub modified: Option<u32>,
+}
+
+/// The attributes this client cares about.
+#[derive(Clone, Copy, Debug, Default)]
+struct Attrs {
+    size: Option<u64>,
+    permissions: Option<u32>,
+    modified: Option<u32>,
+}
+
+impl Attrs {
+    fn file_type(&self) -> Option<u32> {
+        self.permissions.map(|p| p & S_IFMT)
+    }
+}
+
+/// One reply from the server.
+struct Reply {
+    kind: u8,
+    id: u32,
+    body: Vec<u8>,
+}
+
+/// A client on an open SFTP stream. One request is answered at a time except inside a
+/// transfer, so a client used for browsing should not also carry a transfer.
+pub struct Sftp<S> {
+    stream: S,
+    next_id: u32,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Sftp<S> {
+    /// Say hello on a stream the server is already running SFTP on.
+    pub async fn start(mut stream: S) -> Result<Self, String> {
+        let mut packet = Vec::new();
+        put_u32(&mut packet, 5);
+        packet.push(FXP_INIT);
+        put_u32(&mut packet, VERSION);
+        write_all(&mut stream, &packet).await?;
+        let (kind, body) = read_packet(&mut stream).await?;
+        if kind != FXP_VERSION || body.len() < 4 {
+            return Err("The server did not answer the SFTP handshake.".to_owned());
+        }
+        Ok(Self { stream, next_id: 0 })
+    }
+
+    /// The absolute form of `path`; `"."` gives the login directory.
+    pub async fn realpath(&mut self, path: &str) -> Result<String, String> {
+        let reply = self.call(FXP_REALPATH, &string(path)).await?;
+        let mut names = expect_names(reply, path)?;
+        names
+            .pop()
+            .map(|(name, _)| name)
+            .ok_or_else(|| format!("The server could not resolve {path}."))
+    }
+
+    /// A directory's contents, directories first, without `.` and `..`.
+    pub async fn list(&mut self, path: &str) -> Result<Vec<Entry>, String> {
+        let handle = self.open_handle(FXP_OPENDIR, &string(path), path).await?;
+        let mut entries = Vec::new();
+        let listed = loop {
+            let reply = match self.call(FXP_READDIR, &string_bytes(&handle)).await {
+                Ok(reply) => reply,
+                Err(e) => break Err(e),
+            };
+            if reply.kind == FXP_STATUS && status_code(&reply.body) == Some(FX_EOF) {
+                break Ok(());
+            }
+            match expect_names(reply, path) {
+                Ok(names) => entries.extend(names),
+                Err(e) => break Err(e),
+            }
+        };
+        let _ = self.close(&handle).await;
+        listed?;
+
+        let mut listing = Vec::new();
+        for (name, mut attrs) in entries {
+            if name == "." || name == ".." {
+                continue;
+            }
+            // A link is shown as whatever it points at, so a linked directory can be entered.
+            if attrs.file_type() == Some(S_IFLNK) {
+                if let Ok(target) = self.stat(&join(path, &name)).await {
+                    attrs = target;
+                }
+            }
+            listing.push(Entry {
+                is_dir: attrs.file_type() == Some(S_IFDIR),
+                size: attrs.size,
+                modified: attrs.modified,
+                name,
+            });
+        }
+        listing.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+        Ok(listing)
+    }
+
+    /// Copy `remote` to `local`, reporting bytes done and the total when it is known.
+    pub async fn download(
+        &mut self,
+        remote: &str,
+        local: &Path,
+        mut progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, String> {
+        let mut open = string(remote);
+        put_u32(&mut open, FXF_READ);
+        put_u32(&mut open, 0);
+        let handle = self.open_handle(FXP_OPEN, &open, remote).await?;
+        let total = match self.call(FXP_FSTAT, &string_bytes(&handle)).await {
+            Ok(reply) if reply.kind == FXP_ATTRS => {
+                Reader(&reply.body).attrs().ok().and_then(|a| a.size)
+            }
+            _ => None,
+        };
+        let copied = match File::create(local).await {
+            Ok(mut file) => {
+                let mut pending = HashMap::new();
+                let copied = self
+                    .read_into(&handle, &mut file, &mut pending, total, &mut progress)
+                    .await;
+                self.drain(pending.len()).await;
+                match file.flush().await {
+                    Ok(()) => copied,
+                    Err(e) => Err(format!("Could not write {}: {e}", local.display())),
+                }
+            }
+            Err(e) => Err(format!("Could not create {}: {e}", local.display())),
+        };
+        let _ = self.close(&handle).await;
+        copied
+    }
+
+    /// Copy `local` to `remote`, replacing it, and report progress as for [`Self::download`].
+    pub async fn upload(
+        &mut self,
+        local: &Path,
+        remote: &str,
+        mut progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, String> {
+        let mut file = File::open(local)
+            .await
+            .map_err(|e| format!("Could not open {}: {e}", local.display()))?;
+        let total = file.metadata().await.ok().map(|m| m.len());
+        let mut open = string(remote);
+        put_u32(&mut open, FXF_WRITE | FXF_CREAT | FXF_TRUNC);
+        put_u32(&mut open, 0);
+        let handle = self.open_handle(FXP_OPEN, &open, remote).await?;
+        let mut pending = HashMap::new();
+        let copied = self
+            .write_from(&handle, &mut file, local, &mut pending, total, &mut progress)
+            .await;
+        self.drain(pending.len()).await;
+        // The close is where some servers report a failed write, so it counts.
+        let closed = self.close(&handle).await;
+        let copied = copied?;
+        closed.map_err(|e| format!("{remote}: {e}"))?;
+        Ok(copied)
+    }
+
+    /// Issue reads ahead of the data arriving, writing each block where it belongs.
+    async fn read_into(
+        &mut self,
+        handle: &[u8],
+        file: &mut File,
+        pending: &mut HashMap<u32, (u64, u32)>,
+        total: Option<u64>,
+        progress: &mut impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, String> {
+        let mut next_offset = 0;
+        let mut end_seen = false;
+        // The rest of a block a server answered short, which it may do anywhere in the file.
+        let mut remainders: Vec<(u64, u32)> = Vec::new();
+        let mut done = 0;
+        loop {
+            while pending.len() < WINDOW {
+                let (offset, length) = match remainders.pop() {
+                    Some(remainder) => remainder,
+                    None if end_seen => break,
+                    None => {
+                        next_offset += u64::from(CHUNK);
+                        (next_offset - u64::from(CHUNK), CHUNK)
+                    }
+                };
+                let mut request = string_bytes(handle);
+                put_u64(&mut request, offset);
+                put_u32(&mut request, length);
+                let id = self.send(FXP_READ, &request).await?;
+                pending.insert(id, (offset, length));
+            }
+            if pending.is_empty() {
+                return Ok(done);
+            }
+
+            let reply = self.recv().await?;
+            let Some((offset, length)) = pending.remove(&reply.id) else {
+                return Err("The server answered a request that was never made.".to_owned());
+            };
+            match reply.kind {
+                FXP_DATA => {
+                    let data = Reader(&reply.body).string()?;
+                    if data.is_empty() {
+                        end_seen = true;
+                        continue;
+                    }
+                    file.seek(SeekFrom::Start(offset))
+                        .await
+                        .map_err(|e| format!("Could not write the download: {e}"))?;
+                    file.write_all(data)
+                        .await
+                        .map_err(|e| format!("Could not write the download: {e}"))?;
+                    let count = data.len() as u32;
+                    if count < length {
+                        remainders.push((offset + u64::from(count), length - count));
+                    }
+                    done += u64::from(count);
+                    progress(done, total);
+                }
+                FXP_STATUS if status_code(&reply.body) == Some(FX_EOF) => end_seen = true,
+                _ => return Err(reply_error(reply)),
+            }
+        }
+    }
+
+    /// Send the file block by block, keeping up to [`WINDOW`] writes unacknowledged.
+    async fn write_from(
+        &mut self,
+        handle: &[u8],
+        file: &mut File,
+        local: &Path,
+        pending: &mut HashMap<u32, u64>,
+        total: Option<u64>,
+        progress: &mut impl FnMut(u64, Option<u64>),
+    ) -> Result<u64, String> {
+        let mut buffer = vec![0; CHUNK as usize];
+        let mut offset = 0;
+        let mut read_all = false;
+        let mut done = 0;
+        loop {
+            while !read_all && pending.len() < WINDOW {
+                let count = file
+                    .read(&mut buffer)
+                    .await
+                    .map_err(|e| format!("Could not read {}: {e}", local.display()))?;
+                if count == 0 {
+                    read_all = true;
+                    break;
+                }
+                let mut request = string_bytes(handle);
+                put_u64(&mut request, offset);
+                put_string(&mut request, &buffer[..count]);
+                let id = self.send(FXP_WRITE, &request).await?;
+                pending.insert(id, count as u64);
+                offset += count as u64;
+            }
+            if pending.is_empty() {
+                return Ok(done);
+            }
+
+            let reply = self.recv().await?;
+            let Some(count) = pending.remove(&reply.id) else {
+                return Err("The server answered a request that was never made.".to_owned());
+            };
+            if reply.kind != FXP_STATUS || status_code(&reply.body) != Some(FX_OK) {
+                return Err(reply_error(reply));
+            }
+            done += count;
+            progress(done, total);
+        }
+    }
+
+    /// Read and discard the replies to requests abandoned by a failed transfer, so the next
+    /// request is not answered with one of theirs.
+    async fn drain(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.recv().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn stat(&mut self, path: &str) -> Result<Attrs, String> {
+        let reply = self.call(FXP_STAT, &string(path)).await?;
+        match reply.kind {
+            FXP_ATTRS => Reader(&reply.body).attrs(),
+            _ => Err(reply_error(reply)),
+        }
+    }
+
+    /// Send an open request and return the handle, naming `path` in any error.
+    async fn open_handle(
+        &mut self,
+        kind: u8,
+        request: &[u8],
+        path: &str,
+    ) -> Result<Vec<u8>, String> {
+        let reply = self.call(kind, request).await?;
+        match reply.kind {
+            FXP_HANDLE => Reader(&reply.body).string().map(<[u8]>::to_vec),
+            _ => Err(format!("{path}: {}", reply_error(reply))),
+        }
+    }
+
+    async fn close(&mut self, handle: &[u8]) -> Result<(), String> {
+        let reply = self.call(FXP_CLOSE, &string_bytes(handle)).await?;
+        match status_code(&reply.body) {
+            Some(FX_OK) if reply.kind == FXP_STATUS => Ok(()),
+            _ => Err(reply_error(reply)),
+        }
+    }
+
+    /// Send one request and wait for its answer.
+    async fn call(&mut self, kind: u8, payload: &[u8]) -> Result<Reply, String> {
+        let id = self.send(kind, payload).await?;
+        let reply = self.recv().await?;
+        if reply.id != id {
+            return Err("The server's replies are out of step with its requests.".to_owned());
+        }
+        Ok(reply)
+    }
+
+    async fn send(&mut self, kind: u8, payload: &[u8]) -> Result<u32, String> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let mut packet = Vec::with_capacity(9 + payload.len());
+        put_u32(&mut packet, 5 + payload.len() as u32);
+        packet.push(kind);
+        put_u32(&mut packet, id);
+        packet.extend_from_slice(payload);
+        write_all(&mut self.stream, &packet).await?;
+        Ok(id)
+    }
+
+    async fn recv(&mut self) -> Result<Reply, String> {
+        let (kind, body) = read_packet(&mut self.stream).await?;
+        let mut reader = Reader(&body);
+        let id = reader.u32()?;
+        let body = reader.0.to_vec();
+        Ok(Reply { kind, id, body })
+    }
+}
+
+/// `name` inside the directory `dir`, for remote paths, which always use `/`.
+pub fn join(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{dir}{name}")
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+/// The directory above `path`, or `None` at the root.
+pub fn parent(path: &str) -> Option<String> {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+    match trimmed.rfind('/') {
+        Some(0) => Some("/".to_owned()),
+        Some(index) => Some(trimmed[..index].to_owned()),
+        None => None,
+    }
+}
+
+async fn write_all<S: AsyncWrite + Unpin>(stream: &mut S, packet: &[u8]) -> Result<(), String> {
+    stream
+        .write_all(packet)
+        .await
+        .map_err(|e| format!("SFTP write failed: {e}"))?;
+    stream
+        .flush()
+        .await
+        .map_err(|e| format!("SFTP write failed: {e}"))
+}
+
+async fn read_packet<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(u8, Vec<u8>), String> {
+    let closed = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => "The SFTP session closed.".to_owned(),
+        _ => format!("SFTP read failed: {e}"),
+    };
+    let length = stream.read_u32().await.map_err(closed)? as usize;
+    if length == 0 || length > MAX_PACKET {
+        return Err(format!("The server sent a {length}-byte SFTP packet, which cannot be right."));
+    }
+    let mut packet = vec![0; length];
+    stream.read_exact(&mut packet).await.map_err(closed)?;
+    let body = packet.split_off(1);
+    Ok((packet[0], body))
+}
+
+fn status_code(body: &[u8]) -> Option<u32> {
+    Reader(body).u32().ok()
+}
+
+/// What a reply that is not the one hoped for says went wrong.
+fn reply_error(reply: Reply) -> String {
+    if reply.kind != FXP_STATUS {
+        return format!("The server sent an unexpected SFTP reply (type {}).", reply.kind);
+    }
+    let mut reader = Reader(&reply.body);
+    let code = reader.u32().unwrap_or(u32::MAX);
+    let message = reader
+        .string()
+        .map(|m| String::from_utf8_lossy(m).trim().to_owned())
+        .unwrap_or_default();
+    match (code, message.is_empty()) {
+        (_, false) => message,
+        (FX_NO_SUCH_FILE, true) => "No such file.".to_owned(),
+        (FX_PERMISSION_DENIED, true) => "Permission denied.".to_owned(),
+        (code, true) => format!("The server reported failure (SFTP status {code})."),
+    }
+}
+
+/// The entries of a NAME reply, or the error it stands in place of.
+fn expect_names(reply: Reply, path: &str) -> Result<Vec<(String, Attrs)>, String> {
+    if reply.kind != FXP_NAME {
+        return Err(format!("{path}: {}", reply_error(reply)));
+    }
+    let mut reader = Reader(&reply.body);
+    let count = reader.u32()?;
+    let mut names = Vec::new();
+    for _ in 0..count {
+        let name = String::from_utf8_lossy(reader.string()?).into_owned();
+        reader.string()?; // the `ls -l` line, for display by clients that do no formatting
+        names.push((name, reader.attrs()?));
+    }
+    Ok(names)
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value);
+}
+
+fn string(value: &str) -> Vec<u8> {
+    string_bytes(value.as_bytes())
+}
+
+fn string_bytes(value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + value.len());
+    put_string(&mut out, value);
+    out
+}
+
+/// Reads SFTP's big-endian fields off the front of a slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < count {
+            return Err("The server sent a truncated SFTP reply.".to_owned());
+        }
+        let (head, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], String> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    fn attrs(&mut self) -> Result<Attrs, String> {
+        let flags = self.u32()?;
+        let mut attrs = Attrs::default();
+        if flags & ATTR_SIZE != 0 {
+            attrs.size = Some(self.u64()?);
+        }
+        if flags & ATTR_UIDGID != 0 {
+            self.take(8)?;
+        }
+        if flags & ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(self.u32()?);
+        }
+        if flags & ATTR_ACMODTIME != 0 {
+            self.u32()?; // access time
+            attrs.modified = Some(self.u32()?);
+        }
+        if flags & ATTR_EXTENDED != 0 {
+            for _ in 0..self.u32()? {
+                self.string()?;
+                self.string()?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use std::io::{Read, Seek, Write};
+    use std::path::PathBuf;
+
+    /// An SFTP server over a local directory, fed bytes and answering with bytes, so it can
+    /// sit behind a duplex stream here or an SSH channel in the SSH tests. Reads are answered
+    /// short on purpose, as servers may, to exercise the client's handling of it.
+    pub(in crate::session) struct FakeServer {
+        root: PathBuf,
+        input: Vec<u8>,
+        handles: HashMap<u32, Open>,
+        next_handle: u32,
+    }
+
+    enum Open {
+        File(std::fs::File),
+        Dir { path: PathBuf, listed: bool },
+    }
+
+    /// Most a read is answered with, well under the client's block size.
+    const SHORT_READ: usize = 10_000;
+
+    impl FakeServer {
+        pub(in crate::session) fn new(root: PathBuf) -> Self {
+            Self {
+                root,
+                input: Vec::new(),
+                handles: HashMap::new(),
+                next_handle: 0,
+            }
+        }
+
+        /// Take bytes from the client and return the replies to every complete request.
+        pub(in crate::session) fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+            self.input.extend_from_slice(data);
+            let mut out = Vec::new();
+            while self.input.len() >= 4 {
+                let length = u32::from_be_bytes(self.input[..4].try_into().unwrap()) as usize;
+                if self.input.len() < 4 + length {
+                    break;
+                }
+                let packet: Vec<u8> = self.input.drain(..4 + length).skip(4).collect();
+                let (kind, body) = self.handle(packet[0], &packet[1..]);
+                put_u32(&mut out, 1 + body.len() as u32);
+                out.push(kind);
+                out.extend(body);
+            }
+            out
+        }
+
+        fn handle(&mut self, kind: u8, body: &[u8]) -> (u8, Vec<u8>) {
+            let mut reader = Reader(body);
+            if kind == FXP_INIT {
+                let mut version = Vec::new();
+                put_u32(&mut version, VERSION);
+                return (FXP_VERSION, version);
+            }
+            let id = reader.u32().unwrap();
+            let mut reply = Vec::new();
+            put_u32(&mut reply, id);
+            let status = |reply: &mut Vec<u8>, code: u32| {
+                put_u32(reply, code);
+                put_string(reply, b"");
+                put_string(reply, b"");
+                FXP_STATUS
+            };
+            let kind = match kind {
+                FXP_REALPATH => {
+                    let path = self.text(&mut reader);
+                    let path = if path == "." { "/".to_owned() } else { path };
+                    put_u32(&mut reply, 1);
+                    put_string(&mut reply, path.as_bytes());
+                    put_string(&mut reply, b"");
+                    put_u32(&mut reply, 0);
+                    FXP_NAME
+                }
+                FXP_OPENDIR => {
+                    let path = self.local(&mut reader);
+                    if !path.is_dir() {
+                        return (FXP_STATUS, { status(&mut reply, FX_NO_SUCH_FILE); reply });
+                    }
+                    self.new_handle(&mut reply, Open::Dir { path, listed: false })
+                }
+                FXP_READDIR => {
+                    let handle = handle_of(&mut reader);
+                    match self.handles.get_mut(&handle) {
+                        Some(Open::Dir { path, listed }) if !*listed => {
+                            *listed = true;
+                            let mut names = vec![(".".to_owned(), path.clone())];
+                            for entry in std::fs::read_dir(&*path).unwrap() {
+                                let entry = entry.unwrap();
+                                let name = entry.file_name().to_string_lossy().into_owned();
+                                names.push((name, entry.path()));
+                            }
+                            put_u32(&mut reply, names.len() as u32);
+                            for (name, path) in names {
+                                put_string(&mut reply, name.as_bytes());
+                                put_string(&mut reply, format!("-rw-r--r-- {name}").as_bytes());
+                                put_attrs(&mut reply, &std::fs::symlink_metadata(path).unwrap());
+                            }
+                            FXP_NAME
+                        }
+                        _ => status(&mut reply, FX_EOF),
+                    }
+                }
+                FXP_OPEN => {
+                    let path = self.local(&mut reader);
+                    let flags = reader.u32().unwrap();
+                    let file = std::fs::OpenOptions::new()
+                        .read(flags & FXF_READ != 0)
+                        .write(flags & FXF_WRITE != 0)
+                        .create(flags & FXF_CREAT != 0)
+                        .truncate(flags & FXF_TRUNC != 0)
+                        .open(path);
+                    match file {
+                        Ok(file) => self.new_handle(&mut reply, Open::File(file)),
+                        Err(_) => status(&mut reply, FX_NO_SUCH_FILE),
+                    }
+                }
+                FXP_READ => {
+                    let handle = handle_of(&mut reader);
+                    let offset = reader.u64().unwrap();
+                    let length = (reader.u32().unwrap() as usize).min(SHORT_READ);
+                    let Some(Open::File(file)) = self.handles.get_mut(&handle) else {
+                        return (FXP_STATUS, { status(&mut reply, 4); reply });
+                    };
+                    file.seek(std::io::SeekFrom::Start(offset)).unwrap();
+                    let mut data = vec![0; length];
+                    let count = file.read(&mut data).unwrap();
+                    if count == 0 {
+                        status(&mut reply, FX_EOF)
+                    } else {
+                        put_string(&mut reply, &data[..count]);
+                        FXP_DATA
+                    }
+                }
+                FXP_WRITE => {
+                    let handle = handle_of(&mut reader);
+                    let offset = reader.u64().unwrap();
+                    let data = reader.string().unwrap();
+                    let Some(Open::File(file)) = self.handles.get_mut(&handle) else {
+                        return (FXP_STATUS, { status(&mut reply, 4); reply });
+                    };
+                    file.seek(std::io::SeekFrom::Start(offset)).unwrap();
+                    file.write_all(data).unwrap();
+                    status(&mut reply, FX_OK)
+                }
+                FXP_FSTAT => {
+                    let handle = handle_of(&mut reader);
+                    let Some(Open::File(file)) = self.handles.get(&handle) else {
+                        return (FXP_STATUS, { status(&mut reply, 4); reply });
+                    };
+                    put_attrs(&mut reply, &file.metadata().unwrap());
+                    FXP_ATTRS
+                }
+                FXP_STAT => match std::fs::metadata(self.local(&mut reader)) {
+                    Ok(metadata) => {
+                        put_attrs(&mut reply, &metadata);
+                        FXP_ATTRS
+                    }
+                    Err(_) => status(&mut reply, FX_NO_SUCH_FILE),
+                },
+                FXP_CLOSE => {
+                    let handle = handle_of(&mut reader);
+                    let code = match self.handles.remove(&handle) {
+                        Some(_) => FX_OK,
+                        None => 4,
+                    };
+                    status(&mut reply, code)
+                }
+                _ => status(&mut reply, 8),
+            };
+            (kind, reply)
+        }
+
+        fn text(&self, reader: &mut Reader) -> String {
+            String::from_utf8(reader.string().unwrap().to_vec()).unwrap()
+        }
+
+        /// Where a path the client sent lives on disk.
+        fn local(&self, reader: &mut Reader) -> PathBuf {
+            self.root.join(self.text(reader).trim_start_matches('/'))
+        }
+
+        fn new_handle(&mut self, reply: &mut Vec<u8>, open: Open) -> u8 {
+            let handle = self.next_handle;
+            self.next_handle += 1;
+            self.handles.insert(handle, open);
+            put_string(reply, &handle.to_be_bytes());
+            FXP_HANDLE
+        }
+    }
+
+    fn handle_of(reader: &mut Reader) -> u32 {
+        let handle = reader.string().unwrap();
+        handle.try_into().map_or(u32::MAX, u32::from_be_bytes)
+    }
+
+    fn put_attrs(out: &mut Vec<u8>, metadata: &std::fs::Metadata) {
+        let kind = if metadata.is_symlink() {
+            S_IFLNK
+        } else if metadata.is_dir() {
+            S_IFDIR
+        } else {
+            0o100_000
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs() as u32);
+        put_u32(out, ATTR_SIZE | ATTR_PERMISSIONS | ATTR_ACMODTIME);
+        put_u64(out, metadata.len());
+        put_u32(out, kind | 0o644);
+        put_u32(out, modified);
+        put_u32(out, modified);
+    }
+
+    /// A fresh, empty directory for one test to serve.
+    pub(in crate::session) fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("uniterm_sftp_{name}"));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    /// A client talking to a [`FakeServer`] over an in-memory stream.
+    async fn client(root: &Path) -> Sftp<tokio::io::DuplexStream> {
+        let (near, mut far) = tokio::io::duplex(64 * 1024);
+        let mut server = FakeServer::new(root.to_owned());
+        tokio::spawn(async move {
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let count = match far.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(count) => count,
+                };
+                let reply = server.feed(&buffer[..count]);
+                if far.write_all(&reply).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Sftp::start(near).await.unwrap()
+    }
+
+    /// Bytes that are not all the same, so a block written at the wrong offset shows.
+    fn pattern(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn a_listing_puts_directories_first_and_drops_the_dot_entries() {
+        let root = temp_root("list");
+        std::fs::create_dir(root.join("logs")).unwrap();
+        std::fs::write(root.join("b.bin"), [0; 5]).unwrap();
+        std::fs::write(root.join("a.txt"), "hello").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("logs"), root.join("latest")).unwrap();
+
+        let mut sftp = client(&root).await;
+        assert_eq!(sftp.realpath(".").await.unwrap(), "/");
+        let listing = sftp.list("/").await.unwrap();
+        let names: Vec<_> = listing.iter().map(|e| (e.name.as_str(), e.is_dir)).collect();
+        #[cfg(unix)]
+        assert_eq!(
+            names,
+            [("latest", true), ("logs", true), ("a.txt", false), ("b.bin", false)],
+            "a link to a directory can be entered like one"
+        );
+        assert_eq!(listing.iter().find(|e| e.name == "a.txt").unwrap().size, Some(5));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn a_download_arrives_intact_with_progress_up_to_the_total() {
+        let root = temp_root("download");
+        let content = pattern(300_000);
+        std::fs::write(root.join("firmware.bin"), &content).unwrap();
+        let local = root.join("copy.bin");
+
+        let mut sftp = client(&root).await;
+        let mut seen = Vec::new();
+        let copied = sftp
+            .download("/firmware.bin", &local, |done, total| seen.push((done, total)))
+            .await
+            .unwrap();
+        assert_eq!(copied, 300_000);
+        assert_eq!(std::fs::read(&local).unwrap(), content);
+        assert_eq!(seen.last(), Some(&(300_000, Some(300_000))));
+        assert!(seen.windows(2).all(|w| w[0].0 < w[1].0));
+
+        // The client is still in step for the next request.
+        assert_eq!(sftp.list("/").await.unwrap().len(), 2);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn an_upload_replaces_the_remote_file() {
+        let root = temp_root("upload");
+        let content = pattern(100_000);
+        let local = root.join("image.bin");
+        std::fs::write(&local, &content).unwrap();
+        std::fs::write(root.join("target.bin"), pattern(200_000)).unwrap();
+
+        let mut sftp = client(&root).await;
+        let copied = sftp.upload(&local, "/target.bin", |_, _| {}).await.unwrap();
+        assert_eq!(copied, 100_000);
+        assert_eq!(std::fs::read(root.join("target.bin")).unwrap(), content);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn a_missing_file_is_named_in_the_error() {
+        let root = temp_root("missing");
+        let mut sftp = client(&root).await;
+        let error = sftp
+            .download("/nope.log", &root.join("nope.log"), |_, _| {})
+            .await
+            .unwrap_err();
+        assert_eq!(error, "/nope.log: No such file.");
+        let error = sftp.list("/nowhere").await.unwrap_err();
+        assert!(error.starts_with("/nowhere"), "{error}");
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn remote_paths_join_and_climb_with_slashes() {
+        assert_eq!(join("/", "etc"), "/etc");
+        assert_eq!(join("/var/log", "syslog"), "/var/log/syslog");
+        assert_eq!(parent("/var/log"), Some("/var".to_owned()));
+        assert_eq!(parent("/var/"), Some("/".to_owned()));
+        assert_eq!(parent("/"), None);
+    }
+}
@@ -1,7 +1,18 @@
 //! SSH transport.
 //!
 //! Opens a connection, verifies the host key against the trust store, authenticates, and
-//! requests an interactive shell on a PTY.
+//! requests an interactive shell on a PTY — or runs one fixed command, with or without a PTY,
+//! and reports its exit status when it ends.
+//!
+//! Jump hosts are chained the way OpenSSH's `ProxyJump` does it: each hop opens a
+//! `direct-tcpip` channel to the next, and the next handshake runs inside that channel. Every
+//! hop is a full SSH connection of its own, so each gets the same host key check and login as
+//! the target — a bastion is not trusted just because it is on the way.
+//!
+//! Port forwards ride on the target's connection once the shell is up. A `-L` port is a local
+//! listener opening a `direct-tcpip` channel per connection; an `-R` port is requested from the
+//! server, whose connections arrive as `forwarded-tcpip` channels and are routed to their
+//! destination by the connection's handler.
 //!
 //! # How the host key decision is made
 //!
@@ -15,19 +26,49 @@
 //!
 //! Approval is bound to the exact fingerprint the user was shown, so saying yes cannot
 //! blanket-trust a different key that arrives on the retry.
+//!
+//! # Certificates
+//!
+//! A key with an OpenSSH user certificate beside it (`id_ed25519-cert.pub` for `id_ed25519`,
+//! as `ssh-keygen -s` names it) is offered as that certificate first, so hosts that trust the
+//! signing CA let it in without the key being listed anywhere. If a server turns the
+//! certificate down, the bare key is offered next, as OpenSSH does. A certificate that cannot
+//! be valid — expired, not yet valid, or for another key — stops the login with a message
+//! saying so, since that is the likeliest reason a short-lived certificate stops working.
+//!
+//! # Keyboard-interactive prompts
+//!
+//! A one-time code cannot be answered by reconnecting, so unlike a host key a server's
+//! keyboard-interactive challenge is put to the user *while the handshake waits*: the
+//! [`Prompter`] hands it to the UI and the attempt resumes when the answers come back.
 
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
-use russh::client::{self, Handle, Msg};
-use russh::keys::ssh_key::PublicKey;
-use russh::keys::{load_secret_key, PrivateKeyWithHashAlg};
-use russh::{Channel, ChannelReadHalf, ChannelWriteHalf, Disconnect};
+use russh::client::{
+    self, AuthResult, ChannelOpenHandle, Handle, KeyboardInteractiveAuthResponse, Msg,
+};
+use russh::keys::ssh_key::certificate::CertType;
+use russh::keys::ssh_key::{Certificate, PublicKey};
+#[cfg(unix)]
+use russh::keys::agent::client::AgentClient;
+use russh::keys::{load_secret_key, HashAlg, PrivateKey, PrivateKeyWithHashAlg};
+use russh::{
+    Channel, ChannelMsg, ChannelOpenFailure, ChannelReadHalf, ChannelWriteHalf, Disconnect,
+    MethodKind, Sig,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tracing::debug;
 
+use super::sftp::Sftp;
+use super::transport::Incoming;
 use crate::knownhosts::{self, Rejection, Trust};
-use crate::settings::{SshAuth, SshSettings};
+use crate::recents::{now_seconds, relative_time};
+use crate::settings::{Forward, ForwardDirection, SshAuth, SshSettings};
 
 /// How often to send a keepalive, and how many may go unanswered before the connection is
 /// declared dead.
@@ -38,6 +79,10 @@ use crate::settings::{SshAuth, SshSettings};
 const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
 const KEEPALIVE_MAX: usize = 3;
 
+/// How many authentication requests one connection may make. A multi-factor chain is two or
+/// three; the cap stops a server that keeps asking from looping forever.
+const MAX_AUTH_ROUNDS: usize = 8;
+
 /// Secrets for one connection attempt.
 ///
 /// Held in memory for the lifetime of the process and never written to disk. Persisting these
@@ -60,6 +105,65 @@ impl Credentials {
             _ => Ok(()),
         }
     }
+
+    /// What may go on to a jump host. A key or the agent works for any host, so its
+    /// passphrase comes along; a password is the target's, so a hop logging in with one is
+    /// asked for its own.
+    fn for_jump(&self, auth: SshAuth) -> Credentials {
+        Credentials {
+            password: String::new(),
+            passphrase: match auth {
+                SshAuth::PublicKey => self.passphrase.clone(),
+                SshAuth::Password | SshAuth::Agent => String::new(),
+            },
+        }
+    }
+}
+
+/// One keyboard-interactive round from the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    /// The host asking: a jump host's while one is being logged into, otherwise the tab's.
+    pub host: String,
+    /// Title for the exchange, often empty.
+    pub name: String,
+    /// Free text to show above the prompts, often empty.
+    pub instructions: String,
+    pub prompts: Vec<ChallengePrompt>,
+}
+
+/// One question in a [`Challenge`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChallengePrompt {
+    /// What the server asks, e.g. `Verification code: `.
+    pub text: String,
+    /// Whether the answer may be shown as typed. False for anything secret.
+    pub echo: bool,
+}
+
+/// The user's half of keyboard-interactive authentication: puts each challenge in front of
+/// them and waits for the answers, one per prompt.
+pub struct Prompter {
+    ask: Box<dyn Fn(Challenge) + Send>,
+    answers: mpsc::UnboundedReceiver<Vec<String>>,
+}
+
+impl Prompter {
+    /// `ask` shows a challenge; the answers arrive on `answers`. Closing the channel cancels.
+    pub fn new(
+        ask: impl Fn(Challenge) + Send + 'static,
+        answers: mpsc::UnboundedReceiver<Vec<String>>,
+    ) -> Self {
+        Self {
+            ask: Box::new(ask),
+            answers,
+        }
+    }
+
+    async fn answer(&mut self, challenge: Challenge) -> Option<Vec<String>> {
+        (self.ask)(challenge);
+        self.answers.recv().await
+    }
 }
 
 /// Why a connection attempt failed.
@@ -90,13 +194,17 @@ struct Verdict {
     learn: Option<PublicKey>,
 }
 
-/// Applies the trust policy during the handshake.
+/// Where each granted `-R` port sends its connections: server port to `(host, port)` here.
+type RemoteRoutes = Arc<Mutex<HashMap<u32, (String, u16)>>>;
+
+/// Applies the trust policy during the handshake, and routes `-R` connections afterwards.
 struct Verifier {
     host: String,
     port: u16,
     known_hosts: PathBuf,
     approved_fingerprint: Option<String>,
     verdict: Arc<Mutex<Verdict>>,
+    routes: RemoteRoutes,
 }
 
 impl client::Handler for Verifier {
@@ -106,24 +214,32 @@ impl client::Handler for Verifier {
         let algorithm = key.algorithm().to_string();
         let fingerprint = knownhosts::fingerprint(key);
 
+        let refuse = |rejection| {
+            if let Ok(mut verdict) = self.verdict.lock() {
+                verdict.rejection = Some(rejection);
+            }
+            Ok(false)
+        };
         match knownhosts::check(&self.host, self.port, key, &self.known_hosts) {
             Trust::Known => {
                 debug!("host key for {}:{} is known", self.host, self.port);
                 Ok(true)
             }
             // Never accepted, and never promptable.
-            Trust::Changed { line } => {
-                if let Ok(mut verdict) = self.verdict.lock() {
-                    verdict.rejection = Some(Rejection::Changed {
-                        host: self.host.clone(),
-                        port: self.port,
-                        line,
-                        algorithm,
-                        fingerprint,
-                    });
-                }
-                Ok(false)
-            }
+            Trust::Changed { line } => refuse(Rejection::Changed {
+                host: self.host.clone(),
+                port: self.port,
+                line,
+                algorithm,
+                fingerprint,
+            }),
+            Trust::Revoked { line } => refuse(Rejection::Revoked {
+                host: self.host.clone(),
+                port: self.port,
+                line,
+                algorithm,
+                fingerprint,
+            }),
             Trust::Unknown => {
                 // Accept only if the user approved this exact key.
                 if self.approved_fingerprint.as_deref() == Some(fingerprint.as_str()) {
@@ -132,19 +248,209 @@ impl client::Handler for Verifier {
                     }
                     Ok(true)
                 } else {
-                    if let Ok(mut verdict) = self.verdict.lock() {
-                        verdict.rejection = Some(Rejection::Unknown {
-                            host: self.host.clone(),
-                            port: self.port,
-                            algorithm,
-                            fingerprint,
-                        });
+                    refuse(Rejection::Unknown {
+                        host: self.host.clone(),
+                        port: self.port,
+                        algorithm,
+                        fingerprint,
+                    })
+                }
+            }
+        }
+    }
+
+    /// A connection to one of our `-R` ports. Anything the server sends for a port we did not
+    /// ask for is refused.
+    async fn server_channel_open_forwarded_tcpip(
+        &mut self,
+        channel: Channel<Msg>,
+        _connected_address: &str,
+        connected_port: u32,
+        _originator_address: &str,
+        _originator_port: u32,
+        reply: ChannelOpenHandle,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        let route = self
+            .routes
+            .lock()
+            .ok()
+            .and_then(|routes| routes.get(&connected_port).cloned());
+        let Some((host, port)) = route else {
+            reply.reject(ChannelOpenFailure::AdministrativelyProhibited).await;
+            return Ok(());
+        };
+        // Connecting can take a while, and the session's event loop must not wait for it.
+        tokio::spawn(async move {
+            match TcpStream::connect((host.as_str(), port)).await {
+                Ok(socket) => {
+                    reply.accept().await;
+                    splice(channel, socket).await;
+                }
+                Err(e) => {
+                    debug!("-R {connected_port}: could not reach {host}:{port}: {e}");
+                    reply.reject(ChannelOpenFailure::ConnectFailed).await;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Copy between a forwarded channel and a TCP connection until either side is done.
+async fn splice(channel: Channel<Msg>, mut socket: TcpStream) {
+    let mut stream = channel.into_stream();
+    let _ = tokio::io::copy_bidirectional(&mut stream, &mut socket).await;
+}
+
+/// The port forwards running on a connection.
+#[derive(Default)]
+struct Forwards {
+    /// Accept loops for `-L` ports. Aborted on drop, so a transport that is dropped rather
+    /// than closed still frees its ports.
+    listeners: Vec<JoinHandle<()>>,
+    /// `-R` ports the server granted, to cancel on close.
+    remote: Vec<u32>,
+}
+
+impl Forwards {
+    /// Cancel the `-R` ports and close the `-L` ones, waiting until they are actually free.
+    async fn stop(&mut self, handle: &Handle<Verifier>) {
+        for port in self.remote.drain(..) {
+            let _ = handle.cancel_tcpip_forward("localhost", port).await;
+        }
+        for listener in self.listeners.drain(..) {
+            listener.abort();
+            let _ = listener.await;
+        }
+    }
+}
+
+impl Drop for Forwards {
+    fn drop(&mut self) {
+        for listener in &self.listeners {
+            listener.abort();
+        }
+    }
+}
+
+/// Start every forward in `forwards`. One that cannot be opened — a port already in use, a
+/// server that refuses — does not take the shell down with it; it is reported instead.
+async fn open_forwards(
+    handle: &Arc<Handle<Verifier>>,
+    forwards: &[Forward],
+    routes: &RemoteRoutes,
+) -> (Forwards, Vec<String>) {
+    let mut opened = Forwards::default();
+    let mut warnings = Vec::new();
+    for forward in forwards {
+        // Port 0 would be whatever port was free, which nobody could then be told to use.
+        if forward.listen_port == 0 {
+            warnings.push(format!("{}: choose a port to listen on", forward.spec()));
+            continue;
+        }
+        let destination = (forward.host.trim().to_owned(), forward.port);
+        match forward.direction {
+            ForwardDirection::Local => {
+                match TcpListener::bind(("127.0.0.1", forward.listen_port)).await {
+                    Ok(listener) => opened.listeners.push(tokio::spawn(accept_local(
+                        listener,
+                        Arc::downgrade(handle),
+                        destination,
+                    ))),
+                    Err(e) => warnings.push(format!("{}: {e}", forward.spec())),
+                }
+            }
+            ForwardDirection::Remote => {
+                let port = forward.listen_port as u32;
+                // Routed before asking, so a connection that arrives with the reply is not lost.
+                if let Ok(mut routes) = routes.lock() {
+                    routes.insert(port, destination);
+                }
+                match handle.tcpip_forward("localhost", port).await {
+                    Ok(_) => opened.remote.push(port),
+                    Err(e) => {
+                        if let Ok(mut routes) = routes.lock() {
+                            routes.remove(&port);
+                        }
+                        warnings.push(format!("{}: the server refused: {e}", forward.spec()));
                     }
-                    Ok(false)
                 }
             }
         }
     }
+    (opened, warnings)
+}
+
+/// Accept connections on a `-L` port and carry each to `destination` from the server's side.
+/// Holds the connection weakly: the transport owns it, and a listener must not keep it open.
+async fn accept_local(
+    listener: TcpListener,
+    handle: Weak<Handle<Verifier>>,
+    destination: (String, u16),
+) {
+    while let Ok((socket, peer)) = listener.accept().await {
+        let Some(handle) = handle.upgrade() else {
+            return;
+        };
+        let (host, port) = destination.clone();
+        tokio::spawn(async move {
+            let opened = handle
+                .channel_open_direct_tcpip(
+                    host.clone(),
+                    port as u32,
+                    peer.ip().to_string(),
+                    peer.port() as u32,
+                )
+                .await;
+            drop(handle);
+            match opened {
+                Ok(channel) => splice(channel, socket).await,
+                Err(e) => debug!("-L to {host}:{port} was refused: {e}"),
+            }
+        });
+    }
+}
+
+/// How long a server gets to answer the SFTP handshake. One with the subsystem disabled may
+/// refuse the request without closing the channel, which would otherwise wait forever.
+const SFTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// An SFTP session on a channel of its own.
+pub type SftpChannel = Sftp<russh::ChannelStream<Msg>>;
+
+/// A tab's SSH connection, for opening further channels on it. Held weakly: the tab owns the
+/// connection, and it ends when the tab disconnects however many of these are around.
+#[derive(Clone)]
+pub struct Connection(Weak<Handle<Verifier>>);
+
+impl Connection {
+    /// Whether both refer to the same live connection, rather than one that was replaced by a
+    /// reconnect.
+    pub fn same_as(&self, other: &Connection) -> bool {
+        Weak::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Start SFTP on a new channel, reusing the tab's authenticated connection.
+    pub async fn open_sftp(&self) -> Result<SftpChannel, String> {
+        let handle = self
+            .0
+            .upgrade()
+            .ok_or_else(|| "The SSH connection has closed.".to_owned())?;
+        let channel = handle
+            .channel_open_session()
+            .await
+            .map_err(|e| format!("Could not open a channel for SFTP: {e}"))?;
+        drop(handle);
+        channel
+            .request_subsystem(true, "sftp")
+            .await
+            .map_err(|e| format!("Could not start SFTP: {e}"))?;
+        match tokio::time::timeout(SFTP_TIMEOUT, Sftp::start(channel.into_stream())).await {
+            Ok(started) => started,
+            Err(_) => Err("The server did not start SFTP. Is its sftp subsystem enabled?".into()),
+        }
+    }
 }
 
 /// A live SSH shell.
@@ -154,8 +460,18 @@ impl client::Handler for Verifier {
 pub struct SshTransport {
     read: ChannelReadHalf,
     write: ChannelWriteHalf<Msg>,
-    /// Kept alive: dropping the handle tears down the connection.
-    handle: Handle<Verifier>,
+    /// Kept alive: dropping the handle tears down the connection. Shared only weakly, with
+    /// the `-L` listeners.
+    handle: Arc<Handle<Verifier>>,
+    /// The jump hosts, first hop first. The target's connection runs inside these.
+    jumps: Vec<Handle<Verifier>>,
+    forwards: Forwards,
+    /// Forwards that could not be opened, until the session reports them.
+    warnings: Vec<String>,
+    /// The command being run, or `None` for a shell.
+    command: Option<String>,
+    /// Whether the channel has a PTY, and so a size to keep up to date.
+    pty: bool,
 }
 
 impl std::fmt::Debug for SshTransport {
@@ -165,8 +481,39 @@ impl std::fmt::Debug for SshTransport {
 }
 
 impl SshTransport {
-    pub fn read_half(&mut self) -> &mut ChannelReadHalf {
-        &mut self.read
+    /// Both stdout and stderr are terminal output. The end of output is not the end of the
+    /// session: the exit status follows it, and is what the tab should report.
+    pub async fn recv(&mut self) -> Incoming {
+        loop {
+            return match self.read.wait().await {
+                Some(ChannelMsg::Data { data }) => Incoming::Data(data.to_vec()),
+                Some(ChannelMsg::ExtendedData { data, .. }) => Incoming::Data(data.to_vec()),
+                Some(ChannelMsg::ExitStatus { exit_status }) => Incoming::Closed(Some(format!(
+                    "{} exited with status {exit_status}.",
+                    self.what()
+                ))),
+                Some(ChannelMsg::ExitSignal { signal_name, .. }) => {
+                    let signal = match signal_name {
+                        Sig::Custom(name) => name,
+                        signal => format!("{signal:?}"),
+                    };
+                    Incoming::Closed(Some(format!("{} was killed by SIG{signal}.", self.what())))
+                }
+                Some(ChannelMsg::Close) | None => Incoming::Closed(Some(
+                    "The remote host closed the connection.".to_owned(),
+                )),
+                // End of output, window adjustments and the like: nothing to show.
+                Some(_) => continue,
+            };
+        }
+    }
+
+    /// What is running, for the exit message.
+    fn what(&self) -> String {
+        match &self.command {
+            Some(command) => format!("`{command}`"),
+            None => "The remote shell".to_owned(),
+        }
     }
 
     pub async fn send(&mut self, data: &[u8]) -> Result<(), String> {
@@ -176,21 +523,41 @@ impl SshTransport {
             .map_err(|e| format!("SSH write failed: {e}"))
     }
 
-    /// Tell the remote end the terminal changed size, so full-screen programs reflow.
+    /// The connection, for the file panel.
+    pub fn connection(&self) -> Connection {
+        Connection(Arc::downgrade(&self.handle))
+    }
+
+    /// Tell the remote end the terminal changed size, so full-screen programs reflow. Without
+    /// a PTY there is no terminal to resize.
     pub async fn resize(&mut self, columns: u16, rows: u16) -> Result<(), String> {
+        if !self.pty {
+            return Ok(());
+        }
         self.write
             .window_change(columns as u32, rows as u32, 0, 0)
             .await
             .map_err(|e| format!("SSH window change failed: {e}"))
     }
 
-    /// Best-effort clean shutdown.
-    pub async fn close(self) {
+    /// Problems worth telling the user about that did not stop the connection.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Best-effort clean shutdown, from the target back out through each jump host.
+    pub async fn close(mut self) {
+        self.forwards.stop(&self.handle).await;
         let _ = self.write.eof().await;
         let _ = self
             .handle
             .disconnect(Disconnect::ByApplication, "closed by user", "")
             .await;
+        for jump in self.jumps.iter().rev() {
+            let _ = jump
+                .disconnect(Disconnect::ByApplication, "closed by user", "")
+                .await;
+        }
     }
 }
 
@@ -200,6 +567,7 @@ pub async fn connect(
     credentials: Credentials,
     approved_fingerprint: Option<String>,
     known_hosts: PathBuf,
+    mut prompter: Prompter,
     columns: u16,
     rows: u16,
 ) -> Result<SshTransport, Error> {
@@ -209,22 +577,127 @@ pub async fn connect(
         ..Default::default()
     });
 
+    // Each hop is reached through the one before it, and the target through the last.
+    let mut jumps: Vec<Handle<Verifier>> = Vec::new();
+    let mut previous = None;
+    for jump in &settings.jump_hosts {
+        let hop = settings.for_jump(jump);
+        let via = jumps.last().zip(previous.as_deref());
+        let handle = establish(
+            &config,
+            &hop,
+            via,
+            &credentials.for_jump(hop.auth),
+            approved_fingerprint.as_deref(),
+            &known_hosts,
+            &mut prompter,
+            RemoteRoutes::default(),
+        )
+        .await?;
+        jumps.push(handle);
+        previous = Some(hop.host);
+    }
+    let via = jumps.last().zip(previous.as_deref());
+    let routes = RemoteRoutes::default();
+    let handle = establish(
+        &config,
+        &settings,
+        via,
+        &credentials,
+        approved_fingerprint.as_deref(),
+        &known_hosts,
+        &mut prompter,
+        Arc::clone(&routes),
+    )
+    .await?;
+    let handle = Arc::new(handle);
+
+    let channel = handle
+        .channel_open_session()
+        .await
+        .map_err(|e| Error::Other(format!("Could not open a session channel: {e}")))?;
+
+    let command = Some(settings.command.trim().to_owned()).filter(|c| !c.is_empty());
+    let pty = command.is_none() || settings.command_pty;
+    if pty {
+        request_pty(&channel, &settings, columns, rows).await?;
+    }
+    match &command {
+        None => channel
+            .request_shell(true)
+            .await
+            .map_err(|e| Error::Other(format!("Could not start a shell: {e}")))?,
+        Some(command) => channel
+            .exec(true, command.as_str())
+            .await
+            .map_err(|e| Error::Other(format!("Could not run `{command}`: {e}")))?,
+    }
+    let (forwards, warnings) = open_forwards(&handle, &settings.forwards, &routes).await;
+
+    let (read, write) = channel.split();
+    Ok(SshTransport {
+        read,
+        write,
+        handle,
+        jumps,
+        forwards,
+        warnings,
+        command,
+        pty,
+    })
+}
+
+/// Open one SSH connection, verify its host key and log in. The connection is made over TCP,
+/// or through `via` — an established jump host and its name — when there is one.
+#[allow(clippy::too_many_arguments)]
+async fn establish(
+    config: &Arc<client::Config>,
+    settings: &SshSettings,
+    via: Option<(&Handle<Verifier>, &str)>,
+    credentials: &Credentials,
+    approved_fingerprint: Option<&str>,
+    known_hosts: &Path,
+    prompter: &mut Prompter,
+    routes: RemoteRoutes,
+) -> Result<Handle<Verifier>, Error> {
     let verdict = Arc::new(Mutex::new(Verdict::default()));
     let verifier = Verifier {
         host: settings.host.clone(),
         port: settings.port,
-        known_hosts: known_hosts.clone(),
-        approved_fingerprint,
+        known_hosts: known_hosts.to_path_buf(),
+        approved_fingerprint: approved_fingerprint.map(str::to_owned),
         verdict: Arc::clone(&verdict),
+        routes,
     };
 
-    let mut handle = match client::connect(
-        config,
-        (settings.host.as_str(), settings.port),
-        verifier,
-    )
-    .await
-    {
+    let connected = match via {
+        None => {
+            client::connect(
+                Arc::clone(config),
+                (settings.host.as_str(), settings.port),
+                verifier,
+            )
+            .await
+        }
+        Some((jump, name)) => {
+            let tunnel = jump
+                .channel_open_direct_tcpip(
+                    settings.host.clone(),
+                    settings.port as u32,
+                    "127.0.0.1",
+                    0,
+                )
+                .await
+                .map_err(|e| {
+                    Error::Other(format!(
+                        "{name} could not reach {}:{}: {e}",
+                        settings.host, settings.port
+                    ))
+                })?;
+            client::connect_stream(Arc::clone(config), tunnel.into_stream(), verifier).await
+        }
+    };
+    let mut handle = match connected {
         Ok(handle) => handle,
         Err(e) => {
             // A refused host key surfaces here as a generic handshake failure, so the
@@ -242,39 +715,202 @@ pub async fn connect(
     // The handshake succeeded, so an approved key can now be recorded.
     let to_learn = verdict.lock().ok().and_then(|v| v.learn.clone());
     if let Some(key) = to_learn {
-        if let Err(e) = knownhosts::learn(&settings.host, settings.port, &key, &known_hosts) {
+        let learn = if settings.hash_known_hosts {
+            knownhosts::learn_hashed
+        } else {
+            knownhosts::learn
+        };
+        if let Err(e) = learn(&settings.host, settings.port, &key, known_hosts) {
             // Not fatal: the connection is up, the user will just be asked again next time.
             tracing::warn!("could not record host key: {e}");
         }
     }
 
-    authenticate(&mut handle, &settings, &credentials).await?;
+    authenticate(&mut handle, settings, credentials, prompter).await?;
+    Ok(handle)
+}
 
-    let channel = handle
-        .channel_open_session()
+/// Authenticate with the configured method, then with whatever else the server asks for.
+///
+/// A server that wants several factors accepts the first with *partial success* and names the
+/// methods still open. Keyboard-interactive is taken whenever it is offered after a partial
+/// success, since that is where one-time codes arrive, and once as a fallback after an outright
+/// failure, which is how PAM-backed servers ask for a password. A password already typed is
+/// offered when a chain asks for one. A password login with none to offer, as on a jump host,
+/// asks the user first.
+async fn authenticate(
+    handle: &mut Handle<Verifier>,
+    settings: &SshSettings,
+    credentials: &Credentials,
+    prompter: &mut Prompter,
+) -> Result<(), Error> {
+    let asked;
+    let credentials = match settings.auth {
+        SshAuth::Password if credentials.password.is_empty() => {
+            asked = Credentials {
+                password: ask_password(settings, prompter).await?,
+                passphrase: String::new(),
+            };
+            &asked
+        }
+        _ => credentials,
+    };
+    let mut result = first_factor(handle, settings, credentials).await?;
+    let mut method = settings.auth.label();
+    let mut tried = vec![match settings.auth {
+        SshAuth::Password => MethodKind::Password,
+        SshAuth::PublicKey | SshAuth::Agent => MethodKind::PublicKey,
+    }];
+
+    for _ in 0..MAX_AUTH_ROUNDS {
+        let AuthResult::Failure {
+            remaining_methods,
+            partial_success,
+        } = &result
+        else {
+            return Ok(());
+        };
+        let offered = |kind| remaining_methods.contains(&kind);
+        let next = if offered(MethodKind::KeyboardInteractive)
+            && (*partial_success || !tried.contains(&MethodKind::KeyboardInteractive))
+        {
+            MethodKind::KeyboardInteractive
+        } else if *partial_success
+            && offered(MethodKind::Password)
+            && !credentials.password.is_empty()
+            && !tried.contains(&MethodKind::Password)
+        {
+            MethodKind::Password
+        } else if *partial_success {
+            return Err(Error::Auth(format!(
+                "{} accepted {method} authentication but wants another factor UniTerm cannot \
+                 provide.",
+                settings.host
+            )));
+        } else {
+            break;
+        };
+
+        tried.push(next);
+        // A fallback after an outright failure is still, to the user, the method they chose.
+        if *partial_success {
+            method = match next {
+                MethodKind::Password => "Password",
+                _ => "Keyboard-interactive",
+            };
+        }
+        result = match next {
+            MethodKind::Password => password(handle, settings, credentials).await?,
+            _ => keyboard_interactive(handle, settings, prompter).await?,
+        };
+    }
+
+    if result.success() {
+        Ok(())
+    } else {
+        Err(Error::Auth(format!(
+            "{method} authentication was rejected by {}.",
+            settings.host
+        )))
+    }
+}
+
+async fn password(
+    handle: &mut Handle<Verifier>,
+    settings: &SshSettings,
+    credentials: &Credentials,
+) -> Result<AuthResult, Error> {
+    handle
+        .authenticate_password(settings.user.clone(), credentials.password.clone())
         .await
-        .map_err(|e| Error::Other(format!("Could not open a session channel: {e}")))?;
+        .map_err(|e| Error::Auth(format!("Password authentication failed: {e}")))
+}
 
-    start_shell(&channel, &settings, columns, rows).await?;
+/// Ask the user for the password of a host the tab holds none for: a jump host, whose
+/// password is its own.
+async fn ask_password(settings: &SshSettings, prompter: &mut Prompter) -> Result<String, Error> {
+    let challenge = Challenge {
+        host: settings.host.clone(),
+        name: String::new(),
+        instructions: String::new(),
+        prompts: vec![ChallengePrompt {
+            text: format!("Password for {}@{}:", settings.user, settings.host),
+            echo: false,
+        }],
+    };
+    prompter
+        .answer(challenge)
+        .await
+        .and_then(|answers| answers.into_iter().next())
+        .ok_or_else(|| Error::Auth("Authentication was cancelled.".to_owned()))
+}
 
-    let (read, write) = channel.split();
-    Ok(SshTransport {
-        read,
-        write,
-        handle,
-    })
+/// Answer the server's prompts until it decides. A round with no prompts is acknowledged
+/// without bothering the user.
+async fn keyboard_interactive(
+    handle: &mut Handle<Verifier>,
+    settings: &SshSettings,
+    prompter: &mut Prompter,
+) -> Result<AuthResult, Error> {
+    let failed = |e: russh::Error| Error::Auth(format!("Keyboard-interactive failed: {e}"));
+    let mut response = handle
+        .authenticate_keyboard_interactive_start(settings.user.clone(), None)
+        .await
+        .map_err(failed)?;
+    loop {
+        match response {
+            KeyboardInteractiveAuthResponse::Success => return Ok(AuthResult::Success),
+            KeyboardInteractiveAuthResponse::Failure {
+                remaining_methods,
+                partial_success,
+            } => {
+                return Ok(AuthResult::Failure {
+                    remaining_methods,
+                    partial_success,
+                })
+            }
+            KeyboardInteractiveAuthResponse::InfoRequest {
+                name,
+                instructions,
+                prompts,
+            } => {
+                let answers = if prompts.is_empty() {
+                    Vec::new()
+                } else {
+                    let challenge = Challenge {
+                        host: settings.host.clone(),
+                        name,
+                        instructions,
+                        prompts: prompts
+                            .into_iter()
+                            .map(|p| ChallengePrompt {
+                                text: p.prompt,
+                                echo: p.echo,
+                            })
+                            .collect(),
+                    };
+                    prompter
+                        .answer(challenge)
+                        .await
+                        .ok_or_else(|| Error::Auth("Authentication was cancelled.".to_owned()))?
+                };
+                response = handle
+                    .authenticate_keyboard_interactive_respond(answers)
+                    .await
+                    .map_err(failed)?;
+            }
+        }
+    }
 }
 
-async fn authenticate(
+/// The configured method: the first factor, and for most servers the only one.
+async fn first_factor(
     handle: &mut Handle<Verifier>,
     settings: &SshSettings,
     credentials: &Credentials,
-) -> Result<(), Error> {
-    let result = match settings.auth {
-        SshAuth::Password => handle
-            .authenticate_password(settings.user.clone(), credentials.password.clone())
-            .await
-            .map_err(|e| Error::Auth(format!("Password authentication failed: {e}")))?,
+) -> Result<AuthResult, Error> {
+    Ok(match settings.auth {
+        SshAuth::Password => password(handle, settings, credentials).await?,
 
         SshAuth::PublicKey => {
             let path = settings
@@ -293,42 +929,213 @@ async fn authenticate(
                     path.display()
                 ))
             })?;
+            let key = Arc::new(key);
 
-            // RSA keys must be signed with a hash the server actually accepts.
-            let hash = handle
-                .best_supported_rsa_hash()
-                .await
-                .map_err(|e| Error::Auth(format!("Could not negotiate a signature hash: {e}")))?
-                .flatten();
+            if let Some(certificate) =
+                load_certificate(path, &key, now_seconds()).map_err(Error::Auth)?
+            {
+                let result = handle
+                    .authenticate_openssh_cert(settings.user.clone(), key.clone(), certificate)
+                    .await
+                    .map_err(|e| Error::Auth(format!("Certificate authentication failed: {e}")))?;
+                // A host that does not trust the CA may still list the key itself.
+                let refused = matches!(
+                    &result,
+                    AuthResult::Failure { remaining_methods, partial_success: false }
+                        if remaining_methods.contains(&MethodKind::PublicKey)
+                );
+                if !refused {
+                    return Ok(result);
+                }
+                debug!("certificate refused; offering the bare key");
+            }
 
+            let hash = rsa_hash(handle).await?;
             handle
                 .authenticate_publickey(
                     settings.user.clone(),
-                    PrivateKeyWithHashAlg::new(Arc::new(key), hash),
+                    PrivateKeyWithHashAlg::new(key, hash),
                 )
                 .await
                 .map_err(|e| Error::Auth(format!("Key authentication failed: {e}")))?
         }
+
+        SshAuth::Agent => authenticate_with_agent(handle, settings).await?,
+    })
+}
+
+/// Where the OpenSSH certificate for `key_path` lives, if it has one.
+pub fn certificate_path(key_path: &Path) -> PathBuf {
+    let mut name = key_path.as_os_str().to_owned();
+    name.push("-cert.pub");
+    PathBuf::from(name)
+}
+
+/// The user certificate beside `key_path`, checked against `key` and the clock. `None` when
+/// there is no certificate file, which is the usual case.
+fn load_certificate(
+    key_path: &Path,
+    key: &PrivateKey,
+    now: u64,
+) -> Result<Option<Certificate>, String> {
+    let path = certificate_path(key_path);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Could not read {}: {e}", path.display())),
     };
+    let name = path.display();
+    let certificate = Certificate::from_openssh(text.trim())
+        .map_err(|e| format!("{name} is not an OpenSSH certificate: {e}"))?;
+    if certificate.public_key() != key.public_key().key_data() {
+        return Err(format!(
+            "{name} certifies a different key than {}.",
+            key_path.display()
+        ));
+    }
+    if certificate.cert_type() != CertType::User {
+        return Err(format!("{name} is a host certificate, not a user certificate."));
+    }
+    if now >= certificate.valid_before() {
+        return Err(format!(
+            "{name} expired {}. Ask your CA for a new one.",
+            relative_time(now, certificate.valid_before())
+        ));
+    }
+    if now < certificate.valid_after() {
+        return Err(format!("{name} is not valid yet. Check this computer's clock."));
+    }
+    debug!("offering certificate {:?} for {}", certificate.key_id(), key_path.display());
+    Ok(Some(certificate))
+}
 
-    if result.success() {
-        Ok(())
-    } else {
-        Err(Error::Auth(format!(
-            "{} authentication was rejected by {}.",
-            settings.auth.label(),
-            settings.host
-        )))
+/// The hash to sign with if the key is RSA: one the server actually accepts.
+async fn rsa_hash(handle: &Handle<Verifier>) -> Result<Option<HashAlg>, Error> {
+    Ok(handle
+        .best_supported_rsa_hash()
+        .await
+        .map_err(|e| Error::Auth(format!("Could not negotiate a signature hash: {e}")))?
+        .flatten())
+}
+
+/// Offer each key the agent holds until the server takes one, as OpenSSH does.
+#[cfg(unix)]
+async fn authenticate_with_agent(
+    handle: &mut Handle<Verifier>,
+    settings: &SshSettings,
+) -> Result<AuthResult, Error> {
+    let socket = settings
+        .agent_socket
+        .clone()
+        .or_else(|| std::env::var_os("SSH_AUTH_SOCK").map(PathBuf::from))
+        .ok_or_else(|| Error::Auth("No ssh-agent found: SSH_AUTH_SOCK is not set.".to_owned()))?;
+    let mut agent = AgentClient::connect_uds(&socket).await.map_err(|e| {
+        Error::Auth(format!("Could not reach ssh-agent at {}: {e}", socket.display()))
+    })?;
+    let identities = agent
+        .request_identities()
+        .await
+        .map_err(|e| Error::Auth(format!("ssh-agent would not list its keys: {e}")))?;
+    let hash = rsa_hash(handle).await?;
+    let mut signer = AgentSigner {
+        agent: &mut agent,
+        failed: None,
+    };
+    let mut result = None;
+    // Whether any key got as far as the server's verdict, and why the last one that did not
+    // could not sign.
+    let (mut answered, mut unsigned) = (false, None);
+    for identity in identities {
+        debug!("offering agent key {}", identity.comment());
+        signer.failed = None;
+        let attempt = handle
+            .authenticate_publickey_with(
+                settings.user.clone(),
+                identity.public_key().into_owned(),
+                hash,
+                &mut signer,
+            )
+            .await
+            .map_err(|e| Error::Auth(format!("ssh-agent could not sign: {e}")))?;
+        if attempt.success() {
+            return Ok(attempt);
+        }
+        result = Some(attempt);
+        match signer.failed.take() {
+            Some(e) => unsigned = Some(e),
+            None => answered = true,
+        }
+    }
+    match (result, unsigned) {
+        (Some(_), Some(e)) if !answered => {
+            Err(Error::Auth(format!("ssh-agent could not sign with any of its keys: {e}")))
+        }
+        (Some(result), _) => Ok(result),
+        (None, _) => Err(Error::Auth("ssh-agent holds no keys. Add one with ssh-add.".to_owned())),
+    }
+}
+
+/// The agent, as a signer that never leaves a login hanging.
+///
+/// One key failing to sign (a token unplugged, a confirmation declined) says nothing about
+/// the others, so it should only cost that key's turn. But russh 0.62 waits for a signature
+/// once it has asked for one and drops everything else until it arrives, so an error here
+/// would wedge the session. A key the agent cannot sign with is answered with a blank
+/// signature instead — well-formed, so the server refuses it like any wrong key rather than
+/// dropping the connection — and the agent's error is kept in case no key gets in.
+#[cfg(unix)]
+struct AgentSigner<'a> {
+    agent: &'a mut AgentClient<tokio::net::UnixStream>,
+    /// Why the agent could not sign with the key on offer, if it could not.
+    failed: Option<russh::keys::Error>,
+}
+
+#[cfg(unix)]
+impl russh::Signer for AgentSigner<'_> {
+    type Error = russh::AgentAuthError;
+
+    async fn auth_sign(
+        &mut self,
+        key: &russh::keys::agent::AgentIdentity,
+        hash_alg: Option<HashAlg>,
+        to_sign: Vec<u8>,
+    ) -> Result<Vec<u8>, Self::Error> {
+        use russh::keys::ssh_encoding::Encode;
+        use russh::keys::ssh_key::{Algorithm, Signature};
+
+        match self.agent.sign_request(key, hash_alg, to_sign.clone()).await {
+            Ok(signed) => Ok(signed),
+            Err(e) => {
+                tracing::warn!("ssh-agent could not sign with {}: {e}", key.comment());
+                self.failed = Some(e);
+                let blank = Signature::new(Algorithm::Ed25519, vec![0; 64])
+                    .map_err(russh::keys::Error::from)?
+                    .encode_vec()
+                    .map_err(russh::keys::Error::from)?;
+                let mut unsigned = to_sign;
+                unsigned.extend((blank.len() as u32).to_be_bytes());
+                unsigned.extend(blank);
+                Ok(unsigned)
+            }
+        }
     }
 }
 
-/// Request a PTY and start a shell on it.
+#[cfg(not(unix))]
+async fn authenticate_with_agent(
+    _handle: &mut Handle<Verifier>,
+    _settings: &SshSettings,
+) -> Result<AuthResult, Error> {
+    Err(Error::Auth("ssh-agent is only supported on Linux and macOS.".to_owned()))
+}
+
+/// Request a PTY for the shell or command about to start.
 ///
 /// `want_reply` is set, but russh does not block for the reply: a refusal arrives later as a
 /// channel failure message rather than an error here. A server that refuses a PTY therefore
 /// yields a line-mode shell instead of an error — rare enough to accept, and visible to the
 /// user as a shell that behaves oddly rather than as silent data loss.
-async fn start_shell(
+async fn request_pty(
     channel: &Channel<Msg>,
     settings: &SshSettings,
     columns: u16,
@@ -345,19 +1152,80 @@ async fn start_shell(
             &[],
         )
         .await
-        .map_err(|e| Error::Other(format!("Could not allocate a PTY: {e}")))?;
-
-    channel
-        .request_shell(true)
-        .await
-        .map_err(|e| Error::Other(format!("Could not start a shell: {e}")))?;
-
-    Ok(())
+        .map_err(|e| Error::Other(format!("Could not allocate a PTY: {e}")))
 }
 
 #[cfg(test)]
-mod tests {
+pub(super) mod tests {
     use super::*;
+    use russh::keys::ssh_key::certificate::Builder;
+    use russh::keys::ssh_key::private::Ed25519Keypair;
+
+    /// A fixed ed25519 key, so certificates can be made without an RNG.
+    pub(in crate::session) fn fixed_key(seed: u8) -> PrivateKey {
+        PrivateKey::from(Ed25519Keypair::from_seed(&[seed; 32]))
+    }
+
+    /// A user certificate for `subject` signed by `ca`, valid from `valid_after` until just
+    /// before `valid_before`, as `ssh-keygen -s` would write it.
+    pub(in crate::session) fn certify(
+        subject: &PrivateKey,
+        ca: &PrivateKey,
+        valid_after: u64,
+        valid_before: u64,
+    ) -> String {
+        let subject = subject.public_key().key_data().clone();
+        let mut builder = Builder::new([0; 16], subject, valid_after, valid_before).unwrap();
+        builder.cert_type(CertType::User).unwrap();
+        builder.key_id("tester@lab").unwrap();
+        builder.valid_principal("tester").unwrap();
+        builder.sign(ca).unwrap().to_openssh().unwrap()
+    }
+
+    /// A key path with `certificate` written beside it.
+    fn key_with_certificate(name: &str, certificate: &str) -> PathBuf {
+        let key_path = std::env::temp_dir().join(format!("uniterm_cert_{name}"));
+        std::fs::write(certificate_path(&key_path), certificate).unwrap();
+        key_path
+    }
+
+    #[test]
+    fn the_certificate_is_found_beside_the_key() {
+        assert_eq!(
+            certificate_path(Path::new("/home/me/.ssh/id_ed25519")),
+            Path::new("/home/me/.ssh/id_ed25519-cert.pub")
+        );
+        let lone = std::env::temp_dir().join("uniterm_cert_none");
+        let _ = std::fs::remove_file(certificate_path(&lone));
+        assert_eq!(load_certificate(&lone, &fixed_key(1), 1_000).unwrap(), None);
+    }
+
+    #[test]
+    fn a_current_certificate_for_the_key_is_offered() {
+        let (key, ca) = (fixed_key(1), fixed_key(2));
+        let key_path = key_with_certificate("current", &certify(&key, &ca, 1_000, 5_000));
+        let certificate = load_certificate(&key_path, &key, 2_000).unwrap().unwrap();
+        assert_eq!(certificate.key_id(), "tester@lab");
+        assert_eq!(certificate.signature_key(), ca.public_key().key_data());
+        let _ = std::fs::remove_file(certificate_path(&key_path));
+    }
+
+    #[test]
+    fn an_unusable_certificate_says_why() {
+        let (key, ca) = (fixed_key(1), fixed_key(2));
+        let key_path = key_with_certificate("stale", &certify(&key, &ca, 1_000, 5_000));
+        let expired = load_certificate(&key_path, &key, 5_000 + 7_200).unwrap_err();
+        assert!(expired.contains("expired 2 hours ago"), "{expired}");
+        let early = load_certificate(&key_path, &key, 500).unwrap_err();
+        assert!(early.contains("not valid yet"), "{early}");
+        let other = load_certificate(&key_path, &fixed_key(3), 2_000).unwrap_err();
+        assert!(other.contains("different key"), "{other}");
+
+        std::fs::write(certificate_path(&key_path), "not a certificate").unwrap();
+        let garbled = load_certificate(&key_path, &key, 2_000).unwrap_err();
+        assert!(garbled.contains("not an OpenSSH certificate"), "{garbled}");
+        let _ = std::fs::remove_file(certificate_path(&key_path));
+    }
 
     #[test]
     fn password_auth_needs_a_password() {
@@ -0,0 +1,634 @@
+//! Telnet transport.
+//!
+//! Telnet is a TCP stream with commands interleaved in-band, each introduced by an IAC byte
+//! (0xFF). [`Codec`] separates the two: only data comes out of [`Codec::decode`] to reach the
+//! terminal buffer and the log, and negotiation is answered as a side effect.
+//!
+//! Only what a console needs is negotiated:
+//!
+//! * **ECHO** and **SUPPRESS-GO-AHEAD** from the server, which together are character-at-a-time
+//!   mode with the server echoing — what a shell expects, and what the terminal view assumes,
+//!   since it never echoes locally.
+//! * **NAWS**, so the server knows the window size, and is told again on every resize the way
+//!   SSH sends `window_change`.
+//! * **TERMINAL-TYPE**, answered with the configured `term`.
+//!
+//! Everything else is refused. Each option's state is tracked per side, so an offer is
+//! answered once rather than acknowledged back and forth forever (the loop RFC 854 warns of).
+//!
+//! The same codec carries RFC 2217 remote serial ports, in [`Codec::com_port`] mode; see
+//! [`super::rfc2217`] for what differs.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::rfc2217::{self, COM_PORT_OPTION};
+use super::transport::{self, Incoming, READ_BUFFER};
+use crate::settings::{SerialSettings, TelnetSettings};
+
+pub(super) const IAC: u8 = 255;
+pub(super) const DONT: u8 = 254;
+pub(super) const DO: u8 = 253;
+pub(super) const WONT: u8 = 252;
+pub(super) const WILL: u8 = 251;
+pub(super) const SB: u8 = 250;
+pub(super) const SE: u8 = 240;
+
+pub(super) const BINARY: u8 = 0;
+const ECHO: u8 = 1;
+pub(super) const SGA: u8 = 3;
+const TTYPE: u8 = 24;
+const NAWS: u8 = 31;
+
+const TTYPE_IS: u8 = 0;
+const TTYPE_SEND: u8 = 1;
+
+/// Longest subnegotiation kept. The only one acted on is a few bytes; anything bigger is not
+/// something this client asked for and is truncated rather than buffered without bound.
+const MAX_SUBNEGOTIATION: usize = 256;
+
+/// Where the decoder is within the stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Data,
+    /// Just passed a CR, which on the wire is followed by NUL when it means a bare CR.
+    Cr,
+    Iac,
+    /// Saw IAC and one of WILL/WONT/DO/DONT; the option code comes next.
+    Command(u8),
+    Sub,
+    SubIac,
+}
+
+/// One side's view of one option.
+#[derive(Clone, Copy, Debug, Default)]
+struct OptionState {
+    enabled: bool,
+    /// We asked and are waiting for the answer, so the answer must not be answered.
+    requested: bool,
+}
+
+/// What one chunk of the stream decoded to.
+#[derive(Debug, Default)]
+pub struct Decoded {
+    /// Terminal data with every Telnet command removed.
+    pub data: Vec<u8>,
+    /// Negotiation replies to write back to the server.
+    pub reply: Vec<u8>,
+}
+
+/// Telnet protocol state: stream decoding and option negotiation.
+pub struct Codec {
+    state: State,
+    sub: Vec<u8>,
+    term: String,
+    columns: u16,
+    rows: u16,
+    /// The line parameters to apply, when this is a remote serial port rather than a terminal
+    /// session.
+    line: Option<SerialSettings>,
+    /// Options this end performs (answered with WILL/WONT), by option code. Boxed so the
+    /// tables do not bloat every [`transport::Transport`].
+    local: Box<[OptionState; 256]>,
+    /// Options the server performs (answered with DO/DONT), by option code.
+    remote: Box<[OptionState; 256]>,
+}
+
+impl Codec {
+    pub fn new(term: String, columns: u16, rows: u16) -> Self {
+        Self {
+            state: State::Data,
+            sub: Vec::new(),
+            term,
+            columns,
+            rows,
+            line: None,
+            local: Box::new([OptionState::default(); 256]),
+            remote: Box::new([OptionState::default(); 256]),
+        }
+    }
+
+    /// A codec for an RFC 2217 port: binary in both directions, and COM-PORT-OPTION in place
+    /// of the terminal options.
+    pub fn com_port(line: SerialSettings) -> Self {
+        Self {
+            line: Some(line),
+            ..Self::new(String::new(), 0, 0)
+        }
+    }
+
+    /// The opening offers, sent as soon as the socket is up.
+    ///
+    /// Many console servers wait for the client to speak first, and NAWS in particular is only
+    /// useful if the size is known before the login shell starts.
+    pub fn start(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let (local, remote): (&[u8], &[u8]) = if self.line.is_some() {
+            (&[BINARY, COM_PORT_OPTION], &[BINARY, SGA])
+        } else {
+            (&[NAWS, TTYPE], &[SGA])
+        };
+        for &option in local {
+            self.local[option as usize].requested = true;
+            out.extend([IAC, WILL, option]);
+        }
+        for &option in remote {
+            self.remote[option as usize].requested = true;
+            out.extend([IAC, DO, option]);
+        }
+        out
+    }
+
+    /// Split received bytes into terminal data and negotiation replies.
+    ///
+    /// Commands may be cut anywhere by the TCP segmentation, so state carries across calls.
+    pub fn decode(&mut self, input: &[u8]) -> Decoded {
+        let mut out = Decoded::default();
+        for &byte in input {
+            match self.state {
+                State::Data => self.data(byte, &mut out.data),
+                State::Cr => {
+                    // CR NUL is how the wire spells a bare CR; the NUL is not data. In binary
+                    // mode there is no such convention and every byte counts.
+                    self.state = State::Data;
+                    if byte != 0 || self.remote[BINARY as usize].enabled {
+                        self.data(byte, &mut out.data);
+                    }
+                }
+                State::Iac => {
+                    self.state = match byte {
+                        // An escaped 0xFF data byte.
+                        IAC => {
+                            out.data.push(IAC);
+                            State::Data
+                        }
+                        WILL | WONT | DO | DONT => State::Command(byte),
+                        SB => {
+                            self.sub.clear();
+                            State::Sub
+                        }
+                        // NOP, GA, AYT and the rest carry nothing to show.
+                        _ => State::Data,
+                    }
+                }
+                State::Command(command) => {
+                    self.state = State::Data;
+                    self.negotiate(command, byte, &mut out.reply);
+                }
+                State::Sub => {
+                    if byte == IAC {
+                        self.state = State::SubIac;
+                    } else if self.sub.len() < MAX_SUBNEGOTIATION {
+                        self.sub.push(byte);
+                    }
+                }
+                State::SubIac => match byte {
+                    SE => {
+                        self.state = State::Data;
+                        self.subnegotiation(&mut out.reply);
+                    }
+                    IAC => {
+                        self.state = State::Sub;
+                        if self.sub.len() < MAX_SUBNEGOTIATION {
+                            self.sub.push(IAC);
+                        }
+                    }
+                    // Malformed: abandon the subnegotiation rather than swallow what follows.
+                    _ => self.state = State::Data,
+                },
+            }
+        }
+        out
+    }
+
+    /// Record a new window size, returning the NAWS update to send if the server asked for
+    /// them.
+    pub fn resize(&mut self, columns: u16, rows: u16) -> Option<Vec<u8>> {
+        self.columns = columns;
+        self.rows = rows;
+        self.local[NAWS as usize].enabled.then(|| self.naws())
+    }
+
+    /// Record new line parameters, returning the COM-PORT-OPTION commands to send if the
+    /// server has agreed to take them. Until it has, they are held and sent on agreement.
+    pub fn set_line(&mut self, line: &SerialSettings) -> Option<Vec<u8>> {
+        self.line.as_ref()?;
+        self.line = Some(line.clone());
+        self.local[COM_PORT_OPTION as usize]
+            .enabled
+            .then(|| rfc2217::encode_line(line))
+    }
+
+    /// Prepare outgoing terminal data for the wire: 0xFF is escaped, and outside binary mode a
+    /// bare CR becomes CR NUL so the server does not wait for the LF it would otherwise expect.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let binary = self.local[BINARY as usize].enabled;
+        let mut out = Vec::with_capacity(data.len() + 1);
+        for (i, &byte) in data.iter().enumerate() {
+            out.push(byte);
+            match byte {
+                IAC => out.push(IAC),
+                b'\r' if !binary && data.get(i + 1) != Some(&b'\n') => out.push(0),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Options this end will perform.
+    fn supports_local(&self, option: u8) -> bool {
+        if self.line.is_some() {
+            matches!(option, BINARY | COM_PORT_OPTION)
+        } else {
+            matches!(option, NAWS | TTYPE)
+        }
+    }
+
+    /// Options the server may perform. A device server echoing would corrupt the data, so
+    /// ECHO is for terminal sessions only.
+    fn supports_remote(&self, option: u8) -> bool {
+        if self.line.is_some() {
+            matches!(option, BINARY | SGA)
+        } else {
+            matches!(option, ECHO | SGA)
+        }
+    }
+
+    fn data(&mut self, byte: u8, data: &mut Vec<u8>) {
+        match byte {
+            IAC => self.state = State::Iac,
+            b'\r' => {
+                data.push(byte);
+                self.state = State::Cr;
+            }
+            _ => data.push(byte),
+        }
+    }
+
+    fn negotiate(&mut self, command: u8, option: u8, reply: &mut Vec<u8>) {
+        match command {
+            DO => {
+                let supported = self.supports_local(option);
+                let state = &mut self.local[option as usize];
+                if !supported {
+                    reply.extend([IAC, WONT, option]);
+                } else if !state.enabled {
+                    let asked = state.requested;
+                    *state = OptionState {
+                        enabled: true,
+                        requested: false,
+                    };
+                    if !asked {
+                        reply.extend([IAC, WILL, option]);
+                    }
+                    if option == NAWS {
+                        reply.extend(self.naws());
+                    }
+                    if let (COM_PORT_OPTION, Some(line)) = (option, &self.line) {
+                        reply.extend(rfc2217::encode_line(line));
+                    }
+                }
+            }
+            DONT => {
+                let state = &mut self.local[option as usize];
+                // Refusing our own offer needs no answer; withdrawing an agreed one does.
+                if state.enabled && !state.requested {
+                    reply.extend([IAC, WONT, option]);
+                }
+                *state = OptionState::default();
+            }
+            WILL => {
+                let supported = self.supports_remote(option);
+                let state = &mut self.remote[option as usize];
+                if !supported {
+                    reply.extend([IAC, DONT, option]);
+                } else if !state.enabled {
+                    let asked = state.requested;
+                    *state = OptionState {
+                        enabled: true,
+                        requested: false,
+                    };
+                    if !asked {
+                        reply.extend([IAC, DO, option]);
+                    }
+                }
+            }
+            WONT => {
+                let state = &mut self.remote[option as usize];
+                if state.enabled && !state.requested {
+                    reply.extend([IAC, DONT, option]);
+                }
+                *state = OptionState::default();
+            }
+            _ => {}
+        }
+    }
+
+    fn subnegotiation(&mut self, reply: &mut Vec<u8>) {
+        if self.sub.as_slice() == [TTYPE, TTYPE_SEND] {
+            reply.extend([IAC, SB, TTYPE, TTYPE_IS]);
+            reply.extend(escape(self.term.as_bytes()));
+            reply.extend([IAC, SE]);
+        }
+    }
+
+    fn naws(&self) -> Vec<u8> {
+        let mut out = vec![IAC, SB, NAWS];
+        let [c_hi, c_lo] = self.columns.to_be_bytes();
+        let [r_hi, r_lo] = self.rows.to_be_bytes();
+        out.extend(escape(&[c_hi, c_lo, r_hi, r_lo]));
+        out.extend([IAC, SE]);
+        out
+    }
+}
+
+/// Double every 0xFF so it is read as data rather than as the start of a command.
+pub(super) fn escape(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        out.push(byte);
+        if byte == IAC {
+            out.push(IAC);
+        }
+    }
+    out
+}
+
+/// A live Telnet connection.
+pub struct TelnetTransport {
+    stream: TcpStream,
+    codec: Codec,
+    buffer: Vec<u8>,
+}
+
+impl TelnetTransport {
+    pub async fn recv(&mut self) -> Incoming {
+        match self.stream.read(&mut self.buffer).await {
+            Ok(0) => Incoming::Closed(Some("The remote host closed the connection.".to_owned())),
+            Ok(count) => {
+                let decoded = self.codec.decode(&self.buffer[..count]);
+                if !decoded.reply.is_empty() {
+                    if let Err(e) = self.stream.write_all(&decoded.reply).await {
+                        return Incoming::Closed(Some(format!("Write failed: {e}")));
+                    }
+                }
+                // May be empty when the chunk was all negotiation; the pump skips those.
+                Incoming::Data(decoded.data)
+            }
+            Err(e) => Incoming::Closed(Some(format!("Read failed: {e}"))),
+        }
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.stream
+            .write_all(&self.codec.encode(data))
+            .await
+            .map_err(|e| format!("Write failed: {e}"))
+    }
+
+    /// Send a NAWS update, if the server asked for window sizes at all.
+    pub async fn resize(&mut self, columns: u16, rows: u16) -> Result<(), String> {
+        match self.codec.resize(columns, rows) {
+            Some(naws) => self
+                .stream
+                .write_all(&naws)
+                .await
+                .map_err(|e| format!("Telnet window size update failed: {e}")),
+            None => Ok(()),
+        }
+    }
+
+    /// Push new line parameters to a remote serial port. A no-op on a terminal session.
+    pub async fn set_line(&mut self, line: &SerialSettings) -> Result<(), String> {
+        match self.codec.set_line(line) {
+            Some(commands) => self
+                .stream
+                .write_all(&commands)
+                .await
+                .map_err(|e| format!("Updating the remote port settings failed: {e}")),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.stream.shutdown().await;
+    }
+}
+
+/// Connect and send the opening offers. The rest of the negotiation happens as data arrives.
+pub async fn connect(
+    settings: &TelnetSettings,
+    columns: u16,
+    rows: u16,
+) -> Result<TelnetTransport, String> {
+    let codec = Codec::new(settings.term.clone(), columns, rows);
+    open(&settings.host, settings.port, &settings.address(), codec).await
+}
+
+/// Dial, send the codec's opening offers, and wrap the result.
+pub(super) async fn open(
+    host: &str,
+    port: u16,
+    address: &str,
+    mut codec: Codec,
+) -> Result<TelnetTransport, String> {
+    let mut stream = transport::connect_tcp(host, port, address).await?;
+    stream
+        .write_all(&codec.start())
+        .await
+        .map_err(|e| format!("Write failed: {e}"))?;
+    Ok(TelnetTransport {
+        stream,
+        codec,
+        buffer: vec![0; READ_BUFFER],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn codec() -> Codec {
+        Codec::new("xterm-256color".to_owned(), 80, 24)
+    }
+
+    #[test]
+    fn commands_never_reach_the_data() {
+        let mut codec = codec();
+        let decoded = codec.decode(&[b'a', IAC, WILL, ECHO, b'b', IAC, 241, b'c']);
+        assert_eq!(decoded.data, b"abc");
+    }
+
+    #[test]
+    fn an_escaped_ff_is_one_data_byte() {
+        let mut codec = codec();
+        assert_eq!(codec.decode(&[1, IAC, IAC, 2]).data, [1, 0xff, 2]);
+    }
+
+    #[test]
+    fn commands_split_across_reads_are_still_recognised() {
+        let mut codec = codec();
+        let mut data = Vec::new();
+        let mut reply = Vec::new();
+        for chunk in [&[b'x', IAC][..], &[DO], &[NAWS, b'y'], &[IAC], &[IAC]] {
+            let decoded = codec.decode(chunk);
+            data.extend(decoded.data);
+            reply.extend(decoded.reply);
+        }
+        assert_eq!(data, [b'x', b'y', 0xff]);
+        assert!(reply.starts_with(&[IAC, WILL, NAWS]), "{reply:?}");
+    }
+
+    #[test]
+    fn cr_nul_is_a_bare_cr() {
+        let mut codec = codec();
+        assert_eq!(codec.decode(b"a\r\0b\r\nc").data, b"a\rb\r\nc");
+        // Split between the two.
+        assert_eq!(codec.decode(b"\r").data, b"\r");
+        assert_eq!(codec.decode(b"\0d").data, b"d");
+    }
+
+    #[test]
+    fn subnegotiations_are_stripped() {
+        let mut codec = codec();
+        let decoded = codec.decode(&[b'a', IAC, SB, 99, 1, 2, IAC, IAC, 3, IAC, SE, b'b']);
+        assert_eq!(decoded.data, b"ab");
+        assert!(decoded.reply.is_empty(), "unknown subnegotiations are ignored");
+    }
+
+    #[test]
+    fn echo_and_sga_are_accepted_once() {
+        let mut codec = codec();
+        assert_eq!(codec.decode(&[IAC, WILL, ECHO]).reply, [IAC, DO, ECHO]);
+        // A repeat must not be acknowledged again, or two implementations loop forever.
+        assert!(codec.decode(&[IAC, WILL, ECHO]).reply.is_empty());
+    }
+
+    #[test]
+    fn answers_to_our_own_offers_are_not_answered() {
+        let mut codec = codec();
+        let offers = codec.start();
+        assert_eq!(
+            offers,
+            [IAC, WILL, NAWS, IAC, WILL, TTYPE, IAC, DO, SGA],
+            "the opening offers"
+        );
+        assert!(codec.decode(&[IAC, WILL, SGA]).reply.is_empty());
+        assert!(codec.decode(&[IAC, DO, TTYPE]).reply.is_empty());
+        assert!(codec.decode(&[IAC, DONT, NAWS]).reply.is_empty());
+    }
+
+    #[test]
+    fn unsupported_options_are_refused() {
+        let mut codec = codec();
+        // LINEMODE and BINARY.
+        assert_eq!(codec.decode(&[IAC, DO, 34]).reply, [IAC, WONT, 34]);
+        assert_eq!(codec.decode(&[IAC, WILL, 0]).reply, [IAC, DONT, 0]);
+    }
+
+    #[test]
+    fn terminal_type_is_sent_on_request() {
+        let mut codec = codec();
+        codec.start();
+        codec.decode(&[IAC, DO, TTYPE]);
+        let reply = codec.decode(&[IAC, SB, TTYPE, TTYPE_SEND, IAC, SE]).reply;
+        let mut expected = vec![IAC, SB, TTYPE, TTYPE_IS];
+        expected.extend(b"xterm-256color");
+        expected.extend([IAC, SE]);
+        assert_eq!(reply, expected);
+    }
+
+    #[test]
+    fn naws_is_sent_when_agreed_and_on_every_resize() {
+        let mut codec = codec();
+        assert_eq!(codec.resize(100, 30), None, "not before the server asks");
+        let reply = codec.decode(&[IAC, DO, NAWS]).reply;
+        assert_eq!(
+            reply,
+            [IAC, WILL, NAWS, IAC, SB, NAWS, 0, 100, 0, 30, IAC, SE]
+        );
+        assert_eq!(
+            codec.resize(132, 43),
+            Some(vec![IAC, SB, NAWS, 0, 132, 0, 43, IAC, SE])
+        );
+    }
+
+    #[test]
+    fn naws_escapes_ff_in_the_size() {
+        let mut codec = codec();
+        codec.decode(&[IAC, DO, NAWS]);
+        assert_eq!(
+            codec.resize(255, 0x01ff),
+            Some(vec![IAC, SB, NAWS, 0, 255, 255, 1, 255, 255, IAC, SE])
+        );
+    }
+
+    #[test]
+    fn withdrawn_naws_stops_updates() {
+        let mut codec = codec();
+        codec.decode(&[IAC, DO, NAWS]);
+        assert_eq!(codec.decode(&[IAC, DONT, NAWS]).reply, [IAC, WONT, NAWS]);
+        assert_eq!(codec.resize(90, 30), None);
+    }
+
+    #[test]
+    fn outgoing_data_is_escaped() {
+        let codec = codec();
+        assert_eq!(codec.encode(&[1, 0xff, 2]), [1, 0xff, 0xff, 2]);
+        assert_eq!(codec.encode(b"ls\r\n"), b"ls\r\n");
+        assert_eq!(codec.encode(b"\r"), b"\r\0");
+    }
+
+    #[tokio::test]
+    async fn telnet_strips_negotiation_and_answers_it() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = TelnetSettings {
+            host: "127.0.0.1".to_owned(),
+            port: listener.local_addr().unwrap().port(),
+            ..Default::default()
+        };
+
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // The client's opening offers.
+            let mut offers = [0u8; 9];
+            socket.read_exact(&mut offers).await.unwrap();
+            assert_eq!(offers, [IAC, WILL, NAWS, IAC, WILL, TTYPE, IAC, DO, SGA]);
+
+            socket
+                .write_all(&[IAC, DO, NAWS, IAC, WILL, ECHO, b'o', b'k', IAC, IAC])
+                .await
+                .unwrap();
+            // The initial NAWS, then DO ECHO.
+            let mut reply = [0u8; 12];
+            socket.read_exact(&mut reply).await.unwrap();
+            assert_eq!(
+                reply,
+                [IAC, SB, NAWS, 0, 80, 0, 24, IAC, SE, IAC, DO, ECHO]
+            );
+            // Then whatever the terminal sends next: a resize, then a keystroke.
+            let mut rest = [0u8; 11];
+            socket.read_exact(&mut rest).await.unwrap();
+            rest
+        });
+
+        let mut transport = connect(&settings, 80, 24).await.unwrap();
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            match transport.recv().await {
+                Incoming::Data(data) => received.extend(data),
+                Incoming::Closed(reason) => panic!("closed early: {reason:?}"),
+            }
+        }
+        assert_eq!(received, [b'o', b'k', 0xff]);
+
+        transport.resize(100, 40).await.unwrap();
+        transport.send(b"\r").await.unwrap();
+        assert_eq!(
+            server.await.unwrap(),
+            [IAC, SB, NAWS, 0, 100, 0, 40, IAC, SE, b'\r', 0]
+        );
+        transport.close().await;
+    }
+}
@@ -0,0 +1,41 @@
+//! Waiting helpers shared by the session tests that run a stand-in server in-process.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::Session;
+
+/// How long either helper waits before failing the test, in 10ms steps.
+const STEPS: usize = 600;
+
+/// Wait until the server has observed something, or fail the test.
+pub(super) async fn wait_for<T>(observed: &Mutex<T>, ready: impl Fn(&T) -> bool) {
+    for _ in 0..STEPS {
+        if ready(&observed.lock().unwrap()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the server never observed the expected request");
+}
+
+/// Pump the session until `ready`, or fail. This is what the UI does each frame.
+pub(super) async fn poll_until(
+    session: &mut Session,
+    ctx: &eframe::egui::Context,
+    label: &str,
+    ready: impl Fn(&Session) -> bool,
+) {
+    let handle = tokio::runtime::Handle::current();
+    for _ in 0..STEPS {
+        session.poll(&handle, ctx);
+        if ready(session) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!(
+        "timed out waiting for {label}; state={:?} error={:?}",
+        session.state, session.last_error
+    );
+}
@@ -1,20 +1,44 @@
-//! Transport abstraction over serial and SSH.
+//! Transport abstraction over serial, SSH, raw TCP, Telnet (which also carries RFC 2217), UDP,
+//! Unix sockets, and local programs on a terminal or on plain pipes.
 //!
 //! An enum rather than a trait object: async trait methods are not dyn-compatible, and with
-//! two variants an enum is both simpler and cheaper than pulling in `async-trait`.
+//! a handful of variants an enum is both simpler and cheaper than pulling in `async-trait`.
 //!
 //! The session loop is written against this, so adding the reconnect button (plan task 3)
 //! means driving one state machine rather than two.
 
-use russh::ChannelMsg;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use socket2::{SockRef, TcpKeepalive};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::Child;
 use tokio_serial::SerialStream;
 
+use super::pipe::PipeTransport;
+#[cfg(unix)]
+use super::pty::PtyTransport;
 use super::ssh::SshTransport;
+use super::telnet::TelnetTransport;
+use super::udp::UdpTransport;
+#[cfg(unix)]
+use super::unix::UnixTransport;
 use crate::discovery;
+use crate::settings::SerialSettings;
+
+/// Size of each serial or socket read.
+pub(super) const READ_BUFFER: usize = 8192;
+
+/// How long to wait for a TCP connect before giving up. The OS default can be minutes.
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
-/// Size of each serial read.
-const READ_BUFFER: usize = 8192;
+/// Idle time before the first TCP keepalive probe, and the gap between probes.
+///
+/// A raw socket has no protocol-level heartbeat, so without this a device server that loses
+/// power leaves the tab showing connected until the next write. The cadence matches the SSH
+/// keepalive.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(15);
 
 /// What came back from the link.
 pub enum Incoming {
@@ -28,28 +52,45 @@ pub enum Incoming {
 pub enum Transport {
     Serial(SerialTransport),
     Ssh(SshTransport),
+    Tcp(TcpTransport),
+    Telnet(TelnetTransport),
+    #[cfg(unix)]
+    Local(PtyTransport),
+    Pipe(PipeTransport),
+    Udp(UdpTransport),
+    #[cfg(unix)]
+    UnixSocket(UnixTransport),
 }
 
 impl Transport {
+    /// Whether each [`Incoming::Data`] is one whole datagram rather than an arbitrary slice of
+    /// a stream.
+    pub fn is_datagram(&self) -> bool {
+        matches!(self, Self::Udp(_))
+    }
+
+    /// Problems from opening the link that did not stop it, such as an SSH port forward whose
+    /// port was taken.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        match self {
+            Self::Ssh(ssh) => ssh.take_warnings(),
+            _ => Vec::new(),
+        }
+    }
+
     /// Wait for the next bytes, or for the link to end.
     pub async fn recv(&mut self) -> Incoming {
         match self {
             Self::Serial(serial) => serial.recv().await,
-            Self::Ssh(ssh) => {
-                // Both stdout and stderr of the remote shell are terminal output.
-                match ssh.read_half().wait().await {
-                    Some(ChannelMsg::Data { data }) => Incoming::Data(data.to_vec()),
-                    Some(ChannelMsg::ExtendedData { data, .. }) => Incoming::Data(data.to_vec()),
-                    Some(ChannelMsg::Eof | ChannelMsg::Close) | None => Incoming::Closed(Some(
-                        "The remote host closed the connection.".to_owned(),
-                    )),
-                    Some(ChannelMsg::ExitStatus { exit_status }) => Incoming::Closed(Some(
-                        format!("The remote shell exited with status {exit_status}."),
-                    )),
-                    // Window adjustments and the like: nothing to show.
-                    Some(_) => Incoming::Data(Vec::new()),
-                }
-            }
+            Self::Tcp(tcp) => tcp.recv().await,
+            Self::Telnet(telnet) => telnet.recv().await,
+            #[cfg(unix)]
+            Self::Local(pty) => pty.recv().await,
+            Self::Pipe(pipe) => pipe.recv().await,
+            Self::Udp(udp) => udp.recv().await,
+            #[cfg(unix)]
+            Self::UnixSocket(unix) => unix.recv().await,
+            Self::Ssh(ssh) => ssh.recv().await,
         }
     }
 
@@ -57,15 +98,39 @@ impl Transport {
         match self {
             Self::Serial(serial) => serial.send(data).await,
             Self::Ssh(ssh) => ssh.send(data).await,
+            Self::Tcp(tcp) => tcp.send(data).await,
+            Self::Telnet(telnet) => telnet.send(data).await,
+            #[cfg(unix)]
+            Self::Local(pty) => pty.send(data).await,
+            Self::Pipe(pipe) => pipe.send(data).await,
+            Self::Udp(udp) => udp.send(data).await,
+            #[cfg(unix)]
+            Self::UnixSocket(unix) => unix.send(data).await,
         }
     }
 
-    /// Tell the far end the terminal size changed. A no-op for serial, which has no concept
-    /// of one.
+    /// Tell the far end the terminal size changed. A no-op for serial, raw TCP, pipes, UDP and
+    /// Unix sockets, which have no concept of one.
     pub async fn resize(&mut self, columns: u16, rows: u16) -> Result<(), String> {
         match self {
-            Self::Serial(_) => Ok(()),
+            Self::Serial(_) | Self::Tcp(_) | Self::Pipe(_) | Self::Udp(_) => Ok(()),
             Self::Ssh(ssh) => ssh.resize(columns, rows).await,
+            Self::Telnet(telnet) => telnet.resize(columns, rows).await,
+            #[cfg(unix)]
+            Self::Local(pty) => pty.resize(columns, rows),
+            #[cfg(unix)]
+            Self::UnixSocket(_) => Ok(()),
+        }
+    }
+
+    /// Apply new line parameters to a remote serial port. A no-op for everything else: RFC
+    /// 2217 rides on the Telnet transport, and only there is there a far end to tell.
+    pub async fn set_line(&mut self, line: &SerialSettings) -> Result<(), String> {
+        match self {
+            Self::Telnet(telnet) => telnet.set_line(line).await,
+            Self::Serial(_) | Self::Ssh(_) | Self::Tcp(_) | Self::Pipe(_) | Self::Udp(_) => Ok(()),
+            #[cfg(unix)]
+            Self::Local(_) | Self::UnixSocket(_) => Ok(()),
         }
     }
 
@@ -73,18 +138,73 @@ impl Transport {
     ///
     /// Serial polls for the port still being present, which is how an unplugged USB adapter
     /// is noticed. SSH relies on russh's keepalive, which surfaces a dead link by closing the
-    /// channel, so there is nothing to poll.
+    /// channel, and TCP and Telnet on the socket keepalive, which surfaces it as a read error,
+    /// so there is nothing to poll. A local program ends the session through its output
+    /// closing; the check only catches one that exited while something it started holds the
+    /// output open. UDP has no link to lose, and a Unix socket reports its peer going away as
+    /// end-of-file.
     pub async fn check_alive(&mut self) -> Option<String> {
         match self {
             Self::Serial(serial) => serial.check_alive().await,
-            Self::Ssh(_) => None,
+            Self::Ssh(_) | Self::Tcp(_) | Self::Telnet(_) | Self::Udp(_) => None,
+            #[cfg(unix)]
+            Self::Local(pty) => pty.check_alive(),
+            Self::Pipe(pipe) => pipe.check_alive(),
+            #[cfg(unix)]
+            Self::UnixSocket(_) => None,
         }
     }
 
     pub async fn close(self) {
         match self {
-            Self::Serial(_) => {}
+            Self::Serial(_) | Self::Udp(_) => {}
             Self::Ssh(ssh) => ssh.close().await,
+            Self::Tcp(tcp) => tcp.close().await,
+            Self::Telnet(telnet) => telnet.close().await,
+            #[cfg(unix)]
+            Self::Local(pty) => pty.close().await,
+            Self::Pipe(pipe) => pipe.close().await,
+            #[cfg(unix)]
+            Self::UnixSocket(unix) => unix.close().await,
+        }
+    }
+}
+
+/// How a local process ended, as the reason shown in the tab.
+pub(super) fn exit_reason(what: &str, status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("{what} exited with status {code}.");
+    }
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return format!("{what} was killed by signal {signal}.");
+    }
+    format!("{what} ended.")
+}
+
+/// How long a local process's output may stay open after it exits before the session ends
+/// anyway. Plenty to drain what it wrote last; short enough that a background job still
+/// holding the output does not keep a finished tab open.
+pub(super) const ORPHAN_GRACE: Duration = Duration::from_secs(2);
+
+/// When a local process was first seen to have exited, for the liveness tick.
+///
+/// The exit itself is not the end of the session: output it wrote just before exiting may
+/// still be unread, and reading reaches end-of-file once it is drained. Only output held open
+/// past [`ORPHAN_GRACE`] by something else ends the session from here.
+#[derive(Default)]
+pub(super) struct Exited(Option<(Instant, ExitStatus)>);
+
+impl Exited {
+    pub(super) fn check(&mut self, child: &mut Child, what: &str) -> Option<String> {
+        if self.0.is_none() {
+            if let Ok(Some(status)) = child.try_wait() {
+                self.0 = Some((Instant::now(), status));
+            }
+        }
+        match self.0 {
+            Some((at, status)) if at.elapsed() >= ORPHAN_GRACE => Some(exit_reason(what, status)),
+            _ => None,
         }
     }
 }
@@ -152,3 +272,139 @@ pub fn open_serial(settings: &crate::settings::SerialSettings) -> Result<Transpo
         Err(e) => Err(format!("Unable to open {}: {e}", settings.name)),
     }
 }
+
+/// A live raw TCP socket.
+pub struct TcpTransport {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buffer: vec![0; READ_BUFFER],
+        }
+    }
+
+    async fn recv(&mut self) -> Incoming {
+        match self.stream.read(&mut self.buffer).await {
+            Ok(0) => Incoming::Closed(Some("The remote host closed the connection.".to_owned())),
+            Ok(count) => Incoming::Data(self.buffer[..count].to_vec()),
+            Err(e) => Incoming::Closed(Some(format!("Read failed: {e}"))),
+        }
+    }
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.stream
+            .write_all(data)
+            .await
+            .map_err(|e| format!("Write failed: {e}"))
+    }
+
+    async fn close(mut self) {
+        // Send a FIN rather than just dropping, so the far end sees an orderly close.
+        let _ = self.stream.shutdown().await;
+    }
+}
+
+/// Connect a raw TCP socket.
+pub async fn open_tcp(settings: &crate::settings::TcpSettings) -> Result<Transport, String> {
+    let stream = connect_tcp(&settings.host, settings.port, &settings.address()).await?;
+    Ok(Transport::Tcp(TcpTransport::new(stream)))
+}
+
+/// Dial a TCP stream with a bounded wait, tuned for interactive use. Shared by every transport
+/// that runs over plain TCP.
+pub(super) async fn connect_tcp(host: &str, port: u16, address: &str) -> Result<TcpStream, String> {
+    let stream = match tokio::time::timeout(TCP_CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+    {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(format!("Unable to connect to {address}: {e}")),
+        Err(_) => return Err(format!("Timed out connecting to {address}.")),
+    };
+    // Interactive traffic is a keystroke at a time; do not hold it back for coalescing.
+    let _ = stream.set_nodelay(true);
+    let keepalive = TcpKeepalive::new()
+        .with_time(TCP_KEEPALIVE)
+        .with_interval(TCP_KEEPALIVE);
+    if let Err(e) = SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+        // Still usable, just slower to notice a dead peer.
+        tracing::warn!("could not enable keepalive on {address}: {e}");
+    }
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TcpSettings;
+    use tokio::net::TcpListener;
+
+    async fn listener() -> (TcpListener, TcpSettings) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let settings = TcpSettings {
+            host: "127.0.0.1".to_owned(),
+            port,
+        };
+        (listener, settings)
+    }
+
+    #[tokio::test]
+    async fn tcp_round_trips_bytes_unchanged() {
+        let (listener, settings) = listener().await;
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket.write_all(&buf[..n]).await.unwrap();
+        });
+
+        let mut transport = open_tcp(&settings).await.unwrap();
+        // Bytes a telnet or line-discipline layer would mangle go through untouched.
+        let payload = [0xff, 0xfd, 0x01, b'\r', b'\n', 0x00];
+        transport.send(&payload).await.unwrap();
+        let mut received = Vec::new();
+        while received.len() < payload.len() {
+            match transport.recv().await {
+                Incoming::Data(data) => received.extend(data),
+                Incoming::Closed(reason) => panic!("closed early: {reason:?}"),
+            }
+        }
+        assert_eq!(received, payload);
+        server.await.unwrap();
+        transport.close().await;
+    }
+
+    #[tokio::test]
+    async fn tcp_reports_the_peer_closing() {
+        let (listener, settings) = listener().await;
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            drop(socket);
+        });
+
+        let mut transport = open_tcp(&settings).await.unwrap();
+        server.await.unwrap();
+        loop {
+            match transport.recv().await {
+                Incoming::Data(data) => assert!(data.is_empty()),
+                Incoming::Closed(reason) => {
+                    assert!(reason.unwrap().contains("closed"));
+                    break;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn tcp_connect_failure_names_the_address() {
+        // Bind then drop, so the port is (almost certainly) not listening.
+        let (listener, settings) = listener().await;
+        drop(listener);
+        let error = open_tcp(&settings).await.err().unwrap();
+        assert!(error.contains(&settings.address()), "{error}");
+    }
+}
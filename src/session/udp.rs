@@ -0,0 +1,200 @@
+//! UDP transport.
+//!
+//! Binds a local port and, when a peer is given, connects to it. A connected socket only
+//! hears from that peer; an unconnected one hears from anyone and answers whoever spoke last,
+//! which is how a telemetry stream from a device whose address is not known up front is
+//! answered at all.
+//!
+//! Each datagram is handed up whole, and each send goes out as exactly one, so the session can
+//! keep packet boundaries visible.
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::net::UdpSocket;
+
+use super::transport::Incoming;
+use crate::settings::UdpSettings;
+
+/// Largest possible UDP payload. A smaller buffer would silently truncate big datagrams.
+const MAX_DATAGRAM: usize = 65_536;
+
+/// A bound UDP socket.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    /// Whether the socket is connected to a fixed peer.
+    connected: bool,
+    /// Who sent the last datagram, for replies on an unconnected socket.
+    last_sender: Option<SocketAddr>,
+    buffer: Vec<u8>,
+}
+
+impl UdpTransport {
+    pub async fn recv(&mut self) -> Incoming {
+        loop {
+            let received = if self.connected {
+                self.socket.recv(&mut self.buffer).await
+            } else {
+                self.socket
+                    .recv_from(&mut self.buffer)
+                    .await
+                    .map(|(count, sender)| {
+                        self.last_sender = Some(sender);
+                        count
+                    })
+            };
+            match received {
+                Ok(count) => return Incoming::Data(self.buffer[..count].to_vec()),
+                // An ICMP "port unreachable" for an earlier send, reported on the next read.
+                // UDP has no connection to lose, so a peer that is not up yet is not an error.
+                Err(e) if is_unreachable(&e) => continue,
+                Err(e) => return Incoming::Closed(Some(format!("Read failed: {e}"))),
+            }
+        }
+    }
+
+    /// Send `data` as one datagram.
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        let sent = match (self.connected, self.last_sender) {
+            (true, _) => self.socket.send(data).await,
+            (false, Some(peer)) => self.socket.send_to(data, peer).await,
+            (false, None) => {
+                let reason = "Nothing has been received yet, so there is no one to send to.";
+                return Err(reason.to_owned());
+            }
+        };
+        match sent {
+            Ok(count) if count == data.len() => Ok(()),
+            Ok(count) => Err(format!("Only {count} of {} bytes were sent.", data.len())),
+            Err(e) if is_unreachable(&e) => Err("The peer is not listening.".to_owned()),
+            Err(e) => Err(format!("Send failed: {e}")),
+        }
+    }
+}
+
+fn is_unreachable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Bind the local port and, if a peer is set, connect to it.
+pub async fn open(settings: &UdpSettings) -> Result<UdpTransport, String> {
+    let host = settings.host.trim();
+    let peer = if host.is_empty() {
+        None
+    } else {
+        let address = settings.address();
+        let resolved = tokio::net::lookup_host((host, settings.port))
+            .await
+            .map_err(|e| format!("Unable to resolve {address}: {e}"))?
+            .next()
+            .ok_or_else(|| format!("{address} did not resolve to any address."))?;
+        Some(resolved)
+    };
+
+    // Bound to the peer's address family. Listening alone binds IPv4, which is what telemetry
+    // devices overwhelmingly send.
+    let local = match peer {
+        Some(SocketAddr::V6(_)) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, settings.bind_port)),
+        _ => SocketAddr::from((Ipv4Addr::UNSPECIFIED, settings.bind_port)),
+    };
+    let socket = UdpSocket::bind(local)
+        .await
+        .map_err(|e| format!("Unable to listen on UDP port {}: {e}", settings.bind_port))?;
+    if let Some(peer) = peer {
+        socket
+            .connect(peer)
+            .await
+            .map_err(|e| format!("Unable to reach {}: {e}", settings.address()))?;
+    }
+
+    Ok(UdpTransport {
+        socket,
+        connected: peer.is_some(),
+        last_sender: None,
+        buffer: vec![0; MAX_DATAGRAM],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn recv(udp: &mut UdpTransport) -> Vec<u8> {
+        match tokio::time::timeout(Duration::from_secs(10), udp.recv())
+            .await
+            .expect("timed out waiting for a datagram")
+        {
+            Incoming::Data(data) => data,
+            Incoming::Closed(reason) => panic!("closed: {reason:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn datagrams_arrive_whole_and_replies_go_to_the_last_sender() {
+        let mut udp = open(&UdpSettings::default()).await.unwrap();
+        let port = udp.socket.local_addr().unwrap().port();
+        let device = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        assert!(udp.send(b"early").await.is_err(), "no one to reply to yet");
+
+        device.send_to(b"one", ("127.0.0.1", port)).await.unwrap();
+        device.send_to(&[0xff, 0, b'\r'], ("127.0.0.1", port)).await.unwrap();
+        assert_eq!(recv(&mut udp).await, b"one");
+        assert_eq!(recv(&mut udp).await, [0xff, 0, b'\r']);
+
+        udp.send(b"ack").await.unwrap();
+        let mut buf = [0u8; 16];
+        let (count, _) = device.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..count], b"ack");
+    }
+
+    #[tokio::test]
+    async fn a_connected_socket_only_hears_its_peer() {
+        let device = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let stranger = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut udp = open(&UdpSettings {
+            bind_port: 0,
+            host: "127.0.0.1".into(),
+            port: device.local_addr().unwrap().port(),
+        })
+        .await
+        .unwrap();
+        let local = udp.socket.local_addr().unwrap();
+
+        udp.send(b"hello").await.unwrap();
+        let mut buf = [0u8; 16];
+        let (count, from) = device.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..count], b"hello");
+
+        stranger.send_to(b"noise", local).await.unwrap();
+        device.send_to(b"reply", from).await.unwrap();
+        assert_eq!(recv(&mut udp).await, b"reply");
+    }
+
+    #[tokio::test]
+    async fn a_peer_that_is_not_listening_does_not_end_the_session() {
+        // Bind then drop, so the port is (almost certainly) closed.
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut udp = open(&UdpSettings {
+            bind_port: 0,
+            host: "127.0.0.1".into(),
+            port,
+        })
+        .await
+        .unwrap();
+        // The refusal arrives asynchronously, so the first send usually succeeds and the error
+        // surfaces on a later call; either way nothing reports the link as closed.
+        let _ = udp.send(b"ping").await;
+        let outcome = tokio::time::timeout(Duration::from_millis(200), udp.recv()).await;
+        assert!(outcome.is_err(), "recv should keep waiting rather than close");
+    }
+}
@@ -0,0 +1,104 @@
+//! Unix domain socket transport.
+//!
+//! A stream socket on the local filesystem: how QEMU exposes a VM's serial console
+//! (`-serial unix:PATH,server`), and how plenty of local daemons offer a console. Bytes go
+//! both ways untouched, as over raw TCP.
+
+use std::path::Path;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+use super::transport::{Incoming, READ_BUFFER};
+use crate::settings::UnixSocketSettings;
+
+/// A connected Unix socket.
+pub struct UnixTransport {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+}
+
+impl UnixTransport {
+    pub async fn recv(&mut self) -> Incoming {
+        match self.stream.read(&mut self.buffer).await {
+            Ok(0) => Incoming::Closed(Some("The other end closed the socket.".to_owned())),
+            Ok(count) => Incoming::Data(self.buffer[..count].to_vec()),
+            Err(e) => Incoming::Closed(Some(format!("Read failed: {e}"))),
+        }
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.stream
+            .write_all(data)
+            .await
+            .map_err(|e| format!("Write failed: {e}"))
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.stream.shutdown().await;
+    }
+}
+
+/// Connect to the socket at `settings.path`.
+pub async fn connect(settings: &UnixSocketSettings) -> Result<UnixTransport, String> {
+    let path: &Path = &settings.path;
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| format!("Unable to connect to {}: {e}", path.display()))?;
+    Ok(UnixTransport {
+        stream,
+        buffer: vec![0; READ_BUFFER],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tokio::net::UnixListener;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("uniterm_unix_{name}.sock"));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn bytes_round_trip_and_the_close_is_reported() {
+        let path = socket_path("round_trip");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket.write_all(&buf[..n]).await.unwrap();
+        });
+
+        let mut unix = connect(&UnixSocketSettings { path: path.clone() })
+            .await
+            .unwrap();
+        let payload = [0xff, 0x00, b'\r', b'\n', 0x1b];
+        unix.send(&payload).await.unwrap();
+        let mut received = Vec::new();
+        let reason = loop {
+            match unix.recv().await {
+                Incoming::Data(data) => received.extend(data),
+                Incoming::Closed(reason) => break reason,
+            }
+        };
+        assert_eq!(received, payload);
+        assert!(reason.unwrap().contains("closed"));
+        server.await.unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn a_missing_socket_names_the_path() {
+        let path = socket_path("missing");
+        let error = connect(&UnixSocketSettings { path: path.clone() })
+            .await
+            .err()
+            .unwrap();
+        assert!(error.contains(&path.display().to_string()), "{error}");
+    }
+}
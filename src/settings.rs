@@ -19,15 +19,52 @@ pub enum ConnectionKind {
     #[default]
     Serial,
     Ssh,
+    /// A raw TCP byte stream, as served by serial-over-Ethernet device servers and embedded
+    /// network consoles.
+    Tcp,
+    /// Telnet, as spoken by lab console servers: a TCP stream with in-band option negotiation.
+    Telnet,
+    /// A serial port on a device server, configured over Telnet's COM-PORT-OPTION (RFC 2217).
+    Rfc2217,
+    /// A program on a local pseudo-terminal, by default the user's shell.
+    Local,
+    /// A command whose stdin and stdout are the link, with no terminal in between.
+    Pipe,
+    /// UDP datagrams, as streamed by telemetry devices.
+    Udp,
+    /// A Unix domain stream socket, such as a QEMU serial console or a local daemon.
+    UnixSocket,
 }
 
 impl ConnectionKind {
-    pub const ALL: &'static [Self] = &[Self::Serial, Self::Ssh];
+    /// The kinds this build can open. A local shell needs a Unix pseudo-terminal, and a Unix
+    /// socket a Unix; the variants still exist elsewhere so a settings file written on Linux
+    /// loads on Windows.
+    pub const ALL: &'static [Self] = &[
+        Self::Serial,
+        Self::Ssh,
+        Self::Tcp,
+        Self::Telnet,
+        Self::Rfc2217,
+        #[cfg(unix)]
+        Self::Local,
+        Self::Pipe,
+        Self::Udp,
+        #[cfg(unix)]
+        Self::UnixSocket,
+    ];
 
     pub fn label(self) -> &'static str {
         match self {
             Self::Serial => "Serial",
             Self::Ssh => "SSH",
+            Self::Tcp => "TCP",
+            Self::Telnet => "Telnet",
+            Self::Rfc2217 => "RFC 2217",
+            Self::Local => "Local",
+            Self::Pipe => "Command",
+            Self::Udp => "UDP",
+            Self::UnixSocket => "Unix socket",
         }
     }
 }
@@ -45,6 +82,26 @@ pub struct ConnectionSettings {
     pub kind: ConnectionKind,
     pub serial: SerialSettings,
     pub ssh: SshSettings,
+    /// Defaulted so settings saved before TCP existed still load.
+    #[serde(default)]
+    pub tcp: TcpSettings,
+    #[serde(default)]
+    pub telnet: TelnetSettings,
+    /// Where the device server listens for [`ConnectionKind::Rfc2217`]. The line parameters
+    /// come from `serial`, so moving between a local and a remote port keeps them.
+    #[serde(default)]
+    pub rfc2217: TcpSettings,
+    /// What a [`ConnectionKind::Local`] tab runs. An empty command means the user's shell.
+    #[serde(default)]
+    pub local: ProcessSettings,
+    /// What a [`ConnectionKind::Pipe`] tab runs. Kept apart from `local` because the two are
+    /// rarely the same command: a shell there, a bridge such as `socat` here.
+    #[serde(default)]
+    pub pipe: ProcessSettings,
+    #[serde(default)]
+    pub udp: UdpSettings,
+    #[serde(default)]
+    pub unix_socket: UnixSocketSettings,
 }
 
 impl ConnectionSettings {
@@ -67,6 +124,42 @@ impl ConnectionSettings {
                     format!("{}@{}", self.ssh.user, self.ssh.host)
                 }
             }
+            ConnectionKind::Tcp => {
+                if self.tcp.host.is_empty() {
+                    "(no host)".to_owned()
+                } else {
+                    self.tcp.address()
+                }
+            }
+            ConnectionKind::Telnet => {
+                if self.telnet.host.is_empty() {
+                    "(no host)".to_owned()
+                } else {
+                    self.telnet.host.clone()
+                }
+            }
+            ConnectionKind::Rfc2217 => {
+                if self.rfc2217.host.is_empty() {
+                    "(no host)".to_owned()
+                } else {
+                    // A device server exposes one port per TCP port, so the host alone is not
+                    // enough to tell tabs apart.
+                    self.rfc2217.address()
+                }
+            }
+            ConnectionKind::Local => self.local.program().unwrap_or("Shell").to_owned(),
+            ConnectionKind::Pipe => self.pipe.program().unwrap_or("(no command)").to_owned(),
+            ConnectionKind::Udp => {
+                if self.udp.host.is_empty() {
+                    format!("UDP :{}", self.udp.bind_port)
+                } else {
+                    self.udp.address()
+                }
+            }
+            ConnectionKind::UnixSocket => match self.unix_socket.path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => "(no socket)".to_owned(),
+            },
         }
     }
 
@@ -76,18 +169,38 @@ impl ConnectionSettings {
     /// rate and framing are part of what you are trying to remember, so `COM3 @ 9600 8N1` and
     /// `COM3 @ 115200 8N1` are properly two different things to reopen.
     ///
-    /// SSH keys on `user@host:port` only. The authentication method is how you get in, not what
-    /// you are connecting to, so switching from a password to a key does not create a duplicate.
+    /// SSH keys on `user@host:port`, plus the command for a tab that runs one. The authentication
+    /// method is how you get in, not what you are connecting to, so switching from a password to
+    /// a key does not create a duplicate; a log viewer and a shell on the same host are two
+    /// different things to reopen.
+    ///
+    /// TCP and Telnet are just `host:port`; the terminal type is a preference, not a destination.
+    /// An RFC 2217 port is a serial port, so it carries the line parameters like one. A local
+    /// or piped tab is the command it runs. UDP is the local port plus the peer, if any, since
+    /// either alone can be what the tab is for. A Unix socket is its path.
     pub fn identity(&self) -> String {
         match self.kind {
             ConnectionKind::Serial => {
-                let s = &self.serial;
-                format!(
-                    "serial:{}@{}:{:?}:{:?}:{:?}:{:?}",
-                    s.name, s.baud_rate, s.data_bits, s.parity, s.stop_bits, s.flow_control
-                )
+                format!("serial:{}@{}", self.serial.name, self.serial.line_identity())
             }
-            ConnectionKind::Ssh => format!("ssh:{}", self.ssh.identity()),
+            ConnectionKind::Ssh => match self.ssh.command.trim() {
+                "" => format!("ssh:{}", self.ssh.identity()),
+                command => format!("ssh:{} {command}", self.ssh.identity()),
+            },
+            ConnectionKind::Tcp => format!("tcp:{}", self.tcp.address()),
+            ConnectionKind::Telnet => format!("telnet:{}", self.telnet.address()),
+            ConnectionKind::Rfc2217 => format!(
+                "rfc2217:{}@{}",
+                self.rfc2217.address(),
+                self.serial.line_identity()
+            ),
+            ConnectionKind::Local => format!("local:{}", self.local.command.trim()),
+            ConnectionKind::Pipe => format!("pipe:{}", self.pipe.command.trim()),
+            ConnectionKind::Udp => match self.udp.host.as_str() {
+                "" => format!("udp:{}", self.udp.bind_port),
+                _ => format!("udp:{}>{}", self.udp.bind_port, self.udp.address()),
+            },
+            ConnectionKind::UnixSocket => format!("unix:{}", self.unix_socket.path.display()),
         }
     }
 
@@ -98,7 +211,34 @@ impl ConnectionSettings {
                 let s = &self.serial;
                 format!("{} · {} baud", s.name, s.baud_rate)
             }
-            ConnectionKind::Ssh => format!("{} · {}", self.ssh.identity(), self.ssh.auth.label()),
+            ConnectionKind::Ssh => {
+                let ssh = &self.ssh;
+                let mut target = ssh.identity();
+                if let Some(jump) = ssh.jump_hosts.first() {
+                    target = format!("{target} via {}", jump.host);
+                }
+                match ssh.command.trim() {
+                    "" => format!("{target} · {}", ssh.auth.label()),
+                    command => format!("{target} · {command}"),
+                }
+            }
+            ConnectionKind::Tcp => format!("{} · raw TCP", self.tcp.address()),
+            ConnectionKind::Telnet => format!("{} · Telnet", self.telnet.address()),
+            ConnectionKind::Rfc2217 => {
+                format!("{} · {} baud", self.rfc2217.address(), self.serial.baud_rate)
+            }
+            ConnectionKind::Local => match self.local.command.trim() {
+                "" => "Shell · local".to_owned(),
+                command => format!("{command} · local"),
+            },
+            ConnectionKind::Pipe => format!("{} · piped", self.pipe.command.trim()),
+            ConnectionKind::Udp => match self.udp.host.as_str() {
+                "" => format!("port {} · UDP", self.udp.bind_port),
+                _ => format!("{} · UDP", self.udp.address()),
+            },
+            ConnectionKind::UnixSocket => {
+                format!("{} · Unix socket", self.unix_socket.path.display())
+            }
         }
     }
 
@@ -108,31 +248,59 @@ impl ConnectionSettings {
             ConnectionKind::Serial if self.serial.name.is_empty() => Err("Select a port first."),
             ConnectionKind::Ssh if self.ssh.host.is_empty() => Err("Enter a host first."),
             ConnectionKind::Ssh if self.ssh.user.is_empty() => Err("Enter a username first."),
+            ConnectionKind::Ssh if self.ssh.jump_hosts.iter().any(|j| j.host.trim().is_empty()) => {
+                Err("Enter each jump host, or remove the empty one.")
+            }
+            ConnectionKind::Ssh if !self.ssh.forwards.iter().all(Forward::is_complete) => {
+                Err("Fill in both ports and the host of each forward, or remove it.")
+            }
+            ConnectionKind::Tcp if self.tcp.host.is_empty() => Err("Enter a host first."),
+            ConnectionKind::Telnet if self.telnet.host.is_empty() => Err("Enter a host first."),
+            ConnectionKind::Rfc2217 if self.rfc2217.host.is_empty() => Err("Enter a host first."),
+            ConnectionKind::Pipe if self.pipe.command.trim().is_empty() => {
+                Err("Enter a command first.")
+            }
+            ConnectionKind::Udp if self.udp.host.is_empty() && self.udp.bind_port == 0 => {
+                Err("Enter a port to listen on, or a peer to send to.")
+            }
+            ConnectionKind::Udp if !self.udp.host.is_empty() && self.udp.port == 0 => {
+                Err("Enter the peer's port first.")
+            }
+            ConnectionKind::UnixSocket if self.unix_socket.path.as_os_str().is_empty() => {
+                Err("Choose a socket first.")
+            }
             _ => Ok(()),
         }
     }
 }
 
 /// How to authenticate an SSH connection.
-///
-/// ssh-agent is deliberately absent: it needs a named-pipe transport on Windows and a
-/// separate code path, and a half-working option is worse than no option. It belongs with
-/// the credential-storage work.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SshAuth {
     #[default]
     Password,
     /// A private key file on disk, optionally passphrase-protected.
     PublicKey,
+    /// Whatever keys a running ssh-agent holds. The agent does the signing, so no key or
+    /// passphrase ever passes through UniTerm.
+    Agent,
 }
 
 impl SshAuth {
-    pub const ALL: &'static [Self] = &[Self::Password, Self::PublicKey];
+    /// The methods this build offers. The agent is reached over a Unix socket; Windows' agent
+    /// listens on a named pipe instead, which is not wired up, so the option is not shown there.
+    pub const ALL: &'static [Self] = &[
+        Self::Password,
+        Self::PublicKey,
+        #[cfg(unix)]
+        Self::Agent,
+    ];
 
     pub fn label(self) -> &'static str {
         match self {
             Self::Password => "Password",
             Self::PublicKey => "Private key",
+            Self::Agent => "ssh-agent",
         }
     }
 }
@@ -153,6 +321,121 @@ pub struct SshSettings {
     /// Overridable so a session can be pointed at a separate store, and so tests can verify
     /// the trust policy without touching the user's real file.
     pub known_hosts: Option<PathBuf>,
+    /// ssh-agent socket, used when `auth` is [`SshAuth::Agent`]. `None` means
+    /// `$SSH_AUTH_SOCK`, which is what every other SSH client uses; the override exists for the
+    /// same reasons as `known_hosts`'.
+    #[serde(default)]
+    pub agent_socket: Option<PathBuf>,
+    /// Bastions to pass through on the way, first hop first, as OpenSSH's `ProxyJump`.
+    ///
+    /// Each hop authenticates with this tab's method, key and secrets. That matches how
+    /// bastions are normally set up — the same key everywhere — and a hop that wants more
+    /// (a one-time code) asks for it through keyboard-interactive like any other server.
+    #[serde(default)]
+    pub jump_hosts: Vec<JumpHost>,
+    /// `-L` and `-R` port forwards, opened once the shell is up.
+    #[serde(default)]
+    pub forwards: Vec<Forward>,
+    /// A command to run instead of a login shell, as in `ssh host journalctl -f`. Empty means
+    /// a shell. The tab ends when the command does, with its exit status.
+    #[serde(default)]
+    pub command: String,
+    /// Run `command` on a PTY, as `ssh -t` does. Needed by anything interactive or full-screen
+    /// (`picocom`, `top`); left off, output arrives exactly as the command wrote it, which is
+    /// what a log viewer wants. A shell always gets a PTY.
+    #[serde(default)]
+    pub command_pty: bool,
+    /// Record newly trusted host keys under a hashed name, as OpenSSH's `HashKnownHosts yes`
+    /// does, so `known_hosts` does not list the hosts connected to.
+    #[serde(default)]
+    pub hash_known_hosts: bool,
+    /// Keep this tab's password or key passphrase in the OS keychain, under
+    /// [`Self::identity`], and use it when none has been typed. Only the choice is saved here;
+    /// the secret itself never is.
+    #[serde(default)]
+    pub keychain: bool,
+}
+
+/// One bastion in [`SshSettings::jump_hosts`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JumpHost {
+    pub host: String,
+    pub port: u16,
+    /// Blank means the same user as the target.
+    pub user: String,
+}
+
+/// Which way a [`Forward`] runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForwardDirection {
+    /// `-L`: a port on this machine reaches a destination as seen from the server.
+    #[default]
+    Local,
+    /// `-R`: a port on the server reaches a destination as seen from this machine.
+    Remote,
+}
+
+impl ForwardDirection {
+    pub const ALL: &'static [Self] = &[Self::Local, Self::Remote];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Local => "-L",
+            Self::Remote => "-R",
+        }
+    }
+}
+
+/// One port forward over an SSH tab's connection.
+///
+/// Listeners bind loopback only, on either end, as OpenSSH does without `GatewayPorts`: a
+/// forward is for the user, not for the rest of the network.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Forward {
+    pub direction: ForwardDirection,
+    /// The port listened on: here for `-L`, on the server for `-R`.
+    pub listen_port: u16,
+    /// Where connections are sent, resolved on the side opposite the listener.
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for Forward {
+    fn default() -> Self {
+        Self {
+            direction: ForwardDirection::default(),
+            listen_port: 0,
+            host: "localhost".to_owned(),
+            port: 0,
+        }
+    }
+}
+
+impl Forward {
+    /// As it would be written on the `ssh` command line, e.g. `-L 8080:localhost:80`.
+    pub fn spec(&self) -> String {
+        format!(
+            "{} {}:{}:{}",
+            self.direction.label(),
+            self.listen_port,
+            self.host,
+            self.port
+        )
+    }
+
+    fn is_complete(&self) -> bool {
+        self.listen_port != 0 && !self.host.trim().is_empty() && self.port != 0
+    }
+}
+
+impl Default for JumpHost {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 22,
+            user: String::new(),
+        }
+    }
 }
 
 impl Default for SshSettings {
@@ -166,6 +449,13 @@ impl Default for SshSettings {
             // 256-colour is what the emulator actually supports, so claim it.
             term: "xterm-256color".to_owned(),
             known_hosts: None,
+            agent_socket: None,
+            jump_hosts: Vec::new(),
+            forwards: Vec::new(),
+            command: String::new(),
+            command_pty: false,
+            hash_known_hosts: false,
+            keychain: false,
         }
     }
 }
@@ -175,6 +465,120 @@ impl SshSettings {
     pub fn identity(&self) -> String {
         format!("{}@{}:{}", self.user, self.host, self.port)
     }
+
+    /// The settings for connecting to `jump` itself: its address and user, with this tab's way
+    /// of logging in and its other options. The tab's password is the target's alone, and is
+    /// never offered to a hop; see `Credentials::for_jump`.
+    pub fn for_jump(&self, jump: &JumpHost) -> SshSettings {
+        SshSettings {
+            host: jump.host.trim().to_owned(),
+            port: jump.port,
+            user: match jump.user.trim() {
+                "" => self.user.clone(),
+                user => user.to_owned(),
+            },
+            jump_hosts: Vec::new(),
+            forwards: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/// A raw TCP endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpSettings {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Default for TcpSettings {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            // There is no standard raw-socket port. 4001 is the first data port on Moxa NPort
+            // device servers, which is as common a starting point as any.
+            port: 4001,
+        }
+    }
+}
+
+impl TcpSettings {
+    /// `host:port`, with an IPv6 literal bracketed so the port stays unambiguous.
+    pub fn address(&self) -> String {
+        address(&self.host, self.port)
+    }
+}
+
+/// A Telnet endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TelnetSettings {
+    pub host: String,
+    pub port: u16,
+    /// Sent when the server asks for TERMINAL-TYPE. Same meaning as [`SshSettings::term`].
+    pub term: String,
+}
+
+impl Default for TelnetSettings {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 23,
+            term: SshSettings::default().term,
+        }
+    }
+}
+
+impl TelnetSettings {
+    pub fn address(&self) -> String {
+        address(&self.host, self.port)
+    }
+}
+
+fn address(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// A UDP socket: a local port, and optionally the one peer it talks to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UdpSettings {
+    /// Local port to bind. 0 lets the system pick, which suits a tab that only sends.
+    pub bind_port: u16,
+    /// Peer to send to. Empty means listen to anyone and reply to whoever spoke last; set,
+    /// the socket is connected and datagrams from anywhere else are dropped.
+    pub host: String,
+    pub port: u16,
+}
+
+impl UdpSettings {
+    pub fn address(&self) -> String {
+        address(&self.host, self.port)
+    }
+}
+
+/// A Unix domain socket to connect to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnixSocketSettings {
+    /// Filesystem path of the socket, e.g. what QEMU was given as `-serial unix:PATH,server`.
+    pub path: PathBuf,
+}
+
+/// A command to run locally.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessSettings {
+    /// A command line, run by the platform shell so quoting and pipes work as typed.
+    pub command: String,
+}
+
+impl ProcessSettings {
+    /// The program name, for a tab title: `picocom` for `/usr/bin/picocom -b 9600 ...`.
+    pub fn program(&self) -> Option<&str> {
+        let first = self.command.split_whitespace().next()?;
+        Some(first.rsplit(['/', '\\']).next().unwrap_or(first))
+    }
 }
 
 /// Everything needed to open a serial port.
@@ -195,6 +599,16 @@ pub struct SerialSettings {
     pub usb_serial: Option<String>,
 }
 
+impl SerialSettings {
+    /// Baud rate and framing, as they appear in a recents identity.
+    fn line_identity(&self) -> String {
+        format!(
+            "{}:{:?}:{:?}:{:?}:{:?}",
+            self.baud_rate, self.data_bits, self.parity, self.stop_bits, self.flow_control
+        )
+    }
+}
+
 impl Default for SerialSettings {
     fn default() -> Self {
         Self {
@@ -487,4 +901,146 @@ mod tests {
         assert_eq!(baud_label(115_200), "115.2 kbaud");
         assert_eq!(baud_label(1_000_000), "1.00 Mbaud");
     }
+
+    #[test]
+    fn settings_saved_before_tcp_existed_still_load() {
+        let old = r#"(
+            kind: Serial,
+            serial: (name: "COM3", baud_rate: 9600, data_bits: Eight, flow_control: None,
+                     parity: None, stop_bits: One, usb_serial: None),
+            ssh: (host: "", port: 22, user: "", auth: Password, key_path: None,
+                  term: "xterm-256color", known_hosts: None),
+        )"#;
+        let settings: ConnectionSettings = ron::from_str(old).unwrap();
+        assert_eq!(settings.serial.name, "COM3");
+        assert_eq!(settings.tcp, TcpSettings::default());
+    }
+
+    #[test]
+    fn local_tabs_are_titled_by_their_program() {
+        let mut settings = ConnectionSettings {
+            kind: ConnectionKind::Local,
+            ..Default::default()
+        };
+        assert_eq!(settings.label(), "Shell");
+        settings.local.command = "/usr/bin/picocom -b 9600 /dev/ttyUSB0".into();
+        assert_eq!(settings.label(), "picocom");
+        assert_eq!(settings.identity(), "local:/usr/bin/picocom -b 9600 /dev/ttyUSB0");
+        assert!(settings.is_complete().is_ok(), "an empty command means the shell");
+
+        // A piped tab has no default to fall back on.
+        settings.kind = ConnectionKind::Pipe;
+        assert_eq!(settings.label(), "(no command)");
+        assert!(settings.is_complete().is_err());
+        settings.pipe.command = "socat - TCP:lab:4001".into();
+        assert_eq!(settings.label(), "socat");
+        assert_eq!(settings.identity(), "pipe:socat - TCP:lab:4001");
+    }
+
+    #[test]
+    fn udp_needs_a_port_to_listen_on_or_a_peer() {
+        let mut settings = ConnectionSettings {
+            kind: ConnectionKind::Udp,
+            ..Default::default()
+        };
+        assert!(settings.is_complete().is_err());
+        settings.udp.bind_port = 5000;
+        assert!(settings.is_complete().is_ok(), "listening alone is enough");
+        assert_eq!(settings.label(), "UDP :5000");
+        assert_eq!(settings.identity(), "udp:5000");
+
+        settings.udp.host = "sensor.lab".into();
+        assert!(settings.is_complete().is_err(), "a peer needs a port");
+        settings.udp.port = 6000;
+        assert!(settings.is_complete().is_ok());
+        assert_eq!(settings.label(), "sensor.lab:6000");
+        assert_eq!(settings.identity(), "udp:5000>sensor.lab:6000");
+    }
+
+    #[test]
+    fn jump_hosts_borrow_the_tabs_login_and_must_be_filled_in() {
+        let mut settings = ConnectionSettings {
+            kind: ConnectionKind::Ssh,
+            ssh: SshSettings {
+                host: "router.lab".into(),
+                user: "admin".into(),
+                auth: SshAuth::PublicKey,
+                key_path: Some("/keys/lab".into()),
+                jump_hosts: vec![JumpHost::default()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(settings.is_complete().is_err(), "an empty hop goes nowhere");
+
+        settings.ssh.jump_hosts[0].host = "bastion.corp".into();
+        assert!(settings.is_complete().is_ok());
+        assert_eq!(settings.description(), "admin@router.lab:22 via bastion.corp · Private key");
+        assert_eq!(settings.identity(), "ssh:admin@router.lab:22", "the route is not the target");
+
+        settings.ssh.forwards.push(Forward::default());
+        let hop = settings.ssh.for_jump(&settings.ssh.jump_hosts[0]);
+        assert_eq!(hop.identity(), "admin@bastion.corp:22");
+        assert_eq!(hop.key_path, settings.ssh.key_path);
+        assert!(hop.jump_hosts.is_empty());
+        assert!(hop.forwards.is_empty(), "forwards belong to the target");
+
+        settings.ssh.jump_hosts[0].user = "jumper".into();
+        assert_eq!(settings.ssh.for_jump(&settings.ssh.jump_hosts[0]).user, "jumper");
+    }
+
+    #[test]
+    fn a_command_tab_is_its_own_recent() {
+        let mut settings = ConnectionSettings {
+            kind: ConnectionKind::Ssh,
+            ssh: SshSettings {
+                host: "router.lab".into(),
+                user: "admin".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let shell = settings.identity();
+        settings.ssh.command = " journalctl -f ".into();
+        assert_eq!(settings.identity(), "ssh:admin@router.lab:22 journalctl -f");
+        assert_ne!(settings.identity(), shell);
+        assert_eq!(settings.description(), "admin@router.lab:22 · journalctl -f");
+    }
+
+    #[test]
+    fn forwards_need_both_ports_and_a_host() {
+        let mut settings = ConnectionSettings {
+            kind: ConnectionKind::Ssh,
+            ssh: SshSettings {
+                host: "router.lab".into(),
+                user: "admin".into(),
+                forwards: vec![Forward::default()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(settings.is_complete().is_err());
+        let forward = &mut settings.ssh.forwards[0];
+        forward.listen_port = 8080;
+        forward.port = 80;
+        assert_eq!(forward.spec(), "-L 8080:localhost:80");
+        assert!(settings.is_complete().is_ok());
+
+        settings.ssh.forwards[0].host = " ".into();
+        assert!(settings.is_complete().is_err());
+    }
+
+    #[test]
+    fn unix_socket_tabs_are_titled_by_the_socket_name() {
+        let mut settings = ConnectionSettings {
+            kind: ConnectionKind::UnixSocket,
+            ..Default::default()
+        };
+        assert_eq!(settings.label(), "(no socket)");
+        assert!(settings.is_complete().is_err());
+        settings.unix_socket.path = "/tmp/vm1/serial.sock".into();
+        assert!(settings.is_complete().is_ok());
+        assert_eq!(settings.label(), "serial.sock");
+        assert_eq!(settings.identity(), "unix:/tmp/vm1/serial.sock");
+    }
 }
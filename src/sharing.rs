@@ -0,0 +1,507 @@
+//! Profiles and recents as a file to hand to someone else.
+//!
+//! A team lead curates a set of bench connections, exports it, and everyone imports it. The
+//! file is RON, like the saved state, and holds [`Profile`]s and [`Recent`]s — both built on
+//! [`crate::settings::ConnectionSettings`], which has no field a password could go in, so
+//! nothing secret can travel this way.
+//!
+//! Importing compares connections by [`crate::settings::ConnectionSettings::identity`]. One
+//! that is new is added. One that is already here is a clash, and the user says per clash
+//! whether to merge it in or skip it; skipping is the default, so importing never changes
+//! anything without being asked. A connection the file lists twice is imported once, and the
+//! review says which repeats were passed over.
+//!
+//! A file from someone else is only as trustworthy as they are. A local or piped tab runs its
+//! command as the user, and an SSH command, port forward or jump host acts with the user's
+//! credentials, so each entry that would do any of that is listed in the review with what it
+//! does, and left out unless the user allows it.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::profiles::{Profile, Profiles};
+use crate::recents::{Recent, Recents};
+use crate::settings::{ConnectionKind, ConnectionSettings};
+
+/// Bumped when the file changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The file's contents.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub recents: Vec<Recent>,
+}
+
+/// Write `profiles`, and `recents` if given, to `path`.
+pub fn export(path: &Path, profiles: &Profiles, recents: Option<&Recents>) -> Result<(), String> {
+    let bundle = Bundle {
+        version: FORMAT_VERSION,
+        profiles: profiles.entries().to_vec(),
+        recents: recents.map(|r| r.entries().to_vec()).unwrap_or_default(),
+    };
+    let text = ron::ser::to_string_pretty(&bundle, ron::ser::PrettyConfig::default())
+        .map_err(|e| format!("Could not write the export: {e}"))?;
+    std::fs::write(path, text).map_err(|e| format!("Could not write {}: {e}", path.display()))
+}
+
+/// Read a file written by [`export`].
+pub fn read(path: &Path) -> Result<Bundle, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    let bundle: Bundle = ron::from_str(&text)
+        .map_err(|e| format!("{} is not a UniTerm export: {e}", path.display()))?;
+    if bundle.version > FORMAT_VERSION {
+        return Err(format!(
+            "{} was written by a newer version of UniTerm (format {} vs {FORMAT_VERSION}).",
+            path.display(),
+            bundle.version
+        ));
+    }
+    Ok(bundle)
+}
+
+/// What connecting with `settings` would run or open beyond a terminal, each described for
+/// the user. Empty for a connection that only talks to its port.
+pub fn risks(settings: &ConnectionSettings) -> Vec<String> {
+    let mut risks = Vec::new();
+    match settings.kind {
+        ConnectionKind::Local => risks.push(match settings.local.command.trim() {
+            "" => "opens your shell".to_owned(),
+            command => format!("runs `{command}` here"),
+        }),
+        ConnectionKind::Pipe => risks.push(format!("runs `{}` here", settings.pipe.command.trim())),
+        ConnectionKind::Ssh => {
+            let ssh = &settings.ssh;
+            if !ssh.command.trim().is_empty() {
+                risks.push(format!("runs `{}` on {}", ssh.command.trim(), ssh.host));
+            }
+            for hop in &ssh.jump_hosts {
+                risks.push(format!("logs in to {} on the way", hop.host));
+            }
+            for forward in &ssh.forwards {
+                risks.push(format!("forwards {}", forward.spec()));
+            }
+        }
+        _ => {}
+    }
+    risks
+}
+
+/// One thing to import.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Incoming<T> {
+    pub item: T,
+    /// What it clashes with here, described for the user. `None` if it is new.
+    pub clash: Option<String>,
+    /// Merge a clash in rather than skip it.
+    pub merge: bool,
+    /// The entry earlier in the file for the same connection, described for the user. A repeat
+    /// is never imported.
+    pub repeat: Option<String>,
+    /// What it would run or open, as [`risks`] has it.
+    pub risks: Vec<String>,
+    /// The user allows `risks`. An entry with any is left out without this.
+    pub allowed: bool,
+}
+
+impl<T> Incoming<T> {
+    fn new(item: T, clash: Option<String>, risks: Vec<String>) -> Self {
+        Self {
+            item,
+            clash,
+            merge: false,
+            repeat: None,
+            risks,
+            allowed: false,
+        }
+    }
+
+    /// Whether applying the import would leave this out whatever its clash says.
+    fn held_back(&self) -> bool {
+        self.repeat.is_some() || (!self.risks.is_empty() && !self.allowed)
+    }
+}
+
+/// Mark each entry whose connection came earlier in the file, by `identity`, as a repeat of
+/// that one, as `describe` has it.
+fn mark_repeats<T>(
+    incoming: &mut [Incoming<T>],
+    identity: impl Fn(&T) -> String,
+    describe: impl Fn(&T) -> String,
+) {
+    for later in 1..incoming.len() {
+        let (earlier, rest) = incoming.split_at_mut(later);
+        let this = identity(&rest[0].item);
+        if let Some(first) = earlier.iter().find(|e| identity(&e.item) == this) {
+            rest[0].repeat = Some(describe(&first.item));
+        }
+    }
+}
+
+/// A file read and checked against what is here, waiting for the user's choices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Import {
+    pub source: PathBuf,
+    pub profiles: Vec<Incoming<Profile>>,
+    pub recents: Vec<Incoming<Recent>>,
+}
+
+impl Import {
+    pub fn new(source: PathBuf, bundle: Bundle, profiles: &Profiles, recents: &Recents) -> Self {
+        let mut profiles = bundle
+            .profiles
+            .into_iter()
+            .map(|item| {
+                let identity = item.settings.identity();
+                let clash = profiles
+                    .entries()
+                    .iter()
+                    .find(|p| p.settings.identity() == identity)
+                    .map(describe_profile);
+                let risks = risks(&item.settings);
+                Incoming::new(item, clash, risks)
+            })
+            .collect::<Vec<_>>();
+        let mut recents = bundle
+            .recents
+            .into_iter()
+            .map(|item| {
+                let identity = item.identity();
+                let clash = recents
+                    .entries()
+                    .iter()
+                    .any(|r| r.identity() == identity)
+                    .then(|| "a recent connection".to_owned());
+                let risks = risks(&item.settings);
+                Incoming::new(item, clash, risks)
+            })
+            .collect::<Vec<_>>();
+        mark_repeats(&mut profiles, |p| p.settings.identity(), describe_profile);
+        mark_repeats(&mut recents, Recent::identity, |r| r.settings.label());
+        Self {
+            source,
+            profiles,
+            recents,
+        }
+    }
+
+    /// Entries already here, repeats aside.
+    pub fn clashes(&self) -> usize {
+        let clash = |clash: &Option<String>, repeat: &Option<String>| {
+            clash.is_some() && repeat.is_none()
+        };
+        let profiles = self.profiles.iter().filter(|p| clash(&p.clash, &p.repeat)).count();
+        profiles + self.recents.iter().filter(|r| clash(&r.clash, &r.repeat)).count()
+    }
+
+    /// Entries that would run or open something, repeats aside.
+    pub fn risky(&self) -> usize {
+        let risky = |risks: &[String], repeat: &Option<String>| {
+            !risks.is_empty() && repeat.is_none()
+        };
+        let profiles = self.profiles.iter().filter(|p| risky(&p.risks, &p.repeat)).count();
+        profiles + self.recents.iter().filter(|r| risky(&r.risks, &r.repeat)).count()
+    }
+
+    /// Entries the file lists a second time.
+    pub fn repeats(&self) -> usize {
+        let profiles = self.profiles.iter().filter(|p| p.repeat.is_some()).count();
+        profiles + self.recents.iter().filter(|r| r.repeat.is_some()).count()
+    }
+
+    /// Merge, or skip, every clash.
+    pub fn merge_all(&mut self, merge: bool) {
+        self.profiles.iter_mut().for_each(|p| p.merge = merge);
+        self.recents.iter_mut().for_each(|r| r.merge = merge);
+    }
+
+    /// Allow, or leave out, everything that would run or open something.
+    pub fn allow_all(&mut self, allowed: bool) {
+        self.profiles.iter_mut().for_each(|p| p.allowed = allowed);
+        self.recents.iter_mut().for_each(|r| r.allowed = allowed);
+    }
+
+    /// Carry out the import, and say what it did.
+    ///
+    /// A merged profile takes the incoming connection, tab options and notes, and keeps its own
+    /// name and folder, which are the user's filing. A merged recent takes the incoming settings
+    /// and keeps whichever use was later, and a pin from either side.
+    pub fn apply(self, profiles: &mut Profiles, recents: &mut Recents) -> String {
+        let (mut added, mut merged, mut skipped) = (0, 0, 0);
+        let (mut repeated, mut held) = (0, 0);
+        let mut hold = |repeat: &Option<String>| match repeat {
+            Some(_) => repeated += 1,
+            None => held += 1,
+        };
+        for incoming in self.profiles {
+            if incoming.held_back() {
+                hold(&incoming.repeat);
+                continue;
+            }
+            let identity = incoming.item.settings.identity();
+            let existing = profiles
+                .entries()
+                .iter()
+                .find(|p| p.settings.identity() == identity)
+                .map(|p| p.id);
+            match (existing, incoming.merge) {
+                (None, _) => {
+                    profiles.add(incoming.item);
+                    added += 1;
+                }
+                (Some(id), true) => {
+                    if let Some(profile) = profiles.get_mut(id) {
+                        profile.settings = incoming.item.settings;
+                        profile.display_mode = incoming.item.display_mode;
+                        profile.enter_crlf = incoming.item.enter_crlf;
+                        profile.send_mode = incoming.item.send_mode;
+                        profile.append_cr = incoming.item.append_cr;
+                        profile.append_lf = incoming.item.append_lf;
+                        if !incoming.item.notes.is_empty() {
+                            profile.notes = incoming.item.notes;
+                        }
+                    }
+                    merged += 1;
+                }
+                (Some(_), false) => skipped += 1,
+            }
+        }
+        let (mut recents_added, mut recents_merged) = (0, 0);
+        for incoming in self.recents {
+            if incoming.held_back() {
+                hold(&incoming.repeat);
+                continue;
+            }
+            match (incoming.clash.is_some(), incoming.merge) {
+                (false, _) => recents_added += 1,
+                (true, true) => recents_merged += 1,
+                (true, false) => {
+                    skipped += 1;
+                    continue;
+                }
+            }
+            recents.merge(incoming.item);
+        }
+        let mut summary = format!(
+            "Imported {added} new and merged {merged} profile(s), \
+             {recents_added} new and merged {recents_merged} recent connection(s)"
+        );
+        if skipped > 0 {
+            summary.push_str(&format!("; skipped {skipped} already here"));
+        }
+        if held > 0 {
+            summary.push_str(&format!("; left out {held} that would run or open something"));
+        }
+        if repeated > 0 {
+            summary.push_str(&format!("; skipped {repeated} listed twice"));
+        }
+        summary.push('.');
+        summary
+    }
+}
+
+/// A profile as the review names it.
+fn describe_profile(profile: &Profile) -> String {
+    match profile.folder.as_str() {
+        "" => format!("profile \"{}\"", profile.name),
+        folder => format!("profile \"{}\" in {folder}", profile.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{DisplayMode, Forward, SendMode, TcpSettings};
+
+    fn tcp(host: &str) -> ConnectionSettings {
+        ConnectionSettings {
+            kind: ConnectionKind::Tcp,
+            tcp: TcpSettings {
+                host: host.into(),
+                port: 4001,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn profile(name: &str, host: &str) -> Profile {
+        Profile {
+            id: 0,
+            name: name.into(),
+            folder: "Bench".into(),
+            notes: String::new(),
+            settings: tcp(host),
+            display_mode: DisplayMode::Ansi,
+            enter_crlf: true,
+            send_mode: SendMode::Ascii,
+            append_cr: false,
+            append_lf: false,
+        }
+    }
+
+    fn recent(host: &str, last_used: u64, pinned: bool) -> Recent {
+        Recent {
+            settings: tcp(host),
+            last_used,
+            uses: 1,
+            pinned,
+        }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("uniterm_sharing_{name}.ron"))
+    }
+
+    #[test]
+    fn an_export_reads_back_and_holds_no_secrets() {
+        let mut profiles = Profiles::default();
+        let mut ssh = profile("Gateway", "gw");
+        ssh.settings.kind = ConnectionKind::Ssh;
+        ssh.settings.ssh.host = "gw".into();
+        profiles.add(ssh);
+        let recents = Recents::from_entries(vec![recent("ts1", 10, true)]);
+        let path = temp_file("round_trip");
+
+        export(&path, &profiles, Some(&recents)).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap().to_lowercase();
+        for field in ["password:", "passphrase:", "credentials:", "secret:"] {
+            assert!(!text.contains(field), "an export must not hold `{field}`:\n{text}");
+        }
+        let bundle = read(&path).unwrap();
+        assert_eq!(bundle.profiles, profiles.entries());
+        assert_eq!(bundle.recents, recents.entries());
+
+        export(&path, &profiles, None).unwrap();
+        assert!(read(&path).unwrap().recents.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn files_that_are_not_exports_are_refused() {
+        let path = temp_file("garbage");
+        std::fs::write(&path, "not ron at all").unwrap();
+        assert!(read(&path).unwrap_err().contains("not a UniTerm export"));
+        std::fs::write(&path, "(version: 99)").unwrap();
+        assert!(read(&path).unwrap_err().contains("newer version"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn clashes_are_found_by_identity_and_skipped_unless_merged() {
+        let mut profiles = Profiles::default();
+        let mine = profiles.add(profile("My modem", "modem"));
+        let mut recents = Recents::from_entries(vec![recent("ts1", 100, false)]);
+
+        let mut theirs = profile("Modem", "modem");
+        theirs.notes = "Use 115200".into();
+        theirs.display_mode = DisplayMode::Hex;
+        let bundle = Bundle {
+            version: FORMAT_VERSION,
+            profiles: vec![theirs, profile("Scope", "scope")],
+            recents: vec![recent("ts1", 50, true), recent("ts2", 60, false)],
+        };
+        let mut import = Import::new(PathBuf::from("x"), bundle.clone(), &profiles, &recents);
+        assert_eq!(import.clashes(), 2);
+        assert_eq!(import.profiles[0].clash.as_deref(), Some("profile \"My modem\" in Bench"));
+        assert_eq!(import.profiles[1].clash, None);
+        assert!(import.recents[0].clash.is_some());
+
+        // Skipping by default: only the new things arrive.
+        let summary = import.clone().apply(&mut profiles.clone(), &mut recents.clone());
+        assert!(summary.contains("skipped 2"), "{summary}");
+
+        import.merge_all(true);
+        let summary = import.apply(&mut profiles, &mut recents);
+        assert!(summary.contains("Imported 1 new and merged 1 profile(s)"), "{summary}");
+        assert_eq!(profiles.len(), 2);
+        let merged = profiles.get(mine).unwrap();
+        assert_eq!(merged.name, "My modem", "the local name is kept");
+        assert_eq!(merged.notes, "Use 115200");
+        assert_eq!(merged.display_mode, DisplayMode::Hex);
+
+        assert_eq!(recents.len(), 2);
+        let ts1 = recents.entries().iter().find(|r| r.settings.tcp.host == "ts1").unwrap();
+        assert!(ts1.pinned, "a pin from either side is kept");
+        assert_eq!(ts1.last_used, 100, "the later use is kept");
+    }
+
+    #[test]
+    fn what_runs_or_opens_something_is_left_out_unless_allowed() {
+        let mut bridge = profile("Bridge", "unused");
+        bridge.settings.kind = ConnectionKind::Pipe;
+        bridge.settings.pipe.command = "socat - /dev/ttyUSB0".into();
+        let mut tunnel = profile("Tunnel", "unused");
+        tunnel.settings.kind = ConnectionKind::Ssh;
+        tunnel.settings.ssh.host = "gw".into();
+        tunnel.settings.ssh.command = "journalctl -f".into();
+        tunnel.settings.ssh.forwards = vec![Forward {
+            listen_port: 8080,
+            port: 80,
+            ..Default::default()
+        }];
+        let mut shell = recent("unused", 1, false);
+        shell.settings.kind = ConnectionKind::Local;
+        let bundle = Bundle {
+            version: FORMAT_VERSION,
+            profiles: vec![bridge, tunnel, profile("Scope", "scope")],
+            recents: vec![shell],
+        };
+        let (profiles, recents) = (Profiles::default(), Recents::default());
+        let mut import = Import::new(PathBuf::from("x"), bundle, &profiles, &recents);
+        assert_eq!(import.risky(), 3);
+        assert_eq!(import.profiles[0].risks, ["runs `socat - /dev/ttyUSB0` here"]);
+        assert_eq!(
+            import.profiles[1].risks,
+            ["runs `journalctl -f` on gw", "forwards -L 8080:localhost:80"]
+        );
+        assert!(import.profiles[2].risks.is_empty());
+        assert_eq!(import.recents[0].risks, ["opens your shell"]);
+
+        let (mut mine, mut my_recents) = (profiles.clone(), recents.clone());
+        let summary = import.clone().apply(&mut mine, &mut my_recents);
+        assert!(summary.contains("left out 3"), "{summary}");
+        assert_eq!(mine.len(), 1);
+        assert_eq!(my_recents.len(), 0);
+
+        import.profiles[0].allowed = true;
+        let (mut mine, mut my_recents) = (profiles.clone(), recents.clone());
+        import.clone().apply(&mut mine, &mut my_recents);
+        assert_eq!(mine.len(), 2);
+        import.allow_all(true);
+        let (mut mine, mut my_recents) = (profiles, recents);
+        let summary = import.apply(&mut mine, &mut my_recents);
+        assert!(!summary.contains("left out"), "{summary}");
+        assert_eq!((mine.len(), my_recents.len()), (3, 1));
+    }
+
+    #[test]
+    fn a_connection_listed_twice_is_imported_once_and_the_repeat_reported() {
+        let bundle = Bundle {
+            version: FORMAT_VERSION,
+            profiles: vec![
+                profile("Modem", "modem"),
+                profile("Modem again", "modem"),
+                profile("Scope", "scope"),
+            ],
+            recents: vec![recent("ts1", 10, false), recent("ts1", 20, true)],
+        };
+        let mut profiles = Profiles::default();
+        let mut recents = Recents::default();
+        let import = Import::new(PathBuf::from("x"), bundle, &profiles, &recents);
+        assert_eq!(import.repeats(), 2);
+        assert_eq!(import.clashes(), 0);
+        assert_eq!(import.profiles[1].repeat.as_deref(), Some("profile \"Modem\" in Bench"));
+        assert_eq!(import.profiles[2].repeat, None);
+        assert!(import.recents[1].repeat.is_some());
+
+        let summary = import.apply(&mut profiles, &mut recents);
+        assert!(summary.contains("skipped 2 listed twice"), "{summary}");
+        assert!(!summary.contains("already here"), "{summary}");
+        assert_eq!((profiles.len(), recents.len()), (2, 1));
+    }
+}
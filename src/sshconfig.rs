@@ -0,0 +1,583 @@
+//! Hosts from `~/.ssh/config`.
+//!
+//! Where most people keep their host inventory already. Every `Host` alias that names one
+//! host — no wildcards, no negation — is offered as a ready-made SSH connection, with the
+//! settings `ssh alias` would use: the first value found for each keyword wins, reading
+//! blocks in order, with wildcard blocks such as `Host *` applying too.
+//!
+//! Understood: `Host`, `HostName` (with `%h`), `User`, `Port`, `IdentityFile` (the first),
+//! `ProxyJump` and `Include`. A hop in `ProxyJump` that is itself an alias is looked up the
+//! same way, for its address, port and user. `Match` blocks are skipped except `Match all`,
+//! since their conditions (`exec`, `localnetwork`, ...) are about a real connection attempt.
+//! Everything else is ignored, as unknown options are by `ssh` itself with
+//! `IgnoreUnknown`.
+//!
+//! With no `IdentityFile` the connection uses the agent, which is where `ssh` would look
+//! first; on Windows, where the agent is not wired up, it asks for a password.
+
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::knownhosts::wildcard;
+use crate::settings::{ConnectionKind, ConnectionSettings, JumpHost, SshAuth, SshSettings};
+
+/// How deep `Include` may nest, as in OpenSSH. Also what stops a file including itself.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// One host from the config, ready to open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigHost {
+    /// The name after `Host`, which is what the user knows it by.
+    pub alias: String,
+    pub settings: ConnectionSettings,
+}
+
+/// Where the directives of an included file begin and end. Neither can come from a file:
+/// `Include` itself is always expanded, and a keyword has no spaces.
+const INCLUDE_BEGINS: &str = "include";
+const INCLUDE_ENDS: &str = "end include";
+
+/// `keyword args...`, with `Include` already expanded in place between markers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Directive {
+    /// Lowercased: keywords are case-insensitive.
+    keyword: String,
+    args: Vec<String>,
+}
+
+/// The user's `~/.ssh/config`.
+///
+/// `UNITERM_SSH_CONFIG` overrides the location, for the snap: it cannot read `~/.ssh`, and its
+/// `HOME` is a directory of its own, so it points this at a copy the user puts in its writable
+/// area. See `knownhosts::default_path`, which has the same problem.
+pub fn default_path() -> Option<PathBuf> {
+    resolve_path(std::env::var_os("UNITERM_SSH_CONFIG"), home())
+}
+
+/// The choice itself, split out so it can be tested without touching the environment. An
+/// empty override means unset.
+fn resolve_path(
+    override_var: Option<std::ffi::OsString>,
+    home: Option<PathBuf>,
+) -> Option<PathBuf> {
+    match override_var.map(PathBuf::from) {
+        Some(path) if !path.as_os_str().is_empty() => Some(path),
+        _ => home.map(|home| home.join(".ssh").join("config")),
+    }
+}
+
+/// The hosts `path` defines, in the order they first appear. A missing file has none; parts
+/// that cannot be read are logged and skipped, so one bad `Include` does not hide the rest.
+pub fn load(path: &Path) -> Vec<ConfigHost> {
+    parse(path, home().as_deref(), &local_user())
+}
+
+/// The settings `ssh host` would use, for any `host` and not just an alias: a name that
+/// appears nowhere in the config still gets what `Host *` blocks say. No config at all gives
+/// the defaults.
+pub fn settings_for(path: Option<&Path>, host: &str) -> ConnectionSettings {
+    let home = home();
+    let directives = path.map(|path| directives(path, home.as_deref())).unwrap_or_default();
+    resolve(&directives, host, home.as_deref(), &local_user())
+}
+
+/// `[user@]host[:port]`, as `ssh` and `ProxyJump` take it. An IPv6 address needs brackets to
+/// carry a port, as it does there.
+pub fn split_target(target: &str) -> (Option<&str>, &str, Option<u16>) {
+    let (user, address) = match target.rsplit_once('@') {
+        Some((user, address)) => (Some(user), address),
+        None => (None, target),
+    };
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            (host, port.parse().ok())
+        }
+        _ => (address, None),
+    };
+    (user, host.trim_start_matches('[').trim_end_matches(']'), port)
+}
+
+fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .filter(|p| !p.as_os_str().is_empty())
+}
+
+/// [`load`] with the environment passed in.
+fn parse(path: &Path, home: Option<&Path>, local_user: &str) -> Vec<ConfigHost> {
+    let directives = directives(path, home);
+    let mut aliases: Vec<String> = Vec::new();
+    for directive in directives.iter().filter(|d| d.keyword == "host") {
+        for pattern in &directive.args {
+            let concrete = !pattern.contains(['*', '?', '!']);
+            if concrete && !aliases.contains(pattern) {
+                aliases.push(pattern.clone());
+            }
+        }
+    }
+    aliases
+        .into_iter()
+        .map(|alias| {
+            let settings = resolve(&directives, &alias, home, local_user);
+            ConfigHost { alias, settings }
+        })
+        .collect()
+}
+
+/// Every directive of `path`, includes expanded.
+fn directives(path: &Path, home: Option<&Path>) -> Vec<Directive> {
+    // Relative includes are relative to the directory of the user's config, as `ssh` has it.
+    let base = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let mut directives = Vec::new();
+    read(path, &base, home, 0, &mut directives);
+    directives
+}
+
+/// Append the directives of `path`, expanding includes. Each included file is bracketed by
+/// markers, since the block it ends in does not carry on into the file that included it.
+fn read(path: &Path, base: &Path, home: Option<&Path>, depth: usize, out: &mut Vec<Directive>) {
+    if depth > MAX_INCLUDE_DEPTH {
+        warn!("{}: includes nest too deeply; skipped", path.display());
+        return;
+    }
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        // `ssh` ignores an include that names nothing, and having no config at all is normal.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("cannot read {}: {e}", path.display());
+            return;
+        }
+    };
+    for line in text.lines() {
+        let Some(directive) = split_line(line) else {
+            continue;
+        };
+        if directive.keyword != "include" {
+            out.push(directive);
+            continue;
+        }
+        for pattern in &directive.args {
+            let pattern = expand_tilde(pattern, home);
+            let pattern = match Path::new(&pattern).is_absolute() {
+                true => PathBuf::from(pattern),
+                false => base.join(pattern),
+            };
+            for included in glob(&pattern) {
+                out.push(marker(INCLUDE_BEGINS));
+                read(&included, base, home, depth + 1, out);
+                out.push(marker(INCLUDE_ENDS));
+            }
+        }
+    }
+}
+
+fn marker(keyword: &str) -> Directive {
+    Directive {
+        keyword: keyword.to_owned(),
+        args: Vec::new(),
+    }
+}
+
+/// Split a line into its keyword and arguments. `Keyword value`, `Keyword=value` and
+/// double-quoted arguments are all accepted; blank lines and comments give `None`.
+fn split_line(line: &str) -> Option<Directive> {
+    let line = line.trim();
+    let (keyword, rest) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+        Some(at) => (&line[..at], &line[at..]),
+        None => (line, ""),
+    };
+    if keyword.is_empty() || keyword.starts_with('#') {
+        return None;
+    }
+    // At most one `=`, optionally surrounded by whitespace, separates the keyword.
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.peek() {
+            None | Some('#') => break,
+            Some('"') => {
+                chars.next();
+                args.push(chars.by_ref().take_while(|&c| c != '"').collect());
+            }
+            Some(_) => {
+                let mut arg = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
+                }
+                args.push(arg);
+            }
+        }
+    }
+    Some(Directive {
+        keyword: keyword.to_ascii_lowercase(),
+        args,
+    })
+}
+
+/// The files an `Include` pattern names, in name order. Wildcards are only understood in the
+/// last component, which is where `config.d/*` puts them.
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    let name = pattern.file_name().map(|n| n.to_string_lossy().into_owned());
+    let Some(name) = name.filter(|n| n.contains(['*', '?'])) else {
+        return vec![pattern.to_path_buf()];
+    };
+    let dir = pattern.parent().unwrap_or(Path::new("."));
+    let Ok(listing) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut found: Vec<PathBuf> = listing
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| !t.is_dir()))
+        .filter(|entry| wildcard(&name, &entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect();
+    found.sort();
+    found
+}
+
+fn expand_tilde(path: &str, home: Option<&Path>) -> String {
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => path.to_owned(),
+    }
+}
+
+/// Whether a `Host` line applies to `alias`. Any negated pattern that matches rules it out.
+fn host_matches(patterns: &[String], alias: &str) -> bool {
+    let alias = alias.to_lowercase();
+    let mut matched = false;
+    for pattern in patterns {
+        let pattern = pattern.to_lowercase();
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard(negated, &alias) => return false,
+            Some(_) => {}
+            None => matched |= wildcard(&pattern, &alias),
+        }
+    }
+    matched
+}
+
+/// The first value each keyword is given in the blocks that apply to `alias`.
+///
+/// As in `ssh`, an included file starts in the block its `Include` is in and leaves it as it
+/// found it, and nothing in a file included from a block that does not apply can apply either.
+fn lookup<'a>(directives: &'a [Directive], alias: &str) -> Vec<&'a Directive> {
+    let mut active = true;
+    // Inside an include from a block that does not apply.
+    let mut never = false;
+    let mut outer = Vec::new();
+    let mut found: Vec<&Directive> = Vec::new();
+    for directive in directives {
+        match directive.keyword.as_str() {
+            INCLUDE_BEGINS => {
+                outer.push((active, never));
+                never |= !active;
+            }
+            INCLUDE_ENDS => (active, never) = outer.pop().unwrap_or((true, false)),
+            "host" => active = !never && host_matches(&directive.args, alias),
+            "match" => {
+                active = !never && directive.args.len() == 1 && directive.args[0] == "all"
+            }
+            keyword if active && !found.iter().any(|d| d.keyword == keyword) => {
+                found.push(directive)
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The first argument of `keyword` among `values`.
+fn first<'a>(values: &[&'a Directive], keyword: &str) -> Option<&'a str> {
+    values
+        .iter()
+        .find(|d| d.keyword == keyword)
+        .and_then(|d| d.args.first())
+        .map(String::as_str)
+}
+
+fn resolve(
+    directives: &[Directive],
+    alias: &str,
+    home: Option<&Path>,
+    local_user: &str,
+) -> ConnectionSettings {
+    let values = lookup(directives, alias);
+    let value = |keyword| first(&values, keyword);
+
+    let host = value("hostname").map_or_else(|| alias.to_owned(), |name| expand_host(name, alias));
+    let port = value("port").and_then(|port| port.parse().ok()).unwrap_or(22);
+    let user = value("user").unwrap_or(local_user).to_owned();
+    let key_path = value("identityfile")
+        .filter(|path| !path.eq_ignore_ascii_case("none"))
+        .map(|path| PathBuf::from(expand_tilde(&expand_host(path, alias), home)));
+    let auth = match key_path {
+        Some(_) => SshAuth::PublicKey,
+        None if cfg!(unix) => SshAuth::Agent,
+        None => SshAuth::Password,
+    };
+    let jump_hosts = match value("proxyjump") {
+        None => Vec::new(),
+        Some(hops) if hops.eq_ignore_ascii_case("none") => Vec::new(),
+        Some(hops) => hops.split(',').map(|hop| jump_host(directives, hop.trim())).collect(),
+    };
+
+    ConnectionSettings {
+        kind: ConnectionKind::Ssh,
+        ssh: SshSettings {
+            host,
+            port,
+            user,
+            auth,
+            key_path,
+            jump_hosts,
+            ..SshSettings::default()
+        },
+        ..ConnectionSettings::default()
+    }
+}
+
+/// A `ProxyJump` hop, `[user@]host[:port]`. A host that is an alias in the config takes its
+/// address, port and user from there, as it would for `ssh`.
+fn jump_host(directives: &[Directive], hop: &str) -> JumpHost {
+    let (user, host, port) = split_target(hop);
+    let values = lookup(directives, host);
+    let value = |keyword| first(&values, keyword);
+    JumpHost {
+        host: value("hostname").map_or_else(|| host.to_owned(), |name| expand_host(name, host)),
+        port: port
+            .or_else(|| value("port").and_then(|port| port.parse().ok()))
+            .unwrap_or(22),
+        // Blank means the target's user, which is what `ssh` does without one too.
+        user: user.or(value("user")).unwrap_or_default().to_owned(),
+    }
+}
+
+/// Expand `%h` (the alias) and `%%`.
+fn expand_host(text: &str, alias: &str) -> String {
+    text.replace("%%", "\0").replace("%h", alias).replace('\0', "%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("uniterm_sshconfig_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn hosts(dir: &Path, text: &str) -> Vec<ConfigHost> {
+        let path = dir.join("config");
+        std::fs::write(&path, text).unwrap();
+        parse(&path, Some(Path::new("/home/me")), "me")
+    }
+
+    fn ssh<'a>(hosts: &'a [ConfigHost], alias: &str) -> &'a SshSettings {
+        &hosts.iter().find(|h| h.alias == alias).unwrap().settings.ssh
+    }
+
+    #[test]
+    fn lines_split_the_ways_ssh_accepts() {
+        let split = |line| split_line(line).map(|d| (d.keyword, d.args));
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(split("  # comment"), None);
+        assert_eq!(split(""), None);
+        assert_eq!(split("HostName srv"), Some(("hostname".into(), words(&["srv"]))));
+        assert_eq!(split("Port=2222"), Some(("port".into(), words(&["2222"]))));
+        assert_eq!(split("Port = 2222 # why"), Some(("port".into(), words(&["2222"]))));
+        assert_eq!(
+            split("IdentityFile \"~/My Keys/id\""),
+            Some(("identityfile".into(), words(&["~/My Keys/id"])))
+        );
+        assert_eq!(split("Host a b\tc"), Some(("host".into(), words(&["a", "b", "c"]))));
+    }
+
+    #[test]
+    fn each_alias_takes_the_first_value_from_the_blocks_that_match() {
+        let dir = config_dir("first_value");
+        let text = "\
+Host web db
+    User deploy
+
+Host web
+    HostName web1.example.com
+    Port 2222
+    User ignored-because-deploy-came-first
+
+Host db
+    HostName %h.internal
+    IdentityFile ~/.ssh/db_key
+
+Host *.example.com !skip.example.com
+    User wildcard
+
+Host *
+    Port 2200
+    User everyone
+";
+        let hosts = hosts(&dir, text);
+        let aliases: Vec<_> = hosts.iter().map(|h| h.alias.as_str()).collect();
+        assert_eq!(aliases, ["web", "db"], "patterns are not hosts to offer");
+
+        let web = ssh(&hosts, "web");
+        let address = (web.host.as_str(), web.port, web.user.as_str());
+        assert_eq!(address, ("web1.example.com", 2222, "deploy"));
+        assert_eq!(hosts[0].settings.kind, ConnectionKind::Ssh);
+
+        let db = ssh(&hosts, "db");
+        assert_eq!((db.host.as_str(), db.port), ("db.internal", 2200));
+        assert_eq!(db.auth, SshAuth::PublicKey);
+        assert_eq!(db.key_path, Some(PathBuf::from("/home/me/.ssh/db_key")));
+    }
+
+    #[test]
+    fn negated_patterns_and_defaults() {
+        let dir = config_dir("negated");
+        let text = "\
+Host box skip
+Host * !skip
+    User team
+";
+        let hosts = hosts(&dir, text);
+        assert_eq!(ssh(&hosts, "box").user, "team");
+        let skip = ssh(&hosts, "skip");
+        assert_eq!((skip.host.as_str(), skip.port, skip.user.as_str()), ("skip", 22, "me"));
+        let expected = if cfg!(unix) { SshAuth::Agent } else { SshAuth::Password };
+        assert_eq!(skip.auth, expected);
+        assert_eq!(skip.key_path, None);
+    }
+
+    #[test]
+    fn proxy_jump_hops_are_resolved_through_the_config() {
+        let dir = config_dir("jump");
+        let text = "\
+Host bastion
+    HostName bastion.example.com
+    User jumper
+    Port 2022
+
+Host inner
+    HostName 10.0.0.5
+    ProxyJump bastion,admin@gw:2200
+
+Host direct
+    ProxyJump none
+Host *
+    ProxyJump bastion
+";
+        let hosts = hosts(&dir, text);
+        let jumps = &ssh(&hosts, "inner").jump_hosts;
+        assert_eq!(
+            jumps,
+            &[
+                JumpHost {
+                    host: "bastion.example.com".into(),
+                    port: 2022,
+                    user: "jumper".into(),
+                },
+                JumpHost {
+                    host: "gw".into(),
+                    port: 2200,
+                    user: "admin".into(),
+                },
+            ]
+        );
+        assert!(ssh(&hosts, "direct").jump_hosts.is_empty());
+    }
+
+    #[test]
+    fn includes_are_read_in_place_with_globs_and_relative_paths() {
+        let dir = config_dir("include");
+        std::fs::create_dir_all(dir.join("config.d")).unwrap();
+        std::fs::write(dir.join("config.d/20-lab"), "Host lab\n    HostName lab.local\n").unwrap();
+        std::fs::write(dir.join("config.d/10-edge"), "Host edge\n    Port 2201\n").unwrap();
+        std::fs::write(dir.join("loop"), "Include loop\nHost looped\n").unwrap();
+        let text = "\
+Include config.d/*
+Include missing-file
+Include loop
+Host top
+";
+        let hosts = hosts(&dir, text);
+        let aliases: Vec<_> = hosts.iter().map(|h| h.alias.as_str()).collect();
+        assert_eq!(aliases, ["edge", "lab", "looped", "top"]);
+        assert_eq!(ssh(&hosts, "edge").port, 2201);
+        assert_eq!(ssh(&hosts, "lab").host, "lab.local");
+    }
+
+    #[test]
+    fn an_included_block_ends_with_its_file() {
+        let dir = config_dir("include_scope");
+        std::fs::create_dir_all(dir.join("config.d")).unwrap();
+        std::fs::write(dir.join("config.d/lab"), "Host lab\n    Port 2201\n").unwrap();
+        std::fs::write(dir.join("everyone"), "Host *\n    Port 2299\n    User anyone\n").unwrap();
+        let text = "\
+Include config.d/*
+User admin
+
+Host other
+    Include everyone
+";
+        let hosts = hosts(&dir, text);
+        let lab = ssh(&hosts, "lab");
+        assert_eq!((lab.port, lab.user.as_str()), (2201, "admin"));
+        let other = ssh(&hosts, "other");
+        assert_eq!((other.port, other.user.as_str()), (2299, "admin"), "the User is global");
+
+        // `Host *` in a file included from a block that does not apply never applies.
+        let path = dir.join("config");
+        let top = resolve(&directives(&path, None), "top", None, "me").ssh;
+        assert_eq!((top.port, top.user.as_str()), (22, "admin"));
+    }
+
+    #[test]
+    fn the_config_can_be_moved_somewhere_a_snap_can_read() {
+        let home = Some(PathBuf::from("/home/me"));
+        let moved = resolve_path(Some("/var/snap/ssh_config".into()), home.clone());
+        assert_eq!(moved, Some(PathBuf::from("/var/snap/ssh_config")));
+        let usual = Some(PathBuf::from("/home/me/.ssh/config"));
+        assert_eq!(resolve_path(Some("".into()), home.clone()), usual);
+        assert_eq!(resolve_path(None, home), usual);
+        assert_eq!(resolve_path(None, None), None);
+    }
+
+    #[test]
+    fn a_missing_config_has_no_hosts() {
+        let dir = config_dir("missing");
+        assert!(parse(&dir.join("config"), None, "me").is_empty());
+    }
+
+    #[test]
+    fn a_host_named_nowhere_still_gets_the_wildcard_blocks() {
+        let dir = config_dir("any_host");
+        let path = dir.join("config");
+        std::fs::write(&path, "Host *.lab\n    User tech\n\nHost *\n    Port 2200\n").unwrap();
+        let scope = resolve(&directives(&path, None), "scope.lab", None, "me").ssh;
+        let address = (scope.host.as_str(), scope.port, scope.user.as_str());
+        assert_eq!(address, ("scope.lab", 2200, "tech"));
+    }
+
+    #[test]
+    fn targets_split_the_way_ssh_reads_them() {
+        assert_eq!(split_target("box"), (None, "box", None));
+        assert_eq!(split_target("root@box:2222"), (Some("root"), "box", Some(2222)));
+        assert_eq!(split_target("me@[fe80::1]:2222"), (Some("me"), "fe80::1", Some(2222)));
+        assert_eq!(split_target("fe80::1"), (None, "fe80::1", None));
+    }
+}
@@ -41,6 +41,9 @@ pub struct TermBuffer {
     max_bytes: usize,
     /// Absolute offset of each logical line's first byte. Always non-empty.
     lines: Vec<u64>,
+    /// Absolute offset of each datagram's first byte, for a transport that delivers its data
+    /// in packets. Empty for a byte stream, which has no boundaries to keep.
+    packets: Vec<u64>,
     /// Bumped on every mutation so views can invalidate caches.
     revision: u64,
     /// Length of the whole stream, including bytes already trimmed away.
@@ -67,6 +70,7 @@ impl TermBuffer {
             discarded: 0,
             max_bytes: max_bytes.clamp(MIN_MAX_BYTES, MAX_MAX_BYTES),
             lines: vec![0],
+            packets: Vec::new(),
             revision: 0,
             stream_len: 0,
             device_bytes: 0,
@@ -78,6 +82,16 @@ impl TermBuffer {
         self.device_bytes
     }
 
+    /// Absolute offset just past the newest byte, where [`Self::slice_from`] would next start.
+    pub fn end(&self) -> u64 {
+        self.stream_len
+    }
+
+    /// Changes whenever the contents do, for views that cache what they derive from them.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
     pub fn retained_bytes(&self) -> usize {
         self.bytes.len()
     }
@@ -112,12 +126,26 @@ impl TermBuffer {
         self.push(data);
     }
 
+    /// Append one datagram received from the device, remembering where it starts.
+    pub fn append_packet(&mut self, data: &[u8]) {
+        if !data.is_empty() {
+            self.packets.push(self.stream_len);
+        }
+        self.append(data);
+    }
+
     /// Append bytes the application generated itself, such as a reconnect divider.
     ///
     /// These flow into every view and survive a display-mode replay, because they live in the
     /// ring like anything else. What they do *not* do is reach the log file — the logger only
     /// sees what the transport returned — or count towards the received-bytes readout.
+    ///
+    /// In a buffer of datagrams a marker starts a packet of its own, so it is not mistaken for
+    /// the tail of whatever arrived last.
     pub fn append_local(&mut self, data: &[u8]) {
+        if !self.packets.is_empty() && !data.is_empty() {
+            self.packets.push(self.stream_len);
+        }
         self.push(data);
     }
 
@@ -146,6 +174,7 @@ impl TermBuffer {
         self.discarded += self.bytes.len() as u64;
         self.bytes.clear();
         self.lines = vec![self.discarded];
+        self.packets.clear();
         self.revision += 1;
     }
 
@@ -182,6 +211,16 @@ impl TermBuffer {
         if self.lines.is_empty() {
             self.lines.push(self.discarded);
         }
+
+        // Likewise for packets, except that a byte stream has none to keep.
+        let keep_from = self
+            .packets
+            .partition_point(|start| *start <= self.discarded)
+            .saturating_sub(1);
+        self.packets.drain(..keep_from);
+        if let Some(first) = self.packets.first_mut() {
+            *first = (*first).max(self.discarded);
+        }
     }
 
     /// Bytes of logical line `index`, without its trailing newline.
@@ -197,6 +236,12 @@ impl TermBuffer {
         Some(slice.strip_suffix(b"\r").unwrap_or(slice))
     }
 
+    /// Where each retained datagram starts, as an index into [`Self::bytes`]. Empty unless the
+    /// data arrived through [`Self::append_packet`].
+    pub fn packet_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        self.packets.iter().map(|start| self.rel(*start))
+    }
+
     /// All retained bytes, for the byte-oriented display modes.
     pub fn bytes(&self) -> &[u8] {
         &self.bytes
@@ -419,4 +464,35 @@ mod tests {
         assert_eq!(seen, buf.bytes());
         assert_eq!(fed, buf.stream_len);
     }
+
+    #[test]
+    fn packets_remember_where_each_datagram_starts() {
+        let mut buf = TermBuffer::new(10_000);
+        buf.append(b"stream");
+        assert_eq!(buf.packet_starts().count(), 0, "a byte stream has no boundaries");
+
+        let mut buf = TermBuffer::new(10_000);
+        buf.append_packet(b"abc");
+        buf.append_packet(b"");
+        buf.append_packet(b"de");
+        buf.append_local(b"--");
+        assert_eq!(buf.packet_starts().collect::<Vec<_>>(), [0, 3, 5]);
+        assert_eq!(buf.total_received(), 5);
+
+        buf.clear();
+        assert_eq!(buf.packet_starts().count(), 0);
+    }
+
+    #[test]
+    fn packet_starts_survive_trimming() {
+        let mut buf = TermBuffer::new(MIN_MAX_BYTES);
+        for i in 0..1000u32 {
+            buf.append_packet(&i.to_be_bytes());
+        }
+        let starts: Vec<_> = buf.packet_starts().collect();
+        assert!(buf.retained_bytes() <= MIN_MAX_BYTES + MIN_MAX_BYTES / 4);
+        assert!(starts.windows(2).all(|w| w[1] - w[0] == 4), "{starts:?}");
+        assert_eq!(*starts.last().unwrap(), buf.retained_bytes() - 4);
+        assert_eq!(&buf.bytes()[buf.retained_bytes() - 4..], 999u32.to_be_bytes());
+    }
 }
@@ -26,6 +26,8 @@ use super::emu::Emulator;
 use super::palette::Palette;
 use super::text;
 use super::TermBuffer;
+use std::ops::Range;
+use std::sync::Arc;
 use crate::settings::DisplayMode;
 
 /// Border drawn while a view holds keyboard focus, so it is obvious that typing is being
@@ -583,7 +585,13 @@ pub fn buffer_view(
     let inner = frame.show(ui, |ui| {
         ui.style_mut().visuals.override_text_color = Some(palette.foreground);
         ui.spacing_mut().item_spacing.y = 0.0;
-        let available_width = ui.available_width();
+        let byte_rows = match mode {
+            DisplayMode::Ascii => None,
+            _ => {
+                let per_row = bytes_per_row(mode, ui.available_width(), char_width);
+                Some(byte_rows(ui, id, buffer, per_row))
+            }
+        };
 
         egui::ScrollArea::both()
             .auto_shrink([false, false])
@@ -594,19 +602,14 @@ pub fn buffer_view(
             .show_rows(
                 ui,
                 row_height,
-                row_count(buffer, mode, available_width, char_width),
+                row_count(buffer, byte_rows.as_deref()),
                 |ui, rows| {
                     for row in rows {
-                        let job = match mode {
-                            DisplayMode::Ascii => text_row(buffer, row, &font, &palette),
-                            _ => byte_row(
-                                buffer,
-                                row,
-                                mode,
-                                &font,
-                                bytes_per_row(mode, available_width, char_width),
-                                &palette,
-                            ),
+                        let job = match &byte_rows {
+                            None => text_row(buffer, row, &font, &palette),
+                            Some(byte_rows) => {
+                                byte_row(buffer, byte_rows.range(row), mode, &font, &palette)
+                            }
                         };
                         ui.add(
                             Label::new(job)
@@ -628,15 +631,91 @@ pub fn buffer_view(
     }
 }
 
-/// How many rows the byte view has in this mode.
-fn row_count(buffer: &TermBuffer, mode: DisplayMode, width: f32, char_width: f32) -> usize {
-    match mode {
-        DisplayMode::Ascii => buffer.line_count(),
-        _ => {
-            let per_row = bytes_per_row(mode, width, char_width);
-            buffer.bytes().len().div_ceil(per_row).max(1)
+/// How many rows the byte view has: one per line in ASCII, otherwise as `byte_rows` lays
+/// them out.
+fn row_count(buffer: &TermBuffer, byte_rows: Option<&ByteRows>) -> usize {
+    match byte_rows {
+        None => buffer.line_count(),
+        Some(byte_rows) => byte_rows.count(),
+    }
+}
+
+/// The rows of a numeric view of `buffer`, kept in egui's memory under the view's `id` and
+/// laid out again only when the buffer changes or a row's width does.
+fn byte_rows(ui: &Ui, id: Id, buffer: &TermBuffer, per_row: usize) -> Arc<ByteRows> {
+    type Cached = ((u64, usize), Arc<ByteRows>);
+    let key = id.with("byte rows");
+    let stamp = (buffer.revision(), per_row);
+    if let Some((cached, rows)) = ui.data(|d| d.get_temp::<Cached>(key)) {
+        if cached == stamp {
+            return rows;
+        }
+    }
+    let rows = Arc::new(ByteRows::new(buffer, per_row));
+    ui.data_mut(|d| d.insert_temp(key, (stamp, Arc::clone(&rows))));
+    rows
+}
+
+/// Where the rows of a numeric view fall in the byte ring.
+///
+/// A byte stream wraps every `per_row` bytes, so any row is found by arithmetic. Datagrams
+/// also start a row each, so packet boundaries stay visible; a row's place then depends on
+/// every packet before it, so the rows are listed up front and cached by [`byte_rows`].
+enum ByteRows {
+    Wrapped { len: usize, per_row: usize },
+    Listed(Vec<Range<usize>>),
+}
+
+impl ByteRows {
+    fn new(buffer: &TermBuffer, per_row: usize) -> Self {
+        if buffer.packet_starts().next().is_none() {
+            return Self::Wrapped {
+                len: buffer.bytes().len(),
+                per_row,
+            };
+        }
+        let rows = segments(buffer)
+            .into_iter()
+            .flat_map(|segment| {
+                segment
+                    .clone()
+                    .step_by(per_row)
+                    .map(move |start| start..(start + per_row).min(segment.end))
+            })
+            .collect();
+        Self::Listed(rows)
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Self::Wrapped { len, per_row } => len.div_ceil(*per_row).max(1),
+            Self::Listed(rows) => rows.len().max(1),
         }
     }
+
+    /// The bytes of `row`, or an empty range past the end.
+    fn range(&self, row: usize) -> Range<usize> {
+        match self {
+            Self::Wrapped { len, per_row } => {
+                let start = row.saturating_mul(*per_row).min(*len);
+                start..start.saturating_add(*per_row).min(*len)
+            }
+            Self::Listed(rows) => rows.get(row).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// The retained bytes split at each packet boundary; the whole ring in one piece for a byte
+/// stream. Empty pieces are left out.
+fn segments(buffer: &TermBuffer) -> Vec<Range<usize>> {
+    let len = buffer.bytes().len();
+    let mut bounds: Vec<usize> = std::iter::once(0).chain(buffer.packet_starts()).collect();
+    bounds.push(len);
+    bounds
+        .windows(2)
+        .filter(|w| w[0] < w[1])
+        .map(|w| w[0]..w[1])
+        .collect()
 }
 
 /// Bytes shown per row in the numeric modes, chosen to fill the available width.
@@ -679,22 +758,21 @@ fn text_row(buffer: &TermBuffer, row: usize, font: &FontId, palette: &Palette) -
 
 fn byte_row(
     buffer: &TermBuffer,
-    row: usize,
+    range: Range<usize>,
     mode: DisplayMode,
     font: &FontId,
-    per_row: usize,
     palette: &Palette,
 ) -> LayoutJob {
     let mut job = new_job();
-    let bytes = buffer.bytes();
-    let start = row * per_row;
-    if start >= bytes.len() {
+    let Some(bytes) = buffer.bytes().get(range) else {
+        return job;
+    };
+    if bytes.is_empty() {
         return job;
     }
-    let end = start.saturating_add(per_row).min(bytes.len());
 
-    let mut out = String::with_capacity(per_row * 5);
-    for byte in &bytes[start..end] {
+    let mut out = String::with_capacity(bytes.len() * 5);
+    for byte in bytes {
         match mode {
             DisplayMode::Decimal => out.push_str(&format!("{byte:<3} ")),
             _ => out.push_str(&format!("{byte:#04x} ")),
@@ -704,7 +782,8 @@ fn byte_row(
     job
 }
 
-/// The whole byte ring as plain text, for the clipboard.
+/// The whole byte ring as plain text, for the clipboard. In the numeric modes each datagram
+/// goes on a line of its own, as it does on screen.
 pub fn plain_text(buffer: &TermBuffer, mode: DisplayMode) -> String {
     match mode {
         DisplayMode::Ansi | DisplayMode::Ascii => {
@@ -717,18 +796,20 @@ pub fn plain_text(buffer: &TermBuffer, mode: DisplayMode) -> String {
             }
             out
         }
-        DisplayMode::Decimal => buffer
-            .bytes()
-            .iter()
-            .map(|b| b.to_string())
+        DisplayMode::Decimal | DisplayMode::Hex => segments(buffer)
+            .into_iter()
+            .map(|segment| {
+                buffer.bytes()[segment]
+                    .iter()
+                    .map(|b| match mode {
+                        DisplayMode::Decimal => b.to_string(),
+                        _ => format!("{b:#04x}"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
             .collect::<Vec<_>>()
-            .join(" "),
-        DisplayMode::Hex => buffer
-            .bytes()
-            .iter()
-            .map(|b| format!("{b:#04x}"))
-            .collect::<Vec<_>>()
-            .join(" "),
+            .join("\n"),
     }
 }
 
@@ -737,6 +818,15 @@ mod tests {
     use super::*;
     use crate::term::emu::TermSize;
 
+    /// How many rows the byte view has in `mode` at this width.
+    fn rows_in(buffer: &TermBuffer, mode: DisplayMode, width: f32, char_width: f32) -> usize {
+        let byte_rows = match mode {
+            DisplayMode::Ascii => None,
+            _ => Some(ByteRows::new(buffer, bytes_per_row(mode, width, char_width))),
+        };
+        row_count(buffer, byte_rows.as_ref())
+    }
+
     /// Drive one headless frame containing only a grid view, and report whether it came out
     /// of the frame holding keyboard focus.
     fn grid_frame(
@@ -891,21 +981,21 @@ mod tests {
     fn ascii_row_count_is_the_line_count() {
         let mut buf = TermBuffer::new(10_000);
         buf.append(b"a\nb\nc");
-        assert_eq!(row_count(&buf, DisplayMode::Ascii, 800.0, 8.0), 3);
+        assert_eq!(rows_in(&buf, DisplayMode::Ascii, 800.0, 8.0), 3);
     }
 
     #[test]
     fn byte_row_count_covers_every_byte() {
         let mut buf = TermBuffer::new(10_000);
         buf.append(&[0u8; 45]);
-        assert_eq!(row_count(&buf, DisplayMode::Hex, 800.0, 8.0), 3);
+        assert_eq!(rows_in(&buf, DisplayMode::Hex, 800.0, 8.0), 3);
     }
 
     #[test]
     fn empty_buffer_still_has_one_row_in_every_mode() {
         let buf = TermBuffer::new(10_000);
         for mode in DisplayMode::ALL {
-            assert!(row_count(&buf, *mode, 800.0, 8.0) >= 1, "{mode:?}");
+            assert!(rows_in(&buf, *mode, 800.0, 8.0) >= 1, "{mode:?}");
         }
     }
 
@@ -982,4 +1072,54 @@ mod tests {
         assert_eq!(point.column, Column(0));
         assert_eq!(point.line, Line(0));
     }
+
+    #[test]
+    fn each_datagram_starts_a_new_byte_row() {
+        let mut buf = TermBuffer::new(10_000);
+        // 20 hex bytes fit a row at this width.
+        buf.append_packet(&[1; 3]);
+        buf.append_packet(&[2; 25]);
+        buf.append_packet(&[3; 2]);
+        assert_eq!(rows_in(&buf, DisplayMode::Hex, 800.0, 8.0), 4);
+
+        let rows = ByteRows::new(&buf, bytes_per_row(DisplayMode::Hex, 800.0, 8.0));
+        assert_eq!(rows.range(0), 0..3);
+        assert_eq!(rows.range(1), 3..23);
+        assert_eq!(rows.range(2), 23..28);
+        assert_eq!(rows.range(3), 28..30);
+        assert!(rows.range(4).is_empty());
+    }
+
+    #[test]
+    fn byte_rows_are_laid_out_again_only_when_something_changed() {
+        let ctx = egui::Context::default();
+        let id = Id::new("byte-rows-cache");
+        let mut buf = TermBuffer::new(10_000);
+        buf.append_packet(&[1; 3]);
+        let rows = |buf: &TermBuffer, per_row| {
+            let mut out = None;
+            let _ = ctx.run_ui(egui::RawInput::default(), |ui| {
+                out = Some(byte_rows(ui, id, buf, per_row));
+            });
+            out.unwrap()
+        };
+
+        let first = rows(&buf, 20);
+        assert!(Arc::ptr_eq(&first, &rows(&buf, 20)), "unchanged, so reused");
+        let narrower = rows(&buf, 10);
+        assert!(!Arc::ptr_eq(&first, &narrower));
+        buf.append_packet(&[2; 2]);
+        let grown = rows(&buf, 10);
+        assert!(!Arc::ptr_eq(&narrower, &grown));
+        assert_eq!(grown.count(), 2);
+    }
+
+    #[test]
+    fn plain_text_puts_each_datagram_on_its_own_line() {
+        let mut buf = TermBuffer::new(10_000);
+        buf.append_packet(&[0x01, 0x02]);
+        buf.append_packet(&[0xff]);
+        assert_eq!(plain_text(&buf, DisplayMode::Hex), "0x01 0x02\n0xff");
+        assert_eq!(plain_text(&buf, DisplayMode::Decimal), "1 2\n255");
+    }
 }
@@ -3,14 +3,22 @@
 //! Ported from `PortMenu.svelte`. Errors are shown inline in the tab instead of via
 //! `alert()`, which blocked the whole window and lost the message once dismissed.
 
+use std::path::PathBuf;
+
 use eframe::egui::{self, Ui};
 use tokio::runtime::Handle;
 
 use crate::discovery::PortInfo;
-use crate::session::{ConnectionState, Session};
+use crate::app::TabId;
+use crate::files::{Direction, FileBrowser};
+use crate::hostkeys::HostKeys;
+use crate::keychain;
+use crate::profiles::{Draft, ProfileView, Profiles};
+use crate::recents;
+use crate::session::{ssh, ConnectionState, Session};
 use crate::settings::{
-    self, baud_label, ConnectionKind, DataBits, DisplayMode, FlowControl, Parity, SendMode, SshAuth,
-    StopBits, BAUD_RATES,
+    self, baud_label, ConnectionKind, DataBits, DisplayMode, FlowControl, ForwardDirection, Parity,
+    SendMode, SshAuth, StopBits, BAUD_RATES,
 };
 use crate::term::{render, MAX_MAX_BYTES, MIN_MAX_BYTES};
 
@@ -31,6 +39,11 @@ pub fn controls(
         host_key_prompt(ui, session, rt);
         return;
     }
+    // Likewise a server question mid-handshake, which holds the connection open until answered.
+    if session.pending_challenge.is_some() {
+        challenge_prompt(ui, session);
+        return;
+    }
 
     // ---- connection parameters ----
     //
@@ -55,10 +68,13 @@ pub fn controls(
             ConnectionKind::ALL,
             ConnectionKind::label,
         );
-        // Switching to SSH switches the view too: a remote shell emits escape sequences
-        // constantly, and the ASCII view would render them as `^[` noise.
+        // Switching to SSH, Telnet or a local shell switches the view too: a shell emits escape
+        // sequences constantly, and the ASCII view would render them as `^[` noise.
         if session.settings.kind != previous_kind
-            && session.settings.kind == ConnectionKind::Ssh
+            && matches!(
+                session.settings.kind,
+                ConnectionKind::Ssh | ConnectionKind::Telnet | ConnectionKind::Local
+            )
             && session.display_mode != DisplayMode::Ansi
         {
             session.display_mode = DisplayMode::Ansi;
@@ -68,10 +84,27 @@ pub fn controls(
         // shown rather than half the row being greyed out.
         match session.settings.kind {
             ConnectionKind::Serial => serial_fields(ui, session, ports, salt),
-            ConnectionKind::Ssh => ssh_fields(ui, session, salt),
+            ConnectionKind::Ssh => ssh_fields(ui, session, rt, salt),
+            ConnectionKind::Tcp => tcp_fields(ui, session, salt),
+            ConnectionKind::Telnet => telnet_fields(ui, session, salt),
+            ConnectionKind::Rfc2217 => rfc2217_fields(ui, session, salt),
+            ConnectionKind::Local => local_fields(ui, session, salt),
+            ConnectionKind::Pipe => pipe_fields(ui, session, salt),
+            ConnectionKind::Udp => udp_fields(ui, session, salt),
+            ConnectionKind::UnixSocket => unix_socket_fields(ui, session, salt),
         }
     });
 
+    // A remote port's line parameters can change while it is open, so unlike a local port's
+    // they get a row of their own that stays enabled.
+    if session.settings.kind == ConnectionKind::Rfc2217 {
+        ui.horizontal_wrapped(|ui| {
+            if line_fields(ui, &mut session.settings.serial, salt) {
+                session.apply_line_settings();
+            }
+        });
+    }
+
     // ---- connect / disconnect ----
     //
     // A separate row from the parameters above, which are disabled while a connection is up
@@ -136,8 +169,8 @@ pub fn controls(
             weak_label(ui, format!("· {}x{}", size.columns, size.screen_lines))
                 .on_hover_text(
                     "Terminal size in columns and rows, derived from the pane size and font. \
-                     Resizing the pane resizes the terminal, and SSH sessions tell the remote \
-                     end so full-screen programs reflow.",
+                     Resizing the pane resizes the terminal, and SSH and Telnet sessions tell \
+                     the remote end so full-screen programs reflow.",
                 );
         }
         if session.settings.kind == ConnectionKind::Ssh {
@@ -273,6 +306,14 @@ pub fn controls(
         ui.horizontal_wrapped(|ui| {
             ui.colored_label(ui.visuals().error_fg_color, "!");
             ui.colored_label(ui.visuals().error_fg_color, error);
+            if session.refused_host_key.is_some()
+                && ui
+                    .small_button("Host keys\u{2026}")
+                    .on_hover_text("See what known_hosts records for this host, and fix it")
+                    .clicked()
+            {
+                session.show_host_keys = true;
+            }
             if ui.small_button("Dismiss").clicked() {
                 session.last_error = None;
             }
@@ -325,6 +366,15 @@ fn connect_buttons(ui: &mut Ui, session: &mut Session, rt: &Handle) {
         session.disconnect();
     }
 
+    if session.settings.kind == ConnectionKind::Ssh
+        && ui
+            .add_enabled(session.ssh_connection().is_some(), egui::Button::new("Files"))
+            .on_hover_text("Browse, upload and download over SFTP on this connection")
+            .clicked()
+    {
+        session.show_files = true;
+    }
+
     // Automatic retry is opt-in: a reconnect can be a visible event on the remote host, and
     // silently redialling is not always wanted.
     ui.checkbox(&mut session.auto_reconnect, "Auto")
@@ -338,7 +388,7 @@ fn connect_buttons(ui: &mut Ui, session: &mut Session, rt: &Handle) {
         .on_hover_text(
             "Connect this tab when UniTerm starts. Skipped if the saved device is not attached, \
              if the port now holds different hardware, or if the connection needs a password \
-             (passwords are not saved).",
+             that is not kept in the keychain.",
         );
 
     // Show the countdown so a pending retry is not a mystery.
@@ -384,6 +434,13 @@ fn serial_fields(ui: &mut Ui, session: &mut Session, ports: &[PortInfo], salt: u
             }
         });
 
+    line_fields(ui, serial, salt);
+}
+
+/// Baud rate and framing, shared by local and remote serial ports. Returns whether anything
+/// changed.
+fn line_fields(ui: &mut Ui, serial: &mut settings::SerialSettings, salt: u64) -> bool {
+    let before = serial.clone();
     combo(ui, (salt, "baud"), 110.0, &baud_label(serial.baud_rate), |ui| {
         for baud in BAUD_RATES {
             ui.selectable_value(&mut serial.baud_rate, *baud, baud_label(*baud));
@@ -393,10 +450,136 @@ fn serial_fields(ui: &mut Ui, session: &mut Session, ports: &[PortInfo], salt: u
     enum_combo(ui, (salt, "data"), 130.0, &mut serial.data_bits, DataBits::ALL, DataBits::label);
     enum_combo(ui, (salt, "parity"), 110.0, &mut serial.parity, Parity::ALL, Parity::label);
     enum_combo(ui, (salt, "stop"), 120.0, &mut serial.stop_bits, StopBits::ALL, StopBits::label);
+    *serial != before
+}
+
+/// Raw TCP host and port.
+fn tcp_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    let tcp = &mut session.settings.tcp;
+    endpoint_fields(ui, &mut tcp.host, &mut tcp.port, (salt, "tcp_host"), "hostname or IP");
+}
+
+/// Telnet host and port. The terminal type is not shown: it is only ever the one the
+/// emulator implements.
+fn telnet_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    let telnet = &mut session.settings.telnet;
+    let salt = (salt, "telnet_host");
+    endpoint_fields(ui, &mut telnet.host, &mut telnet.port, salt, "hostname or IP");
+}
+
+/// Host and port of something reached over the network, with `hint` in the empty host field.
+fn endpoint_fields(
+    ui: &mut Ui,
+    host: &mut String,
+    port: &mut u16,
+    salt: impl egui::AsIdSalt,
+    hint: &str,
+) {
+    ui.label("Host");
+    ui.add(
+        egui::TextEdit::singleline(host)
+            .desired_width(160.0)
+            .hint_text(hint)
+            .id_salt(salt),
+    );
+
+    ui.label("Port");
+    ui.add(egui::DragValue::new(port).range(1..=65535).speed(1.0));
+}
+
+/// The device server for a remote serial port. Its line parameters are drawn separately.
+fn rfc2217_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    let server = &mut session.settings.rfc2217;
+    let salt = (salt, "rfc2217_host");
+    endpoint_fields(ui, &mut server.host, &mut server.port, salt, "device server");
+}
+
+/// What a local tab runs.
+fn local_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    ui.label("Command");
+    ui.add(
+        egui::TextEdit::singleline(&mut session.settings.local.command)
+            .desired_width(240.0)
+            .hint_text("$SHELL")
+            .id_salt((salt, "local_command")),
+    )
+    .on_hover_text("Run by /bin/sh on a pseudo-terminal. Leave empty for your shell ($SHELL).");
+}
+
+/// What a piped tab runs.
+fn pipe_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    ui.label("Command");
+    ui.add(
+        egui::TextEdit::singleline(&mut session.settings.pipe.command)
+            .desired_width(240.0)
+            .hint_text("socat - TCP:host:port")
+            .id_salt((salt, "pipe_command")),
+    )
+    .on_hover_text(
+        "Run by the system shell. Its stdin and stdout are the link, with no terminal in \
+         between; stderr is shown too.",
+    );
+}
+
+/// The local UDP port, and the peer if there is one.
+fn udp_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    ui.label("Listen on");
+    ui.add(
+        egui::DragValue::new(&mut session.settings.udp.bind_port)
+            .range(0..=65535)
+            .speed(1.0),
+    )
+    .on_hover_text("Local port. 0 lets the system pick one, which is fine for sending only.");
+
+    ui.label("Peer");
+    ui.add(
+        egui::TextEdit::singleline(&mut session.settings.udp.host)
+            .desired_width(160.0)
+            .hint_text("anyone")
+            .id_salt((salt, "udp_host")),
+    )
+    .on_hover_text(
+        "Only datagrams from this host are shown, and sends go to it. Leave empty to hear \
+         from anyone and reply to whoever sent last.",
+    );
+
+    ui.label("Port");
+    ui.add(
+        egui::DragValue::new(&mut session.settings.udp.port)
+            .range(0..=65535)
+            .speed(1.0),
+    );
+}
+
+/// The socket a Unix socket tab connects to: typed, or picked from the filesystem.
+fn unix_socket_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    let path = &mut session.settings.unix_socket.path;
+    ui.label("Socket");
+    let mut text = path.display().to_string();
+    if ui
+        .add(
+            egui::TextEdit::singleline(&mut text)
+                .desired_width(240.0)
+                .hint_text("/tmp/vm.sock")
+                .id_salt((salt, "unix_socket_path")),
+        )
+        .changed()
+    {
+        *path = text.into();
+    }
+    if ui.button("Browse…").clicked() {
+        let mut dialog = rfd::FileDialog::new();
+        if let Some(dir) = path.parent().filter(|dir| dir.is_dir()) {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(picked) = dialog.pick_file() {
+            *path = picked;
+        }
+    }
 }
 
 /// SSH connection parameters and credentials.
-fn ssh_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+fn ssh_fields(ui: &mut Ui, session: &mut Session, rt: &Handle, salt: u64) {
     ui.label("Host");
     ui.add(
         egui::TextEdit::singleline(&mut session.settings.ssh.host)
@@ -428,17 +611,24 @@ fn ssh_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
         SshAuth::label,
     );
 
-    // Secrets are typed here and held in memory only; they are never written to disk.
+    // Secrets are typed here and held in memory only; they are never written to disk. The
+    // keychain, if the tab opts in, is the one place they are kept.
+    let keychain = keychain::enabled(&session.settings.ssh);
     match session.settings.ssh.auth {
         SshAuth::Password => {
             ui.add(
                 egui::TextEdit::singleline(&mut session.credentials.password)
                     .desired_width(130.0)
                     .password(true)
-                    .hint_text("password")
+                    .hint_text(if keychain { "from keychain" } else { "password" })
                     .id_salt((salt, "password")),
             )
-            .on_hover_text("Held in memory for this session only. Never written to disk.");
+            .on_hover_text(if keychain {
+                "Leave blank to use the one in the keychain. One typed here is saved there once \
+                 it has worked."
+            } else {
+                "Held in memory for this session only. Never written to disk."
+            });
         }
         SshAuth::PublicKey => {
             let label = session
@@ -470,18 +660,167 @@ fn ssh_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
                     session.settings.ssh.key_path = Some(path);
                 }
             }
+            let certificate = session.settings.ssh.key_path.as_deref().map(ssh::certificate_path);
+            if let Some(certificate) = certificate.filter(|path| path.is_file()) {
+                ui.weak("+ certificate").on_hover_text(format!(
+                    "{} is offered first; the key alone if the server refuses it.",
+                    certificate.display()
+                ));
+            }
             ui.add(
                 egui::TextEdit::singleline(&mut session.credentials.passphrase)
                     .desired_width(120.0)
                     .password(true)
-                    .hint_text("passphrase")
+                    .hint_text(if keychain { "from keychain" } else { "passphrase" })
                     .id_salt((salt, "passphrase")),
             )
-            .on_hover_text("Leave blank for an unencrypted key. Never written to disk.");
+            .on_hover_text(if keychain {
+                "Leave blank to use the one in the keychain, or for an unencrypted key. One \
+                 typed here is saved there once it has worked."
+            } else {
+                "Leave blank for an unencrypted key. Never written to disk."
+            });
+        }
+        SshAuth::Agent => {
+            // Nothing to type; just warn early when there is obviously no agent to ask.
+            if session.settings.ssh.agent_socket.is_none()
+                && std::env::var_os("SSH_AUTH_SOCK").is_none()
+            {
+                ui.weak("SSH_AUTH_SOCK is not set")
+                    .on_hover_text("Start ssh-agent and add a key with ssh-add, then restart.");
+            }
+        }
+    }
+
+    if let Some(kind) = keychain::Kind::wanted(session.settings.ssh.auth) {
+        if keychain::AVAILABLE {
+            keychain_fields(ui, session, kind, rt);
+        }
+    }
+
+    ui.label("Run");
+    ui.add(
+        egui::TextEdit::singleline(&mut session.settings.ssh.command)
+            .desired_width(140.0)
+            .hint_text("login shell")
+            .id_salt((salt, "command")),
+    )
+    .on_hover_text("Run this command instead of a shell; the tab ends with its exit status");
+    if !session.settings.ssh.command.trim().is_empty() {
+        ui.checkbox(&mut session.settings.ssh.command_pty, "PTY")
+            .on_hover_text("Give the command a terminal, as ssh -t does, for programs like top");
+    }
+
+    jump_host_fields(ui, session, salt);
+    forward_fields(ui, session, salt);
+}
+
+/// Whether the tab's secret lives in the keychain, and a way to take it out again.
+fn keychain_fields(ui: &mut Ui, session: &mut Session, kind: keychain::Kind, rt: &Handle) {
+    let identity = session.settings.ssh.identity();
+    ui.checkbox(&mut session.settings.ssh.keychain, "Keychain")
+        .on_hover_text(format!(
+            "Keep the {} for {identity} in the login keychain, so a restart or \"On start\" \
+             does not need it typed. Only this choice is saved with the tab.",
+            kind.label()
+        ));
+    if session.settings.ssh.keychain {
+        if ui
+            .small_button("Forget")
+            .on_hover_text(format!("Delete what the keychain holds for {identity}"))
+            .clicked()
+        {
+            session.forget_keychain(rt, ui.ctx());
+        }
+        if let Some(notice) = &session.keychain_notice {
+            weak_label(ui, notice);
         }
     }
 }
 
+/// The bastions in front of an SSH target, first hop first. Each row is one hop; the tab's own
+/// login is used at every one of them.
+fn jump_host_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    let mut remove = None;
+    for (index, jump) in session.settings.ssh.jump_hosts.iter_mut().enumerate() {
+        ui.label("via");
+        ui.add(
+            egui::TextEdit::singleline(&mut jump.host)
+                .desired_width(130.0)
+                .hint_text("jump host")
+                .id_salt((salt, "jump host", index)),
+        );
+        ui.add(egui::DragValue::new(&mut jump.port).range(1..=65535).speed(1.0));
+        ui.add(
+            egui::TextEdit::singleline(&mut jump.user)
+                .desired_width(90.0)
+                .hint_text("same user")
+                .id_salt((salt, "jump user", index)),
+        );
+        if ui.small_button("\u{00d7}").on_hover_text("Remove this jump host").clicked() {
+            remove = Some(index);
+        }
+    }
+    if let Some(index) = remove {
+        session.settings.ssh.jump_hosts.remove(index);
+    }
+    if ui
+        .small_button("+ jump host")
+        .on_hover_text("Reach the host through a bastion, as ssh -J does")
+        .clicked()
+    {
+        session.settings.ssh.jump_hosts.push(Default::default());
+    }
+}
+
+/// `-L` and `-R` port forwards, one row each, written the way `ssh` takes them:
+/// listen port, then destination host and port.
+fn forward_fields(ui: &mut Ui, session: &mut Session, salt: u64) {
+    let mut remove = None;
+    for (index, forward) in session.settings.ssh.forwards.iter_mut().enumerate() {
+        enum_combo(
+            ui,
+            (salt, "forward direction", index),
+            40.0,
+            &mut forward.direction,
+            ForwardDirection::ALL,
+            ForwardDirection::label,
+        );
+        // 0 is left alone as "not filled in yet", but never offered.
+        ui.add(
+            egui::DragValue::new(&mut forward.listen_port)
+                .range(1..=65535)
+                .clamp_existing_to_range(false)
+                .speed(1.0),
+        )
+        .on_hover_text(match forward.direction {
+            ForwardDirection::Local => "Port to listen on here",
+            ForwardDirection::Remote => "Port for the server to listen on",
+        });
+        ui.label("to");
+        ui.add(
+            egui::TextEdit::singleline(&mut forward.host)
+                .desired_width(100.0)
+                .hint_text("host")
+                .id_salt((salt, "forward host", index)),
+        );
+        ui.add(egui::DragValue::new(&mut forward.port).range(0..=65535).speed(1.0));
+        if ui.small_button("\u{00d7}").on_hover_text("Remove this forward").clicked() {
+            remove = Some(index);
+        }
+    }
+    if let Some(index) = remove {
+        session.settings.ssh.forwards.remove(index);
+    }
+    if ui
+        .small_button("+ forward")
+        .on_hover_text("Forward a port over this connection, as ssh -L or -R does")
+        .clicked()
+    {
+        session.settings.ssh.forwards.push(Default::default());
+    }
+}
+
 /// Trust-on-first-use prompt for an unrecognised host key.
 ///
 /// Only reached for [`crate::knownhosts::Rejection::Unknown`]. A changed key is reported as an
@@ -499,7 +838,8 @@ fn host_key_prompt(ui: &mut Ui, session: &mut Session, rt: &Handle) {
             fingerprint,
         } => (host, port, algorithm, fingerprint),
         // Not promptable; nothing to draw.
-        crate::knownhosts::Rejection::Changed { .. } => return,
+        crate::knownhosts::Rejection::Changed { .. }
+        | crate::knownhosts::Rejection::Revoked { .. } => return,
     };
 
     egui::Frame::default()
@@ -534,8 +874,627 @@ fn host_key_prompt(ui: &mut Ui, session: &mut Session, rt: &Handle) {
                 if ui.button("Copy fingerprint").clicked() {
                     ui.ctx().copy_text(fingerprint.clone());
                 }
+                ui.checkbox(&mut session.settings.ssh.hash_known_hosts, "Hash host name")
+                    .on_hover_text(
+                        "Record the host as |1|salt|hash, as OpenSSH's HashKnownHosts does, so \
+                         the file does not list where you connect",
+                    );
+            });
+        });
+}
+
+/// The tab's pending keyboard-interactive prompts, with a field for each answer.
+fn challenge_prompt(ui: &mut Ui, session: &mut Session) {
+    let Some(challenge) = session.pending_challenge.clone() else {
+        return;
+    };
+    let host = challenge.host.clone();
+
+    let mut submit = false;
+    egui::Frame::default()
+        .fill(ui.visuals().widgets.active.bg_fill.gamma_multiply(0.35))
+        .stroke(egui::Stroke::new(1.0, ui.visuals().selection.stroke.color))
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let title = challenge.name.trim();
+                if title.is_empty() {
+                    ui.strong(format!("{host} asks:"));
+                } else {
+                    ui.strong(format!("{host} asks: {title}"));
+                }
+            });
+            if !challenge.instructions.trim().is_empty() {
+                ui.add_space(2.0);
+                ui.label(challenge.instructions.trim());
+            }
+            ui.add_space(4.0);
+            for (index, prompt) in challenge.prompts.iter().enumerate() {
+                let Some(answer) = session.challenge_answers.get_mut(index) else {
+                    break;
+                };
+                ui.horizontal(|ui| {
+                    ui.label(prompt.text.trim());
+                    let field = ui.add(
+                        egui::TextEdit::singleline(answer)
+                            .password(!prompt.echo)
+                            .desired_width(200.0),
+                    );
+                    if index == 0 && ui.memory(|m| m.focused().is_none()) {
+                        field.request_focus();
+                    }
+                    if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        submit = true;
+                    }
+                });
+            }
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                if ui.button("Submit").clicked() {
+                    submit = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    session.cancel_challenge();
+                }
+            });
+        });
+    if submit {
+        session.answer_challenge();
+    }
+}
+
+/// The SFTP panel beside an SSH tab: a path bar, the transfers, and the directory listing.
+pub fn file_panel(ui: &mut Ui, files: &mut FileBrowser, rt: &Handle) {
+    let ctx = ui.ctx().clone();
+    ui.add_space(4.0);
+    ui.horizontal(|ui| {
+        if !files.is_connected() {
+            ui.disable();
+        }
+        let has_parent = files.path.as_deref().and_then(crate::session::sftp::parent).is_some();
+        if ui
+            .add_enabled(has_parent, egui::Button::new("Up"))
+            .on_hover_text("The directory above this one")
+            .clicked()
+        {
+            files.up();
+        }
+        let field = ui.add(
+            egui::TextEdit::singleline(&mut files.path_text)
+                .desired_width((ui.available_width() - 120.0).max(120.0))
+                .hint_text("remote path"),
+        );
+        if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            let path = files.path_text.trim().to_owned();
+            if !path.is_empty() {
+                files.open(path);
+            }
+        }
+        if ui.button("\u{21bb}").on_hover_text("List this directory again").clicked() {
+            files.refresh();
+        }
+        if ui
+            .add_enabled(files.path.is_some(), egui::Button::new("Upload\u{2026}"))
+            .on_hover_text("Copy files from this computer into this directory")
+            .clicked()
+        {
+            for local in rfd::FileDialog::new().pick_files().unwrap_or_default() {
+                files.upload(rt, local, &ctx);
+            }
+        }
+    });
+
+    if !files.is_connected() {
+        weak_label(ui, "Not connected. The panel picks up again when the tab reconnects.");
+    } else if files.loading && files.path.is_none() {
+        weak_label(ui, "Starting SFTP\u{2026}");
+    }
+    if let Some(error) = &files.error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+
+    if !files.transfers.is_empty() {
+        ui.separator();
+        let mut cancel = None;
+        for (index, transfer) in files.transfers.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let arrow = match transfer.direction {
+                    Direction::Upload => "up",
+                    Direction::Download => "down",
+                };
+                let name = transfer.remote.rsplit('/').next().unwrap_or(&transfer.remote);
+                let route = format!("{} \u{00b7} {}", transfer.remote, transfer.local.display());
+                ui.label(format!("{arrow} {name}")).on_hover_text(route);
+                match &transfer.outcome {
+                    None => {
+                        let text = match transfer.total {
+                            Some(total) => {
+                                format!("{} / {}", bytes_label(transfer.done), bytes_label(total))
+                            }
+                            None => bytes_label(transfer.done),
+                        };
+                        ui.add(
+                            egui::ProgressBar::new(transfer.fraction().unwrap_or(0.0))
+                                .desired_width(160.0)
+                                .text(text),
+                        );
+                        if ui.small_button("\u{00d7}").on_hover_text("Cancel").clicked() {
+                            cancel = Some(index);
+                        }
+                    }
+                    Some(Ok(())) => {
+                        weak_label(ui, format!("done, {}", bytes_label(transfer.done)));
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                }
             });
+        }
+        if let Some(index) = cancel {
+            files.cancel(index);
+        }
+        if files.transfers.iter().any(|t| t.outcome.is_some())
+            && ui.small_button("Clear finished").clicked()
+        {
+            files.clear_finished();
+        }
+    }
+
+    ui.separator();
+    let now = recents::now_seconds();
+    let mut open = None;
+    let mut download = None;
+    egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+        egui::Grid::new("sftp listing").striped(true).num_columns(4).show(ui, |ui| {
+            for entry in &files.entries {
+                if entry.is_dir {
+                    if ui.link(format!("{}/", entry.name)).clicked() {
+                        open = Some(entry.name.clone());
+                    }
+                    ui.label("");
+                } else {
+                    ui.label(&entry.name);
+                    ui.label(entry.size.map(bytes_label).unwrap_or_default());
+                }
+                let modified = entry.modified.map(|m| recents::relative_time(now, m.into()));
+                weak_label(ui, modified.unwrap_or_default());
+                if !entry.is_dir
+                    && ui
+                        .small_button("Download\u{2026}")
+                        .on_hover_text("Copy this file to this computer")
+                        .clicked()
+                {
+                    download = Some(entry.name.clone());
+                }
+                ui.end_row();
+            }
+        });
+    });
+    if let (Some(name), Some(dir)) = (open, files.path.clone()) {
+        files.open(crate::session::sftp::join(&dir, &name));
+    }
+    if let Some(name) = download {
+        if let Some(local) = rfd::FileDialog::new().set_file_name(&name).save_file() {
+            files.download(rt, &name, local, &ctx);
+        }
+    }
+}
+
+/// The host key manager.
+///
+/// Removing takes a second click, since a line dropped by mistake means the next connection
+/// has to be verified from scratch. "Replace" is offered on the line a refusal pointed at.
+pub fn host_key_panel(ui: &mut Ui, keys: &mut HostKeys) {
+    ui.add_space(4.0);
+    ui.horizontal(|ui| {
+        ui.label("Host");
+        let field = ui.add(
+            egui::TextEdit::singleline(&mut keys.filter)
+                .desired_width(200.0)
+                .hint_text("all hosts"),
+        );
+        if field.changed() {
+            keys.refresh();
+        }
+        if ui.button("\u{21bb}").on_hover_text("Read the file again").clicked() {
+            keys.refresh();
+        }
+        match &keys.path {
+            Some(path) => weak_label(ui, path.display().to_string()),
+            None => weak_label(ui, "No home directory, so no known_hosts to show."),
+        };
+    });
+    if let Some(notice) = &keys.notice {
+        weak_label(ui, notice);
+    }
+    if let Some(error) = &keys.error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+    ui.separator();
+
+    if keys.entries.is_empty() {
+        weak_label(ui, "Nothing recorded.");
+        return;
+    }
+    let mut remove = None;
+    let mut replace = None;
+    egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+        egui::Grid::new("known hosts").striped(true).num_columns(5).show(ui, |ui| {
+            for entry in &keys.entries {
+                let highlighted = keys.highlight == Some(entry.line);
+                let line = entry.line.to_string();
+                if highlighted {
+                    let line = egui::RichText::new(line).strong();
+                    ui.colored_label(ui.visuals().error_fg_color, line)
+                        .on_hover_text("The line the connection was refused over");
+                } else {
+                    weak_label(ui, line);
+                }
+                if entry.is_hashed() {
+                    weak_label(ui, "(hashed)").on_hover_text(&entry.hosts);
+                } else {
+                    ui.label(&entry.hosts);
+                }
+                ui.label(entry.marker.unwrap_or_default());
+                ui.horizontal(|ui| {
+                    ui.label(&entry.algorithm);
+                    ui.code(&entry.fingerprint);
+                });
+                ui.horizontal(|ui| {
+                    if ui.small_button("Copy").on_hover_text("Copy the fingerprint").clicked() {
+                        ui.ctx().copy_text(entry.fingerprint.clone());
+                    }
+                    if let Some(marker) = entry.marker {
+                        weak_label(ui, "edit by hand").on_hover_text(format!(
+                            "A {marker} line applies to every host it names, so it is not \
+                             removed from here"
+                        ));
+                    } else if keys.confirm == Some(entry.line) {
+                        if ui.small_button("Really remove").clicked() {
+                            remove = Some((entry.line, entry.fingerprint.clone()));
+                        }
+                        if ui.small_button("Keep").clicked() {
+                            keys.confirm = None;
+                        }
+                    } else if ui
+                        .small_button("Remove")
+                        .on_hover_text("Forget this key")
+                        .clicked()
+                    {
+                        keys.confirm = Some(entry.line);
+                    }
+                    if keys.replaceable == Some(entry.line)
+                        && keys.origin.is_some()
+                        && ui
+                            .small_button("Replace")
+                            .on_hover_text(
+                                "Forget this key and reconnect, to be shown the host's new one",
+                            )
+                            .clicked()
+                    {
+                        replace = Some((entry.line, entry.fingerprint.clone()));
+                    }
+                });
+                ui.end_row();
+            }
         });
+    });
+    if let Some((line, fingerprint)) = remove {
+        keys.remove(line, &fingerprint);
+    }
+    if let Some((line, fingerprint)) = replace {
+        keys.replace(line, &fingerprint);
+    }
+}
+
+/// What the profile panel asks the app to do. Everything else it does to the library itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProfileAction {
+    Open(u64),
+    /// Add a new profile from this tab.
+    SaveTab(TabId),
+    /// Overwrite a profile's settings with those of a tab.
+    Update { profile: u64, tab: TabId },
+    /// Write the library, and the recents if asked, to a file.
+    Export { path: PathBuf, recents: bool },
+    /// Read a file and check it against what is here.
+    Import(PathBuf),
+    /// Carry out the import under review.
+    ApplyImport,
+}
+
+/// The profile library: search, folders, and open, edit, duplicate and delete.
+///
+/// `tabs` is every open tab with its title and the profile it is tied to, for saving a tab as a
+/// profile and a profile back from its tab.
+pub fn profile_panel(
+    ui: &mut Ui,
+    profiles: &mut Profiles,
+    view: &mut ProfileView,
+    tabs: &[(TabId, String, Option<u64>)],
+) -> Option<ProfileAction> {
+    let mut action = None;
+    ui.add_space(4.0);
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut view.filter)
+                .desired_width(220.0)
+                .hint_text("search name, folder, notes, address"),
+        );
+        ui.menu_button("Save tab as profile", |ui| {
+            for (id, title, _) in tabs {
+                if ui.button(title).clicked() {
+                    action = Some(ProfileAction::SaveTab(*id));
+                    ui.close();
+                }
+            }
+        });
+        ui.menu_button("Export", |ui| {
+            let choices = [("Profiles\u{2026}", false), ("Profiles and recents\u{2026}", true)];
+            for (label, recents) in choices {
+                if ui.button(label).clicked() {
+                    ui.close();
+                    let dialog = rfd::FileDialog::new()
+                        .add_filter("UniTerm export", &["ron"])
+                        .set_file_name("uniterm-profiles.ron");
+                    if let Some(path) = dialog.save_file() {
+                        action = Some(ProfileAction::Export { path, recents });
+                    }
+                }
+            }
+        });
+        if ui
+            .button("Import\u{2026}")
+            .on_hover_text("Add profiles and recents from an exported file")
+            .clicked()
+        {
+            let dialog = rfd::FileDialog::new().add_filter("UniTerm export", &["ron"]);
+            if let Some(path) = dialog.pick_file() {
+                action = Some(ProfileAction::Import(path));
+            }
+        }
+        weak_label(ui, format!("{} profile(s)", profiles.len()));
+    });
+    if let Some(notice) = &view.notice {
+        weak_label(ui, notice);
+    }
+    if let Some(error) = &view.error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+    ui.separator();
+    if view.import.is_some() {
+        return import_review(ui, view).or(action);
+    }
+    if profiles.is_empty() {
+        weak_label(ui, "No profiles yet. Save a tab as one to start the library.");
+        return action;
+    }
+
+    let mut duplicate = None;
+    let mut delete = None;
+    let mut save = None;
+    egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+        for folder in profiles.folders() {
+            let shown: Vec<_> = profiles
+                .entries()
+                .iter()
+                .filter(|p| p.folder == folder && p.matches(&view.filter))
+                .collect();
+            if shown.is_empty() {
+                continue;
+            }
+            let title = if folder.is_empty() { "(no folder)" } else { folder };
+            egui::CollapsingHeader::new(format!("{title} ({})", shown.len()))
+                .id_salt(("profile folder", folder))
+                .default_open(true)
+                .show(ui, |ui| {
+                    for profile in shown {
+                        if let Some(draft) = view.editing.as_mut().filter(|d| d.id == profile.id) {
+                            ui.horizontal(|ui| {
+                                let name = egui::TextEdit::singleline(&mut draft.name);
+                                ui.add(name.hint_text("name"));
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut draft.folder)
+                                        .hint_text("folder, e.g. Lab/Bench 3"),
+                                );
+                            });
+                            ui.add(
+                                egui::TextEdit::multiline(&mut draft.notes)
+                                    .desired_rows(2)
+                                    .hint_text("notes"),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Save").clicked() {
+                                    save = Some(true);
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    save = Some(false);
+                                }
+                            });
+                            continue;
+                        }
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button(&profile.name)
+                                .on_hover_text("Open in a new tab")
+                                .clicked()
+                            {
+                                action = Some(ProfileAction::Open(profile.id));
+                            }
+                            weak_label(ui, profile.settings.description());
+                            if ui.small_button("Edit").clicked() {
+                                view.editing = Some(Draft::of(profile));
+                            }
+                            if ui.small_button("Duplicate").clicked() {
+                                duplicate = Some(profile.id);
+                            }
+                            if view.confirm_delete == Some(profile.id) {
+                                if ui.small_button("Really delete").clicked() {
+                                    delete = Some(profile.id);
+                                }
+                                if ui.small_button("Keep").clicked() {
+                                    view.confirm_delete = None;
+                                }
+                            } else if ui.small_button("Delete").clicked() {
+                                view.confirm_delete = Some(profile.id);
+                            }
+                            let tab = tabs.iter().find(|(_, _, bound)| *bound == Some(profile.id));
+                            if let Some((tab, _, _)) = tab {
+                                if ui
+                                    .small_button("Update from tab")
+                                    .on_hover_text("Save the open tab's current settings here")
+                                    .clicked()
+                                {
+                                    action = Some(ProfileAction::Update {
+                                        profile: profile.id,
+                                        tab: *tab,
+                                    });
+                                }
+                            }
+                        });
+                        if !profile.notes.is_empty() {
+                            ui.indent(("profile notes", profile.id), |ui| {
+                                weak_label(ui, &profile.notes);
+                            });
+                        }
+                    }
+                });
+        }
+    });
+
+    if let Some(id) = duplicate {
+        if let Some(copy) = profiles.duplicate(id) {
+            view.editing = profiles.get(copy).map(Draft::of);
+        }
+    }
+    if let Some(id) = delete {
+        profiles.remove(id);
+        view.confirm_delete = None;
+    }
+    match save {
+        Some(true) => {
+            if let Some(draft) = view.editing.take() {
+                draft.apply(profiles);
+            }
+        }
+        Some(false) => view.editing = None,
+        None => {}
+    }
+    action
+}
+
+/// A file being imported: what it would run or open, each to allow or leave out; its clashes,
+/// each to merge or skip; and the repeats that will be passed over.
+fn import_review(ui: &mut Ui, view: &mut ProfileView) -> Option<ProfileAction> {
+    let Some(import) = &mut view.import else {
+        return None;
+    };
+    let mut action = None;
+    let name = import.source.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    ui.strong(format!(
+        "Importing {} profile(s) and {} recent connection(s) from {name}.",
+        import.profiles.len(),
+        import.recents.len()
+    ));
+    egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+        let risky = import.risky();
+        if risky > 0 {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!(
+                    "{risky} of them would run commands or open connections as you. Tick only \
+                     the ones you trust whoever sent this file with; the rest are left out."
+                ),
+            );
+            ui.horizontal(|ui| {
+                if ui.small_button("Allow all").clicked() {
+                    import.allow_all(true);
+                }
+                if ui.small_button("Allow none").clicked() {
+                    import.allow_all(false);
+                }
+            });
+            for incoming in import.profiles.iter_mut().filter(|p| p.repeat.is_none()) {
+                if !incoming.risks.is_empty() {
+                    let label =
+                        format!("{} \u{00b7} {}", incoming.item.name, incoming.risks.join(", "));
+                    ui.checkbox(&mut incoming.allowed, label)
+                        .on_hover_text(incoming.item.settings.description());
+                }
+            }
+            for incoming in import.recents.iter_mut().filter(|r| r.repeat.is_none()) {
+                if !incoming.risks.is_empty() {
+                    let name = incoming.item.settings.label();
+                    let label = format!("{name} \u{00b7} {}", incoming.risks.join(", "));
+                    ui.checkbox(&mut incoming.allowed, label)
+                        .on_hover_text(incoming.item.settings.description());
+                }
+            }
+            ui.add_space(4.0);
+        }
+
+        let clashes = import.clashes();
+        if clashes > 0 {
+            ui.label(format!(
+                "{clashes} of them are already here. Tick the ones to merge in; the rest are \
+                 skipped."
+            ));
+            ui.horizontal(|ui| {
+                if ui.small_button("Merge all").clicked() {
+                    import.merge_all(true);
+                }
+                if ui.small_button("Skip all").clicked() {
+                    import.merge_all(false);
+                }
+            });
+            for incoming in import.profiles.iter_mut().filter(|p| p.repeat.is_none()) {
+                if let Some(clash) = &incoming.clash {
+                    let label = format!("{} \u{00b7} same as {clash}", incoming.item.name);
+                    ui.checkbox(&mut incoming.merge, label)
+                        .on_hover_text(incoming.item.settings.description());
+                }
+            }
+            for incoming in import.recents.iter_mut().filter(|r| r.repeat.is_none()) {
+                if let Some(clash) = &incoming.clash {
+                    let name = incoming.item.settings.label();
+                    let label = format!("{name} \u{00b7} same as {clash}");
+                    ui.checkbox(&mut incoming.merge, label)
+                        .on_hover_text(incoming.item.settings.description());
+                }
+            }
+            ui.add_space(4.0);
+        }
+
+        let repeats = import.repeats();
+        if repeats > 0 {
+            ui.label(format!(
+                "{repeats} of them repeat a connection listed earlier in the file, and are \
+                 skipped:"
+            ));
+            for incoming in &import.profiles {
+                if let Some(first) = &incoming.repeat {
+                    ui.weak(format!("{} \u{00b7} same as {first}", incoming.item.name));
+                }
+            }
+            for incoming in &import.recents {
+                if let Some(first) = &incoming.repeat {
+                    let name = incoming.item.settings.label();
+                    ui.weak(format!("{name} \u{00b7} same as {first}"));
+                }
+            }
+        }
+    });
+    ui.add_space(4.0);
+    ui.horizontal(|ui| {
+        if ui.button("Import").clicked() {
+            action = Some(ProfileAction::ApplyImport);
+        }
+        if ui.button("Cancel").clicked() {
+            view.import = None;
+        }
+    });
+    action
 }
 
 /// Compact byte count for the status readout.